@@ -0,0 +1,61 @@
+#![deny(warnings)]
+
+//! Reading and writing BMP images.
+//!
+//! This crate is the stable face of the BMP library of the workspace. The
+//! implementation lives in the `two` crate, whose modules may still move; the
+//! names exported here do not.
+//!
+//! * `prelude` has what nearly every user needs,
+//! * `codec` decodes and encodes files,
+//! * `geom` has the points, sizes and rectangles taken by region methods,
+//! * `options` configures decoding and encoding.
+//!
+//! # Example
+//!
+//! ```
+//! use bmp::prelude::*;
+//!
+//! let bmp_data = include_bytes!("../../two/test/rgbw.bmp");
+//! let img = Decoder::new(&bmp_data[..]).flip_vertical(true).decode().unwrap();
+//! assert_eq!(img.get_width(), 2);
+//!
+//! let mut copy = Vec::new();
+//! img.to_writer(&mut copy).unwrap();
+//! assert_eq!(bmp::codec::from_bytes(&copy).unwrap(), img);
+//! ```
+
+extern crate two;
+
+pub use two::px;
+pub use two::{
+    consts, BmpError, BmpErrorKind, BmpResult, BmpVersion, BmpWarning, Image, IndexedImage, Pixel,
+};
+
+/// The types and functions used by almost every program.
+pub mod prelude {
+    pub use crate::codec::{from_bytes, from_reader, open, Decoder};
+    pub use crate::geom::{Point, Rect, Size};
+    pub use crate::{BmpError, BmpErrorKind, BmpResult, Image, IndexedImage, Pixel};
+}
+
+/// Decoding files into images and encoding images into files.
+pub mod codec {
+    pub use two::{
+        decode_into_slice, decode_untrusted, encode_into_buf, from_bytes, from_bytes_with_options,
+        from_reader, from_reader_with_options, open, open_lenient, open_with_options,
+        open_with_report, CancellationToken, Decoder, ImageInfo, Metadata,
+    };
+}
+
+/// Points, sizes and rectangles in pixels.
+pub mod geom {
+    pub use two::geom::{Point, Rect, Size};
+}
+
+/// The switches of decoding and encoding.
+pub mod options {
+    pub use two::pack::Layout;
+    pub use two::policy::{ColorPolicy, OutOfRange, Rounding};
+    pub use two::{AlphaMode, Compression, DecodeOptions, EncoderOptions, Limits, RowOrder};
+}
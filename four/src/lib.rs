@@ -1,26 +1,51 @@
 #![deny(warnings)]
 #![cfg_attr(test, deny(warnings))]
+#![allow(clippy::duplicated_attributes)]
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::cell::{Ref, RefCell, RefMut};
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::rc::{Rc, Weak};
 
-type Link = Rc<RefCell<Node>>;
+type Link<T> = Rc<RefCell<Node<T>>>;
+// Back-links are weak so that neighbouring nodes do not keep each other alive.
+type WeakLink<T> = Weak<RefCell<Node<T>>>;
 
-#[derive(Clone)]
-struct Node {
-    value: i32,
-    prev: Option<Link>,
-    next: Option<Link>,
+struct Node<T> {
+    value: T,
+    prev: Option<WeakLink<T>>,
+    next: Option<Link<T>>,
 }
 
-pub struct List {
+impl<T> Node<T> {
+    fn new(value: T) -> Link<T> {
+        Rc::new(RefCell::new(Node {
+            value,
+            prev: None,
+            next: None,
+        }))
+    }
+
+    /// Takes the value out of a node that is no longer linked from anywhere.
+    fn into_value(link: Link<T>) -> T {
+        match Rc::try_unwrap(link) {
+            Ok(node) => node.into_inner().value,
+            Err(_) => panic!("unlinked node is still referenced"),
+        }
+    }
+}
+
+pub struct List<T> {
     size: usize,
-    head: Option<Link>,
-    tail: Option<Link>,
+    head: Option<Link<T>>,
+    tail: Option<Link<T>>,
 }
 
-impl List {
-    pub fn new() -> List {
+impl<T> List<T> {
+    pub fn new() -> List<T> {
         List {
             size: 0,
             head: None,
@@ -28,34 +53,305 @@ impl List {
         }
     }
 
-    pub fn push_back(&mut self, value: i32) {
-        let n = Node {
-            value,
-            prev: self.tail.clone(),
-            next: None,
-        };
-        let n = Rc::new(RefCell::new(n));
+    pub fn push_back(&mut self, value: T) {
+        self.push_back_node(Node::new(value));
+    }
 
-        match self.tail {
-            Some(ref pre_tail) => {
+    /// Links an unattached node at the back.
+    fn push_back_node(&mut self, n: Link<T>) {
+        match self.tail.take() {
+            Some(pre_tail) => {
                 pre_tail.borrow_mut().next = Some(Rc::clone(&n));
-                n.borrow_mut().prev = Some(Rc::clone(pre_tail));
-                self.tail = Some(Rc::clone(&n));
+                n.borrow_mut().prev = Some(Rc::downgrade(&pre_tail));
+                self.tail = Some(n);
             }
             None => {
                 self.head = Some(Rc::clone(&n));
-                self.tail = Some(Rc::clone(self.head.as_ref().unwrap()));
+                self.tail = Some(n);
             }
         }
         self.size += 1;
     }
 
-    pub fn get(&self, index: usize) -> Option<i32> {
+    pub fn push_front(&mut self, value: T) {
+        let n = Node::new(value);
+
+        match self.head.take() {
+            Some(pre_head) => {
+                pre_head.borrow_mut().prev = Some(Rc::downgrade(&n));
+                n.borrow_mut().next = Some(pre_head);
+                self.head = Some(n);
+            }
+            None => {
+                self.tail = Some(Rc::clone(&n));
+                self.head = Some(n);
+            }
+        }
+        self.size += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.pop_front_node().map(Node::into_value)
+    }
+
+    /// Detaches the head node, leaving it without links.
+    fn pop_front_node(&mut self) -> Option<Link<T>> {
+        let old_head = self.head.take()?;
+        match old_head.borrow_mut().next.take() {
+            Some(new_head) => {
+                new_head.borrow_mut().prev = None;
+                self.head = Some(new_head);
+            }
+            None => {
+                self.tail = None;
+            }
+        }
+        self.size -= 1;
+        Some(old_head)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tail.take().map(|old_tail| {
+            let prev = old_tail.borrow_mut().prev.take();
+            match prev.and_then(|prev| prev.upgrade()) {
+                Some(new_tail) => {
+                    new_tail.borrow_mut().next = None;
+                    self.tail = Some(new_tail);
+                }
+                None => {
+                    self.head = None;
+                }
+            }
+            self.size -= 1;
+            Node::into_value(old_tail)
+        })
+    }
+
+    /// Inserts `value` so that it ends up at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(
+            index <= self.size,
+            "insertion index (is {}) should be <= len (is {})",
+            index,
+            self.size
+        );
+
+        match self.get_link_at(index) {
+            Some(node) => self.link_before(&node, value),
+            None => self.push_back(value),
+        }
+    }
+
+    /// Removes and returns the element at position `index`, or `None` if out of bounds.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.get_link_at(index).map(|node| self.unlink(node))
+    }
+
+    /// Moves all elements of `other` to the back of the list, leaving `other` empty.
+    pub fn append(&mut self, other: &mut List<T>) {
+        let other_head = match other.head.take() {
+            Some(head) => head,
+            None => return,
+        };
+        match self.tail.take() {
+            Some(tail) => {
+                other_head.borrow_mut().prev = Some(Rc::downgrade(&tail));
+                tail.borrow_mut().next = Some(other_head);
+            }
+            None => self.head = Some(other_head),
+        }
+        self.tail = other.tail.take();
+        self.size += mem::replace(&mut other.size, 0);
+    }
+
+    /// Splits the list in two at `at`, returning everything from `at` onwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> List<T> {
+        assert!(
+            at <= self.size,
+            "cannot split off at a nonexistent index (is {}, len is {})",
+            at,
+            self.size
+        );
+
+        let node = match self.get_link_at(at) {
+            Some(node) => node,
+            None => return List::new(),
+        };
+        let prev = node
+            .borrow_mut()
+            .prev
+            .take()
+            .and_then(|prev| prev.upgrade());
+        match prev {
+            Some(ref prev) => prev.borrow_mut().next = None,
+            None => self.head = None,
+        }
+
+        let second = List {
+            size: self.size - at,
+            head: Some(node),
+            tail: mem::replace(&mut self.tail, prev),
+        };
+        self.size = at;
+        second
+    }
+
+    /// Removes the elements in `range` and returns them in order.
+    ///
+    /// The elements are removed right away, even if the iterator is not consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is decreasing or its end lies past the end of the list.
+    pub fn drain<R>(&mut self, range: R) -> IntoIter<T>
+    where
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.size,
+        };
+        assert!(
+            start <= end,
+            "drain start {} is past its end {}",
+            start,
+            end
+        );
+        assert!(
+            end <= self.size,
+            "drain end {} is past len {}",
+            end,
+            self.size
+        );
+
+        let mut rest = self.split_off(end);
+        let drained = self.split_off(start);
+        self.append(&mut rest);
+        drained.into_iter()
+    }
+
+    /// Returns a cursor positioned on the first element.
+    pub fn cursor_front(&mut self) -> Cursor<'_, T> {
+        let current = self.head.clone();
+        Cursor {
+            list: self,
+            current,
+            index: 0,
+        }
+    }
+
+    /// Returns a cursor positioned on the last element.
+    pub fn cursor_back(&mut self) -> Cursor<'_, T> {
+        let current = self.tail.clone();
+        let index = self.size.saturating_sub(1);
+        Cursor {
+            list: self,
+            current,
+            index,
+        }
+    }
+
+    /// Links a new node holding `value` right before `node`.
+    fn link_before(&mut self, node: &Link<T>, value: T) {
+        let prev = node.borrow_mut().prev.take();
+        match prev.and_then(|prev| prev.upgrade()) {
+            Some(prev) => {
+                let n = Node::new(value);
+                prev.borrow_mut().next = Some(Rc::clone(&n));
+                n.borrow_mut().prev = Some(Rc::downgrade(&prev));
+                n.borrow_mut().next = Some(Rc::clone(node));
+                node.borrow_mut().prev = Some(Rc::downgrade(&n));
+                self.size += 1;
+            }
+            None => self.push_front(value),
+        }
+    }
+
+    /// Detaches `node` from its neighbours and returns its value.
+    fn unlink(&mut self, node: Link<T>) -> T {
+        let prev = node
+            .borrow_mut()
+            .prev
+            .take()
+            .and_then(|prev| prev.upgrade());
+        let next = node.borrow_mut().next.take();
+
+        match next {
+            Some(ref next) => next.borrow_mut().prev = prev.as_ref().map(Rc::downgrade),
+            None => self.tail = prev.clone(),
+        }
+        match prev {
+            Some(prev) => prev.borrow_mut().next = next,
+            None => self.head = next,
+        }
+
+        self.size -= 1;
+        Node::into_value(node)
+    }
+
+    /// Sorts the list with a stable merge sort, relinking the existing nodes.
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let list = mem::take(self);
+        *self = merge_sort(list, &mut compare);
+    }
+
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.sort_by(T::cmp)
+    }
+
+    /// Returns `true` if the list contains an element equal to `value`.
+    pub fn contains(&self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.links().any(|node| node.borrow().value == *value)
+    }
+
+    /// Returns the index of the first element matching `predicate`.
+    pub fn find<P>(&self, mut predicate: P) -> Option<usize>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        self.links()
+            .position(|node| predicate(&node.borrow().value))
+    }
+
+    pub fn get(&self, index: usize) -> Option<T>
+    where
+        T: Clone,
+    {
         self.get_link_at(index)
-            .map(|node| node.as_ref().borrow().value)
+            .map(|node| node.as_ref().borrow().value.clone())
     }
 
-    fn get_link_at(&self, index: usize) -> Option<Link> {
+    /// Walks the nodes from head to tail.
+    fn links(&self) -> Links<T> {
+        Links {
+            next: self.head.clone(),
+        }
+    }
+
+    fn get_link_at(&self, index: usize) -> Option<Link<T>> {
         if index >= self.len() {
             return None;
         }
@@ -67,7 +363,7 @@ impl List {
             self.size - index - 1
         };
 
-        let mut current: Link = match direction_from_head {
+        let mut current: Link<T> = match direction_from_head {
             true => Rc::clone(self.head.as_ref().unwrap()),
             false => Rc::clone(self.tail.as_ref().unwrap()),
         };
@@ -75,13 +371,31 @@ impl List {
         for _ in 0..index {
             current = match direction_from_head {
                 true => Rc::clone(current.as_ref().borrow().next.as_ref().unwrap()),
-                false => Rc::clone(current.as_ref().borrow().prev.as_ref().unwrap()),
+                false => current
+                    .as_ref()
+                    .borrow()
+                    .prev
+                    .as_ref()
+                    .unwrap()
+                    .upgrade()
+                    .unwrap(),
             };
         }
 
         Some(current)
     }
 
+    /// Removes all elements from the list.
+    pub fn clear(&mut self) {
+        self.tail = None;
+        // Unlink one node at a time, dropping a long chain recursively could overflow the stack
+        let mut current = self.head.take();
+        while let Some(node) = current {
+            current = node.borrow_mut().next.take();
+        }
+        self.size = 0;
+    }
+
     pub fn len(&self) -> usize {
         self.size
     }
@@ -95,17 +409,475 @@ impl List {
     }
 }
 
-impl Default for List {
+/// A cursor over a `List` that can edit the list at its position in O(1).
+///
+/// Besides the elements, the cursor can rest on a "ghost" position between the
+/// tail and the head, which it reaches by moving past either end.
+pub struct Cursor<'a, T> {
+    list: &'a mut List<T>,
+    current: Option<Link<T>>,
+    index: usize,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    /// Returns the position of the cursor, or `None` on the ghost position.
+    pub fn index(&self) -> Option<usize> {
+        self.current.as_ref().map(|_| self.index)
+    }
+
+    /// Moves to the next element, wrapping through the ghost position.
+    pub fn move_next(&mut self) {
+        match self.current.take() {
+            Some(node) => {
+                self.current = node.borrow().next.clone();
+                self.index += 1;
+            }
+            None => {
+                self.current = self.list.head.clone();
+                self.index = 0;
+            }
+        }
+    }
+
+    /// Moves to the previous element, wrapping through the ghost position.
+    pub fn move_prev(&mut self) {
+        match self.current.take() {
+            Some(node) => {
+                self.current = node.borrow().prev.as_ref().and_then(|prev| prev.upgrade());
+                self.index = self.index.checked_sub(1).unwrap_or(self.list.size);
+            }
+            None => {
+                self.current = self.list.tail.clone();
+                self.index = self.list.size.saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn current(&self) -> Option<Ref<'_, T>> {
+        self.current
+            .as_ref()
+            .map(|node| Ref::map(node.borrow(), |node| &node.value))
+    }
+
+    pub fn current_mut(&mut self) -> Option<RefMut<'_, T>> {
+        self.current
+            .as_ref()
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.value))
+    }
+
+    /// Inserts `value` before the current element, or at the back on the ghost position.
+    pub fn insert_before(&mut self, value: T) {
+        match self.current {
+            Some(ref node) => self.list.link_before(node, value),
+            None => self.list.push_back(value),
+        }
+        self.index += 1;
+    }
+
+    /// Inserts `value` after the current element, or at the front on the ghost position.
+    pub fn insert_after(&mut self, value: T) {
+        let next = match self.current {
+            Some(ref node) => node.borrow().next.clone(),
+            None => self.list.head.clone(),
+        };
+        match next {
+            Some(ref next) => self.list.link_before(next, value),
+            None => self.list.push_back(value),
+        }
+        if self.current.is_none() {
+            self.index += 1;
+        }
+    }
+
+    /// Removes the current element and moves the cursor to the next one.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.current.take()?;
+        self.current = node.borrow().next.clone();
+        Some(self.list.unlink(node))
+    }
+}
+
+fn merge_sort<T, F>(mut list: List<T>, compare: &mut F) -> List<T>
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if list.len() <= 1 {
+        return list;
+    }
+
+    let mut left = List::new();
+    for _ in 0..list.len() / 2 {
+        left.push_back_node(list.pop_front_node().unwrap());
+    }
+    let mut left = merge_sort(left, compare);
+    let mut right = merge_sort(list, compare);
+
+    let mut merged = List::new();
+    loop {
+        // Ties are taken from the left to keep the sort stable
+        let take_right = match (&left.head, &right.head) {
+            (Some(l), Some(r)) => compare(&r.borrow().value, &l.borrow().value) == Ordering::Less,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        let node = match take_right {
+            true => right.pop_front_node(),
+            false => left.pop_front_node(),
+        };
+        match node {
+            Some(node) => merged.push_back_node(node),
+            None => return merged,
+        }
+    }
+}
+
+struct Links<T> {
+    next: Option<Link<T>>,
+}
+
+impl<T> Iterator for Links<T> {
+    type Item = Link<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().next.clone();
+        Some(node)
+    }
+}
+
+/// An owning iterator over the elements of a `List`.
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|value| self.push_back(value));
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries = f.debug_list();
+        for node in self.links() {
+            entries.entry(&node.borrow().value);
+        }
+        entries.finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self
+                .links()
+                .zip(other.links())
+                .all(|(a, b)| a.borrow().value == b.borrow().value)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> Default for List<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 #[cfg(test)]
-mod tests{
-    //use super::*;
+mod tests {
+    use super::*;
+
+    const UPPER_BOUNDS: usize = 1000;
+
+    #[test]
+    fn push_back_and_get_keep_insertion_order() {
+        let mut list = List::new();
+        (0..UPPER_BOUNDS).for_each(|i| list.push_back(i));
 
-    //const UPPER_BOUNDS: usize = 1000;
+        assert_eq!(list.len(), UPPER_BOUNDS);
+        for i in 0..UPPER_BOUNDS {
+            assert_eq!(list.get(i), Some(i));
+        }
+        assert_eq!(list.get(UPPER_BOUNDS), None);
+    }
 
+    #[test]
+    fn push_front_reverses_order() {
+        let mut list = List::new();
+        list.push_front("b");
+        list.push_front("a");
+        list.push_back("c");
+
+        assert_eq!(list.get(0), Some("a"));
+        assert_eq!(list.get(1), Some("b"));
+        assert_eq!(list.get(2), Some("c"));
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn pop_from_both_ends() {
+        let mut list = List::new();
+        (0..4).for_each(|i| list.push_back(i.to_string()));
+
+        assert_eq!(list.pop_front(), Some("0".to_string()));
+        assert_eq!(list.pop_back(), Some("3".to_string()));
+        assert_eq!(list.pop_back(), Some("2".to_string()));
+        assert_eq!(list.pop_front(), Some("1".to_string()));
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+
+        list.push_back("again".to_string());
+        assert_eq!(list.pop_back(), Some("again".to_string()));
+    }
+
+    #[test]
+    fn insert_and_remove_at_index() {
+        let mut list = List::new();
+        list.insert(0, 1);
+        list.insert(1, 3);
+        list.insert(1, 2);
+        list.insert(0, 0);
+
+        for i in 0..4 {
+            assert_eq!(list.get(i), Some(i));
+        }
+
+        assert_eq!(list.remove(2), Some(2));
+        assert_eq!(list.remove(3), None);
+        assert_eq!(list.remove(2), Some(3));
+        assert_eq!(list.remove(0), Some(0));
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.get(0), Some(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_past_the_end_panics() {
+        let mut list = List::new();
+        list.insert(1, 0);
+    }
+
+    #[test]
+    fn cursor_splices_without_rewalking() {
+        let mut list = List::new();
+        (0..5).for_each(|i| list.push_back(i));
+
+        let mut cursor = list.cursor_front();
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.index(), Some(2));
+        assert_eq!(cursor.remove_current(), Some(2));
+        assert_eq!(*cursor.current().unwrap(), 3);
+
+        cursor.insert_before(20);
+        cursor.insert_after(30);
+        *cursor.current_mut().unwrap() += 100;
+        assert_eq!(cursor.index(), Some(3));
+
+        cursor.move_prev();
+        assert_eq!(*cursor.current().unwrap(), 20);
+
+        let expected = [0, 1, 20, 103, 30, 4];
+        assert_eq!(list.len(), expected.len());
+        for (i, value) in expected.iter().enumerate() {
+            assert_eq!(list.get(i).as_ref(), Some(value));
+        }
+    }
+
+    #[test]
+    fn cursor_wraps_through_ghost_position() {
+        let mut list = List::new();
+        list.push_back(1);
+
+        let mut cursor = list.cursor_back();
+        cursor.move_next();
+        assert_eq!(cursor.index(), None);
+        assert!(cursor.current().is_none());
+
+        cursor.insert_after(0);
+        cursor.insert_before(2);
+        cursor.move_next();
+        assert_eq!(cursor.index(), Some(0));
+        assert_eq!(*cursor.current().unwrap(), 0);
+
+        assert_eq!(list.get(2), Some(2));
+    }
+
+    #[test]
+    fn dropping_the_list_frees_every_node() {
+        let marker = Rc::new(());
+        let mut list = List::new();
+        (0..UPPER_BOUNDS).for_each(|_| list.push_back(Rc::clone(&marker)));
+        list.insert(UPPER_BOUNDS / 2, Rc::clone(&marker));
+        list.remove(1);
+
+        let head = Rc::clone(list.head.as_ref().unwrap());
+        let middle = list.get_link_at(UPPER_BOUNDS / 2).unwrap();
+        assert_eq!(Rc::strong_count(&marker), UPPER_BOUNDS + 1);
+
+        drop(list);
+        assert_eq!(Rc::strong_count(&head), 1);
+        assert_eq!(Rc::strong_count(&middle), 1);
+
+        drop(head);
+        drop(middle);
+        assert_eq!(Rc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn clear_empties_the_list() {
+        let marker = Rc::new(());
+        let mut list = List::new();
+        (0..10).for_each(|_| list.push_front(Rc::clone(&marker)));
+
+        list.clear();
+        assert!(list.is_empty());
+        assert_eq!(list.pop_back(), None);
+        assert_eq!(Rc::strong_count(&marker), 1);
+
+        list.push_back(Rc::clone(&marker));
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn dropping_a_long_list_does_not_overflow_the_stack() {
+        let mut list = List::new();
+        (0..1_000_000).for_each(|i| list.push_back(i));
+        drop(list);
+    }
+
+    #[test]
+    fn collect_extend_and_compare() {
+        let mut list: List<i32> = (1..4).collect();
+        assert_eq!(list, vec![1, 2, 3].into_iter().collect());
+
+        list.extend(vec![4, 5]);
+        assert_eq!(list.len(), 5);
+        assert_eq!(list, (1..6).collect());
+        assert_ne!(list, (1..5).collect());
+        assert_ne!(list, (0..5).collect());
+    }
+
+    #[test]
+    fn debug_prints_like_a_slice() {
+        let list: List<&str> = vec!["a", "b"].into_iter().collect();
+        assert_eq!(format!("{:?}", list), r#"["a", "b"]"#);
+        assert_eq!(format!("{:?}", List::<u8>::new()), "[]");
+    }
+
+    #[test]
+    fn sort_by_is_stable() {
+        let mut list: List<(u8, char)> = vec![(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e')]
+            .into_iter()
+            .collect();
+        list.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let expected = vec![(1, 'b'), (1, 'e'), (2, 'd'), (3, 'a'), (3, 'c')];
+        assert_eq!(list, expected.into_iter().collect());
+    }
+
+    #[test]
+    fn sort_large_list_in_reverse() {
+        let mut list: List<usize> = (0..UPPER_BOUNDS).collect();
+        list.sort_by(|a, b| b.cmp(a));
+        assert_eq!(list, (0..UPPER_BOUNDS).rev().collect());
+
+        list.sort();
+        assert_eq!(list, (0..UPPER_BOUNDS).collect());
+        assert_eq!(list.pop_back(), Some(UPPER_BOUNDS - 1));
+    }
+
+    #[test]
+    fn contains_and_find() {
+        let list: List<i32> = vec![4, 8, 15, 16, 23, 42].into_iter().collect();
+
+        assert!(list.contains(&15));
+        assert!(!list.contains(&7));
+        assert_eq!(list.find(|&v| v > 15), Some(3));
+        assert_eq!(list.find(|&v| v > 42), None);
+    }
+
+    #[test]
+    fn split_off_and_append() {
+        let mut list: List<i32> = (0..6).collect();
+
+        let mut back = list.split_off(4);
+        assert_eq!(list, (0..4).collect());
+        assert_eq!(back, (4..6).collect());
+
+        let all = list.split_off(0);
+        assert!(list.is_empty());
+        assert_eq!(all, (0..4).collect());
+
+        list.append(&mut back);
+        assert!(back.is_empty());
+        assert_eq!(list, (4..6).collect());
+        assert!(list.split_off(2).is_empty());
+        assert_eq!(list.pop_back(), Some(5));
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_off_past_the_end_panics() {
+        let mut list: List<i32> = (0..2).collect();
+        list.split_off(3);
+    }
+
+    #[test]
+    fn drain_removes_the_range() {
+        let mut list: List<i32> = (0..10).collect();
+
+        let drained: Vec<i32> = list.drain(2..5).collect();
+        assert_eq!(drained, vec![2, 3, 4]);
+        assert_eq!(list, vec![0, 1, 5, 6, 7, 8, 9].into_iter().collect());
+
+        assert_eq!(list.drain(5..).rev().collect::<Vec<_>>(), vec![9, 8]);
+        assert_eq!(list.drain(..=0).len(), 1);
+        assert_eq!(list, vec![1, 5, 6, 7].into_iter().collect());
+
+        list.drain(..);
+        assert!(list.is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+//! Renders the contents of a `four::List` as a bar chart into a BMP image,
+//! then decodes the encoded chart to check that it survived the round trip.
+
+extern crate bmp;
+extern crate four;
+
+use bmp::{consts, Image, Pixel};
+use four::List;
+
+const BAR_WIDTH: u32 = 12;
+const GAP: u32 = 4;
+const SCALE: u32 = 8;
+
+/// Returns the values of the list in order, walking it once.
+fn values(list: &mut List<u32>) -> Vec<u32> {
+    let mut values = Vec::with_capacity(list.len());
+    let mut cursor = list.cursor_front();
+    while let Some(value) = cursor.current().map(|value| *value) {
+        values.push(value);
+        cursor.move_next();
+    }
+    values
+}
+
+fn render(list: &mut List<u32>) -> Image {
+    let values = values(list);
+    let max = values.iter().copied().max().unwrap_or(0);
+    let width = GAP + values.len() as u32 * (BAR_WIDTH + GAP);
+    let height = 2 * GAP + max * SCALE;
+
+    let mut img = Image::new(width, height);
+    img.fill(consts::WHITE);
+
+    let colors = [consts::STEEL_BLUE, consts::CORAL, consts::SEAGREEN];
+    for (i, &value) in values.iter().enumerate() {
+        let color: Pixel = colors[i % colors.len()];
+        let left = GAP + i as u32 * (BAR_WIDTH + GAP);
+        // Bars grow upwards from the bottom margin
+        let top = height - GAP - value * SCALE;
+        img.fill_rect((left, top, BAR_WIDTH, value * SCALE), color);
+    }
+    img
+}
+
+#[test]
+fn bar_chart_survives_a_round_trip() {
+    let mut list: List<u32> = vec![3, 7, 2, 9, 5, 1, 6].into_iter().collect();
+    let chart = render(&mut list);
+    assert_eq!((chart.get_width(), chart.get_height()), (116, 80));
+    // The tallest bar reaches the top margin
+    assert_eq!(chart.get_pixel(52, 4), consts::STEEL_BLUE);
+    assert_eq!(chart.get_pixel(52, 3), consts::WHITE);
+
+    let mut bmp_data = Vec::new();
+    chart.to_writer(&mut bmp_data).unwrap();
+    let img = bmp::codec::from_bytes(&bmp_data).unwrap();
+    assert_eq!(img, chart);
+}
@@ -0,0 +1,215 @@
+#![deny(warnings)]
+
+//! A singly linked list, a stack really: elements are pushed and popped at the front.
+
+struct Node<T> {
+    next: Option<Box<Node<T>>>,
+    data: T,
+}
+
+impl<T> Node<T> {
+    pub const fn new(data: T) -> Self {
+        Self { next: None, data }
+    }
+}
+
+pub struct List<T> {
+    start: Option<Box<Node<T>>>,
+    size: usize,
+}
+
+impl<T> List<T> {
+    pub const fn new() -> Self {
+        Self {
+            start: None,
+            size: 0,
+        }
+    }
+
+    pub fn push_front(&mut self, data: T) {
+        let mut n = Box::new(Node::new(data));
+        n.next = self.start.take();
+        self.start = Some(n);
+        self.size += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.start.take().map(|n| {
+            self.start = n.next;
+            self.size -= 1;
+            n.data
+        })
+    }
+
+    /// Returns a reference to the first element.
+    pub fn peek(&self) -> Option<&T> {
+        self.start.as_ref().map(|n| &n.data)
+    }
+
+    /// Returns a mutable reference to the first element.
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.start.as_mut().map(|n| &mut n.data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.start.as_deref(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.start.as_deref_mut(),
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // Drop the nodes one by one, the default recursive drop could overflow the stack
+        let mut ptr = self.start.take();
+        while let Some(mut n) = ptr {
+            ptr = n.next.take();
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|n| {
+            self.next = n.next.as_deref();
+            &n.data
+        })
+    }
+}
+
+pub struct IterMut<'a, T> {
+    next: Option<&'a mut Node<T>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.take().map(|n| {
+            self.next = n.next.as_deref_mut();
+            &mut n.data
+        })
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_pop_in_lifo_order() {
+        let mut list = List::new();
+        assert_eq!(list.pop_front(), None);
+
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+        assert_eq!(list.len(), 3);
+
+        assert_eq!(list.pop_front(), Some(3));
+        assert_eq!(list.pop_front(), Some(2));
+        list.push_front(4);
+        assert_eq!(list.pop_front(), Some(4));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn peek_at_the_front() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+
+        list.push_front(String::from("a"));
+        list.push_front(String::from("b"));
+        assert_eq!(list.peek().map(String::as_str), Some("b"));
+
+        if let Some(s) = list.peek_mut() {
+            s.push('!');
+        }
+        assert_eq!(list.pop_front().as_deref(), Some("b!"));
+    }
+
+    #[test]
+    fn iterators_walk_from_the_front() {
+        let mut list = List::new();
+        (1..=3).for_each(|i| list.push_front(i));
+
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+
+        for data in &mut list {
+            *data *= 10;
+        }
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![30, 20, 10]);
+    }
+
+    #[test]
+    fn dropping_a_long_list_does_not_overflow_the_stack() {
+        let mut list = List::new();
+        (0..1_000_000).for_each(|i| list.push_front(i));
+    }
+}
@@ -0,0 +1,42 @@
+//! Times encoding and decoding of a large bottom-up image.
+//!
+//! Run with `cargo run --release --example flip_bench`.
+
+extern crate two;
+
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+use two::{Image, Pixel};
+
+const WIDTH: u32 = 4000;
+const HEIGHT: u32 = 3000;
+const RUNS: usize = 5;
+
+fn median<F: FnMut()>(mut f: F) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn main() {
+    let mut img = Image::new(WIDTH, HEIGHT);
+    for (x, y) in img.coordinates() {
+        img.set_pixel(x, y, Pixel::new(x as u8, y as u8, (x ^ y) as u8));
+    }
+
+    let mut buf = Vec::new();
+    let encode = median(|| two::encode_into_buf(&img, &mut buf).unwrap());
+    let decode = median(|| {
+        two::from_reader(&mut Cursor::new(&buf)).unwrap();
+    });
+
+    println!("{}x{} image, median of {} runs", WIDTH, HEIGHT, RUNS);
+    println!("encode: {:>8.2?}", encode);
+    println!("decode: {:>8.2?}", decode);
+}
@@ -0,0 +1,128 @@
+//! Frame accumulation for averaging repeated captures.
+//!
+//! ```
+//! use two::accumulate::Accumulator;
+//! use two::{consts, Image};
+//!
+//! let mut acc = Accumulator::new(1, 1);
+//! for px in &[consts::BLACK, consts::WHITE] {
+//!     let mut frame = Image::new(1, 1);
+//!     frame.set_pixel(0, 0, *px);
+//!     acc.add(&frame).unwrap();
+//! }
+//! assert_eq!(acc.mean().get_pixel(0, 0), consts::GRAY);
+//! ```
+
+use crate::decoder::{BmpError, BmpErrorKind, BmpResult};
+use crate::{Image, Pixel};
+
+/// Sums frames of the same size channel by channel.
+///
+/// The sums are kept in `u32`, so up to 16 million frames can be added.
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    width: u32,
+    height: u32,
+    count: u32,
+    sums: Vec<[u32; 3]>,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            count: 0,
+            sums: vec![[0; 3]; (width * height) as usize],
+        }
+    }
+
+    /// Returns the number of frames added so far.
+    #[inline]
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Adds `img` to the sums, it must be as large as the accumulator.
+    pub fn add(&mut self, img: &Image) -> BmpResult<()> {
+        if img.width != self.width || img.height != self.height {
+            return Err(BmpError::new(
+                BmpErrorKind::DimensionMismatch,
+                format!(
+                    "Expected a {}x{} frame, but was {}x{}",
+                    self.width, self.height, img.width, img.height
+                ),
+            ));
+        }
+
+        for ((x, y), sum) in img.coordinates().zip(self.sums.iter_mut()) {
+            let px = img.get_pixel(x, y);
+            sum[0] += px.r as u32;
+            sum[1] += px.g as u32;
+            sum[2] += px.b as u32;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Returns the rounded per-pixel average, black if nothing was added.
+    pub fn mean(&self) -> Image {
+        let mut img = Image::new(self.width, self.height);
+        if self.count == 0 {
+            return img;
+        }
+
+        let (count, half) = (self.count, self.count / 2);
+        for ((x, y), sum) in img.coordinates().zip(self.sums.iter()) {
+            let avg = |s: u32| (s + half) / count;
+            img.set_pixel(x, y, px!(avg(sum[0]), avg(sum[1]), avg(sum[2])));
+        }
+        img
+    }
+
+    /// Clears the sums to start over.
+    pub fn reset(&mut self) {
+        self.sums.fill([0; 3]);
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(val: u8) -> Image {
+        let mut img = Image::new(2, 2);
+        img.fill(px!(val, val / 2, 255 - val));
+        img
+    }
+
+    #[test]
+    fn mean_rounds_the_average() {
+        let mut acc = Accumulator::new(2, 2);
+        assert_eq!(acc.mean(), Image::new(2, 2));
+
+        for val in &[10, 11, 11] {
+            acc.add(&frame(*val)).unwrap();
+        }
+        assert_eq!(acc.count(), 3);
+        assert_eq!(acc.mean().get_pixel(1, 1), px!(11, 5, 244));
+
+        acc.reset();
+        acc.add(&frame(20)).unwrap();
+        assert_eq!(acc.mean(), frame(20));
+    }
+
+    #[test]
+    fn error_when_adding_a_frame_of_another_size() {
+        let mut acc = Accumulator::new(3, 2);
+        match acc.add(&frame(0)) {
+            Err(BmpError {
+                kind: BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Frames of another size cannot be accumulated"),
+        }
+        assert_eq!(acc.count(), 0);
+    }
+}
@@ -0,0 +1,228 @@
+//! Measurements on image content, such as those used to straighten scans or
+//! to reject blurry frames.
+//!
+//! ```no_run
+//! use two::sample::Filter;
+//! use two::{analysis, consts, GrayImage};
+//!
+//! let scan = two::open("scan.bmp").unwrap();
+//! let skew = analysis::estimate_skew(&GrayImage::from_image(&scan));
+//! let straight = scan.rotate(-skew, Filter::Bilinear, consts::WHITE);
+//! ```
+
+use crate::{GrayImage, Image};
+
+// Largest skew looked for, in degrees
+const MAX_SKEW: f32 = 15.0;
+// Steps of the coarse and the fine search, in degrees
+const COARSE_STEP: f32 = 0.5;
+const FINE_STEP: f32 = 0.05;
+
+/// Estimates by how many degrees the text lines of a scanned page are rotated
+/// clockwise, within 15 degrees either way.
+///
+/// Samples below 128 are taken as ink. The ink is projected onto rows at a
+/// range of angles, and the angle where the rows are most unevenly filled is
+/// the one where they line up with the text. Pages without ink give `0.0`.
+pub fn estimate_skew(img: &GrayImage) -> f32 {
+    let ink: Vec<(f32, f32)> = img
+        .coordinates()
+        .filter(|&(x, y)| img.get_pixel(x, y) < 128)
+        .map(|(x, y)| (x as f32, y as f32))
+        .collect();
+    if ink.is_empty() {
+        return 0.0;
+    }
+
+    let coarse = best_angle(&ink, -MAX_SKEW, MAX_SKEW, COARSE_STEP);
+    best_angle(&ink, coarse - COARSE_STEP, coarse + COARSE_STEP, FINE_STEP)
+}
+
+/// Per-block measurements, row by row from the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockMap {
+    block: u32,
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl BlockMap {
+    /// Returns the size of the square blocks in pixels.
+    #[inline]
+    pub fn block_size(&self) -> u32 {
+        self.block
+    }
+
+    /// Returns the number of blocks across.
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the number of blocks down.
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the value of the block in column `bx` and row `by`.
+    #[inline]
+    pub fn get(&self, bx: u32, by: u32) -> f32 {
+        self.values[(by * self.width + bx) as usize]
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Returns the largest value, or `0.0` if there are no blocks.
+    pub fn max(&self) -> f32 {
+        self.values.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Returns the average value, or `0.0` if there are no blocks.
+    pub fn mean(&self) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        self.values.iter().sum::<f32>() / self.values.len() as f32
+    }
+}
+
+/// Measures the focus of every `block` by `block` region of `img` as the
+/// variance of the Laplacian of its luma.
+///
+/// Sharp detail gives high values, blurred or flat regions give values close
+/// to zero. Blocks along the right and bottom edges may be smaller.
+///
+/// # Panics
+///
+/// Panics if `block` is zero.
+pub fn sharpness_map(img: &Image, block: u32) -> BlockMap {
+    assert!(block > 0, "block size must be positive");
+    let (w, h) = (img.width, img.height);
+    let gray = GrayImage::from_image(img);
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, w as i64 - 1) as u32;
+        let y = y.clamp(0, h as i64 - 1) as u32;
+        gray.get_pixel(x, y) as f64
+    };
+
+    let (bw, bh) = (w.div_ceil(block), h.div_ceil(block));
+    // Sums and sums of squares of the Laplacian, per block
+    let mut sums = vec![(0f64, 0f64, 0u32); (bw * bh) as usize];
+    for (x, y) in img.coordinates() {
+        let (xi, yi) = (x as i64, y as i64);
+        let lap =
+            at(xi - 1, yi) + at(xi + 1, yi) + at(xi, yi - 1) + at(xi, yi + 1) - 4.0 * at(xi, yi);
+        let sum = &mut sums[((y / block) * bw + x / block) as usize];
+        sum.0 += lap;
+        sum.1 += lap * lap;
+        sum.2 += 1;
+    }
+
+    let values = sums
+        .iter()
+        .map(|&(s, s2, n)| {
+            let n = n as f64;
+            let mean = s / n;
+            (s2 / n - mean * mean).max(0.0) as f32
+        })
+        .collect();
+    BlockMap {
+        block,
+        width: bw,
+        height: bh,
+        values,
+    }
+}
+
+/// Returns the angle in `from..=to` whose projection profile scores highest.
+fn best_angle(ink: &[(f32, f32)], from: f32, to: f32, step: f32) -> f32 {
+    let steps = ((to - from) / step).round() as i32;
+    let mut best: (f32, f64) = (0.0, f64::MIN);
+    let mut bins = Vec::new();
+    for i in 0..=steps {
+        let angle = from + i as f32 * step;
+        let score = profile_score(ink, angle, &mut bins);
+        // Ties go to the angle closest to level
+        if score > best.1 || (score == best.1 && angle.abs() < best.0.abs()) {
+            best = (angle, score);
+        }
+    }
+    best.0
+}
+
+/// Sums the squared ink counts of the rows of a page rotated by `degrees`.
+fn profile_score(ink: &[(f32, f32)], degrees: f32, bins: &mut Vec<u32>) -> f64 {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    // Points on a line rotated clockwise by the angle share this coordinate
+    let row = |&(x, y): &(f32, f32)| y * cos - x * sin;
+    let (min, max) = ink
+        .iter()
+        .map(row)
+        .fold((f32::MAX, f32::MIN), |(lo, hi), r| (lo.min(r), hi.max(r)));
+
+    bins.clear();
+    bins.resize((max - min) as usize + 1, 0);
+    for p in ink {
+        bins[(row(p) - min) as usize] += 1;
+    }
+    bins.iter().map(|&n| (n as f64) * (n as f64)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page of dark, slightly rotated lines.
+    fn page(degrees: f32) -> GrayImage {
+        let mut img = GrayImage::from_vec(200, 120, vec![255; 200 * 120]).unwrap();
+        let slope = degrees.to_radians().tan();
+        for line in 0..5 {
+            for x in 10..190 {
+                let y = 20.0 + line as f32 * 20.0 + x as f32 * slope;
+                if (0.0..120.0).contains(&y) {
+                    img.set_pixel(x, y as u32, 0);
+                }
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn estimates_the_angle_of_text_lines() {
+        for &degrees in &[0.0, 3.0, -7.5] {
+            let skew = estimate_skew(&page(degrees));
+            assert!((skew - degrees).abs() < 0.2, "{} vs {}", skew, degrees);
+        }
+    }
+
+    #[test]
+    fn blank_pages_are_level() {
+        let blank = GrayImage::from_vec(4, 4, vec![255; 16]).unwrap();
+        assert_eq!(estimate_skew(&blank), 0.0);
+        assert_eq!(estimate_skew(&GrayImage::new(0, 0)), 0.0);
+    }
+
+    #[test]
+    fn sharp_blocks_score_higher_than_flat_ones() {
+        let mut img = Image::new(24, 8);
+        for (x, y) in img.coordinates() {
+            if x < 8 && (x + y) % 2 == 0 {
+                img.set_pixel(x, y, crate::consts::WHITE);
+            }
+        }
+
+        let map = sharpness_map(&img, 8);
+        assert_eq!((map.get_width(), map.get_height()), (3, 1));
+        assert!(map.get(0, 0) > 1000.0);
+        assert_eq!(map.get(2, 0), 0.0);
+        assert_eq!(map.max(), map.get(0, 0));
+
+        let map = sharpness_map(&img, 5);
+        assert_eq!(map.as_slice().len(), 5 * 2);
+    }
+}
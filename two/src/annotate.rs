@@ -0,0 +1,226 @@
+//! Scale bars and color bar legends that make exported images self-describing.
+//!
+//! ```
+//! use two::annotate::{self, ScaleBarStyle};
+//! use two::colormap::ColorMap;
+//! use two::GrayImage;
+//!
+//! let mut img = GrayImage::new(200, 100).apply_colormap(&ColorMap::Viridis);
+//! // 4 pixels are one micrometer
+//! annotate::scale_bar(&mut img, 4.0, &ScaleBarStyle::default());
+//! annotate::color_bar(&mut img, &ColorMap::Viridis, (0.0, 1.0));
+//! ```
+
+use crate::colormap::ColorMap;
+use crate::{consts, draw, Image, Pixel};
+
+// Distance kept between the annotations and the image edges
+const MARGIN: u32 = 4;
+// Width of the color bar's gradient
+const COLOR_BAR_WIDTH: u32 = 10;
+
+/// A corner of the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// The look of a scale bar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScaleBarStyle {
+    /// Length of the bar in units, a round length of about a fifth of the image if `None`.
+    pub length: Option<f32>,
+    /// Unit shown after the length.
+    pub unit: String,
+    pub corner: Corner,
+    pub color: Pixel,
+    /// Fills the box behind the bar and label, if any.
+    pub background: Option<Pixel>,
+    /// Thickness of the bar in pixels.
+    pub thickness: u32,
+    /// Magnification of the label's font.
+    pub text_scale: u32,
+}
+
+impl Default for ScaleBarStyle {
+    fn default() -> ScaleBarStyle {
+        ScaleBarStyle {
+            length: None,
+            unit: String::from("um"),
+            corner: Corner::BottomRight,
+            color: consts::WHITE,
+            background: Some(consts::BLACK),
+            thickness: 3,
+            text_scale: 1,
+        }
+    }
+}
+
+/// Draws a bar with a length label into a corner of `img`, where
+/// `pixels_per_unit` pixels correspond to one unit.
+pub fn scale_bar(img: &mut Image, pixels_per_unit: f32, style: &ScaleBarStyle) {
+    if !pixels_per_unit.is_finite() || pixels_per_unit <= 0.0 {
+        return;
+    }
+    let length = style
+        .length
+        .unwrap_or_else(|| round_length(img.width as f32 / 5.0 / pixels_per_unit));
+    let bar_width = (length * pixels_per_unit).round().max(1.0) as u32;
+
+    let label = format!("{} {}", format_value(length), style.unit);
+    let (text_width, text_height) = draw::text_size(&label, style.text_scale);
+    let box_width = bar_width.max(text_width) + 2 * MARGIN;
+    let box_height = style.thickness + text_height + 3 * MARGIN;
+
+    let (x, y) = corner_origin(img, style.corner, box_width, box_height);
+    if let Some(background) = style.background {
+        img.fill_rect(
+            (x.max(0) as u32, y.max(0) as u32, box_width, box_height),
+            background,
+        );
+    }
+
+    // The bar and the label are centered in the box
+    let bar_x = x + ((box_width - bar_width) / 2) as i32;
+    let bar_y = y + MARGIN as i32;
+    for dy in 0..style.thickness as i32 {
+        let end = bar_x + bar_width as i32 - 1;
+        draw::line(img, (bar_x, bar_y + dy), (end, bar_y + dy), style.color);
+    }
+    let text_x = x + ((box_width - text_width) / 2) as i32;
+    let text_y = bar_y + (style.thickness + MARGIN) as i32;
+    draw::text(img, (text_x, text_y), &label, style.color, style.text_scale);
+}
+
+/// Draws a vertical legend of `map` along the right edge of `img`, labelled
+/// with the values `range` maps to its bottom and top.
+pub fn color_bar(img: &mut Image, map: &ColorMap, range: (f32, f32)) {
+    if img.height <= 2 * MARGIN {
+        return;
+    }
+    let lut = map.lut();
+    let (low, high) = (format_value(range.0), format_value(range.1));
+    let label_width = draw::text_size(&low, 1).0.max(draw::text_size(&high, 1).0);
+
+    let height = img.height - 2 * MARGIN;
+    let bar_x = img.width as i32 - (MARGIN + COLOR_BAR_WIDTH) as i32;
+    let top = MARGIN as i32;
+    let bottom = top + height as i32 - 1;
+    for i in 0..height {
+        // The top row shows the last entry of the map
+        let val = if height > 1 {
+            (height - 1 - i) * 255 / (height - 1)
+        } else {
+            255
+        };
+        let y = top + i as i32;
+        let end = bar_x + COLOR_BAR_WIDTH as i32 - 1;
+        draw::line(img, (bar_x, y), (end, y), lut[val as usize]);
+    }
+    let outline = (bar_x - 1, top - 1, COLOR_BAR_WIDTH + 2, height + 2);
+    draw::rect(img, outline, consts::WHITE);
+
+    // Labels go on a dark box to the left of the bar
+    let label_x = bar_x - (label_width + MARGIN) as i32;
+    let box_x = (label_x - 1).max(0) as u32;
+    let label_box = |y: i32| {
+        (
+            box_x,
+            (y - 1).max(0) as u32,
+            label_width + 2,
+            draw::GLYPH_HEIGHT + 2,
+        )
+    };
+    let low_y = bottom - draw::GLYPH_HEIGHT as i32 + 1;
+    img.fill_rect(label_box(top), consts::BLACK);
+    img.fill_rect(label_box(low_y), consts::BLACK);
+    draw::text(img, (label_x, top), &high, consts::WHITE, 1);
+    draw::text(img, (label_x, low_y), &low, consts::WHITE, 1);
+}
+
+/// Returns the top-left corner of a `width` by `height` box placed in `corner`.
+fn corner_origin(img: &Image, corner: Corner, width: u32, height: u32) -> (i32, i32) {
+    let right = img.width as i32 - (width + MARGIN) as i32;
+    let bottom = img.height as i32 - (height + MARGIN) as i32;
+    match corner {
+        Corner::TopLeft => (MARGIN as i32, MARGIN as i32),
+        Corner::TopRight => (right, MARGIN as i32),
+        Corner::BottomLeft => (MARGIN as i32, bottom),
+        Corner::BottomRight => (right, bottom),
+    }
+}
+
+/// Rounds `length` down to 1, 2 or 5 times a power of ten.
+fn round_length(length: f32) -> f32 {
+    if !length.is_finite() || length <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f32.powf(length.log10().floor());
+    let leading = length / magnitude;
+    let nice = if leading >= 5.0 {
+        5.0
+    } else if leading >= 2.0 {
+        2.0
+    } else {
+        1.0
+    };
+    nice * magnitude
+}
+
+/// Formats `val` with at most two decimals and without trailing zeros.
+fn format_value(val: f32) -> String {
+    let s = format!("{:.2}", val);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        String::from("0")
+    } else {
+        String::from(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_are_rounded_to_nice_values() {
+        assert_eq!(round_length(7.3), 5.0);
+        assert_eq!(round_length(0.031), 0.02);
+        assert_eq!(round_length(180.0), 100.0);
+        assert_eq!(format_value(0.5), "0.5");
+        assert_eq!(format_value(20.0), "20");
+    }
+
+    #[test]
+    fn scale_bar_spans_the_requested_length() {
+        let mut img = Image::new(100, 50);
+        let style = ScaleBarStyle {
+            length: Some(10.0),
+            background: None,
+            corner: Corner::TopLeft,
+            ..ScaleBarStyle::default()
+        };
+        scale_bar(&mut img, 3.0, &style);
+
+        let y = 2 * MARGIN;
+        let row: Vec<u32> = (0..img.width)
+            .filter(|&x| img.get_pixel(x, y) == style.color)
+            .collect();
+        assert_eq!(row.len(), 30);
+        assert_eq!(row[29] - row[0], 29);
+    }
+
+    #[test]
+    fn color_bar_runs_from_high_to_low() {
+        let mut img = Image::new(60, 40);
+        color_bar(&mut img, &ColorMap::Grayscale, (0.0, 1.0));
+
+        let x = img.width - MARGIN - COLOR_BAR_WIDTH / 2;
+        assert_eq!(img.get_pixel(x, MARGIN), consts::WHITE);
+        assert_eq!(img.get_pixel(x, img.height - MARGIN - 1), consts::BLACK);
+        assert!(img.get_pixel(x, 20).r > 100);
+    }
+}
@@ -0,0 +1,81 @@
+//! Conversions to and from `ndarray` arrays, behind the `ndarray` feature.
+//!
+//! Arrays have the shape `(height, width, 3)`, indexed as `[[y, x, channel]]`
+//! with the channels in RGB order.
+//!
+//! ```
+//! use two::Image;
+//!
+//! let img = two::open("test/rgbw.bmp").unwrap();
+//! let array = img.to_ndarray();
+//! assert_eq!(array.dim(), (2, 2, 3));
+//! assert_eq!(array[[0, 1, 1]], 255);
+//! assert_eq!(Image::from_ndarray(array.view()).unwrap().to_ndarray(), array);
+//! ```
+
+use ndarray::{Array3, ArrayView3};
+
+use crate::decoder::{BmpError, BmpErrorKind, BmpResult};
+use crate::{Image, Pixel};
+
+impl Image {
+    /// Copies the pixels into an array of shape `(height, width, 3)`.
+    pub fn to_ndarray(&self) -> Array3<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        Array3::from_shape_fn((height, width, 3), |(y, x, c)| {
+            let px = self.data[y * width + x];
+            [px.r, px.g, px.b][c]
+        })
+    }
+
+    /// Creates an image from an array of shape `(height, width, 3)`, in any
+    /// memory layout.
+    ///
+    /// Fails with `DimensionMismatch` if the last axis does not hold 3 channels.
+    pub fn from_ndarray(array: ArrayView3<u8>) -> BmpResult<Image> {
+        let (height, width, channels) = array.dim();
+        if channels != 3 {
+            return Err(BmpError::new(
+                BmpErrorKind::DimensionMismatch,
+                format!("Expected 3 channels, but found {}", channels),
+            ));
+        }
+
+        let mut img = Image::new(width as u32, height as u32);
+        for (px, rgb) in img.data.iter_mut().zip(array.rows()) {
+            *px = px!(rgb[0], rgb[1], rgb[2]);
+        }
+        Ok(img)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+    use ndarray::ShapeBuilder;
+
+    #[test]
+    fn arrays_round_trip_in_any_layout() {
+        let mut img = Image::new(3, 2);
+        img.set_pixel(2, 1, consts::RED);
+        img.set_pixel(0, 1, consts::BLUE);
+        let array = img.to_ndarray();
+        assert_eq!(array[[1, 2, 0]], 255);
+        assert_eq!(array[[1, 0, 2]], 255);
+
+        // Column-major, so the channels of a pixel are not next to each other
+        let mut planar = Array3::zeros((2, 3, 3).f());
+        planar.assign(&array);
+        assert_eq!(Image::from_ndarray(planar.view()).unwrap().data, img.data);
+
+        let rgba = Array3::<u8>::zeros((2, 3, 4));
+        match Image::from_ndarray(rgba.view()) {
+            Err(BmpError {
+                kind: BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Four channels should not be accepted"),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Storage for the pixels of an image.
+//!
+//! An `Image` keeps its pixels in a `Vec` by default, but any `PixelBuffer`
+//! will do: a `Box<[Pixel]>` from a custom allocator, a fixed array or a
+//! `&'static mut [Pixel]` on targets without a heap. Accessing pixels,
+//! filling and saving work on every buffer, while operations that create new
+//! images return `Vec` backed ones.
+//!
+//! ```
+//! use two::{consts, Image, Pixel};
+//!
+//! let mut storage = [consts::BLACK; 16];
+//! let mut img = Image::from_buffer(4, 4, &mut storage[..]).unwrap();
+//! img.set_pixel(1, 0, consts::RED);
+//! drop(img);
+//! assert_eq!(storage[1], consts::RED);
+//!
+//! let boxed: Box<[Pixel]> = vec![consts::WHITE; 6].into_boxed_slice();
+//! let bmp_data = include_bytes!("../test/rgbw.bmp");
+//! let img = two::decode_into_buffer(bmp_data, boxed).unwrap();
+//! assert_eq!(img.get_pixel(1, 1), consts::WHITE);
+//! assert_eq!(img.into_buffer().len(), 6);
+//! ```
+
+use crate::Pixel;
+
+/// Anything that holds pixels in a contiguous, mutable slice.
+pub trait PixelBuffer: AsRef<[Pixel]> + AsMut<[Pixel]> {}
+
+impl<T: AsRef<[Pixel]> + AsMut<[Pixel]>> PixelBuffer for T {}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{BmpError, BmpErrorKind};
+    use crate::{consts, Image};
+
+    #[test]
+    fn images_over_boxed_slices_round_trip() {
+        let mut img = Image::from_buffer(3, 2, vec![consts::BLUE; 8].into_boxed_slice()).unwrap();
+        img.set_pixel(2, 1, consts::RED);
+        let mut bmp_data = Vec::new();
+        img.to_writer(&mut bmp_data).unwrap();
+        let decoded = crate::from_bytes(&bmp_data).unwrap();
+        assert_eq!(decoded.pixels(), img.pixels());
+        assert_eq!(img.pixels().len(), 6);
+
+        match Image::from_buffer(3, 3, [consts::BLACK; 8]) {
+            Err(BmpError {
+                kind: BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Buffers too small for the image should be rejected"),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Memoized decoding of image files.
+//!
+//! ```no_run
+//! use two::cache::{open_cached, Cache};
+//!
+//! let mut cache = Cache::new();
+//! for _ in 0..10 {
+//!     // Only the first call decodes the file, as long as it does not change
+//!     let img = open_cached("img.bmp", &mut cache).unwrap();
+//!     println!("{}x{}", img.get_width(), img.get_height());
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::decoder::BmpResult;
+use crate::Image;
+
+#[derive(Clone, Debug)]
+struct Entry {
+    modified: SystemTime,
+    size: u64,
+    image: Image,
+}
+
+/// Decoded images keyed by their path, modification time and file size.
+///
+/// Paths are used as given, so the same file reached through two different
+/// paths is cached twice.
+#[derive(Clone, Debug, Default)]
+pub struct Cache {
+    entries: HashMap<PathBuf, Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache::default()
+    }
+
+    /// Returns the number of cached images.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns how many lookups were served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns how many lookups had to decode the file.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Forgets the image cached for `path`.
+    pub fn invalidate<P: AsRef<Path>>(&mut self, path: P) {
+        self.entries.remove(path.as_ref());
+    }
+
+    /// Forgets all cached images.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Opens the image at `path`, decoding it only if it is not in `cache` or
+/// the file's modification time or size changed since it was cached.
+pub fn open_cached<P: AsRef<Path>>(path: P, cache: &mut Cache) -> BmpResult<&Image> {
+    let path = path.as_ref();
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?;
+    let size = metadata.len();
+
+    let fresh = cache
+        .entries
+        .get(path)
+        .is_some_and(|e| e.modified == modified && e.size == size);
+    if fresh {
+        cache.hits += 1;
+    } else {
+        let image = crate::open(path)?;
+        cache.misses += 1;
+        cache.entries.insert(
+            path.to_path_buf(),
+            Entry {
+                modified,
+                size,
+                image,
+            },
+        );
+    }
+
+    Ok(&cache.entries[path].image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+    use std::env;
+
+    #[test]
+    fn second_open_is_served_from_the_cache() {
+        let mut cache = Cache::new();
+        let first = open_cached("test/rgbw.bmp", &mut cache).unwrap().clone();
+        let second = open_cached("test/rgbw.bmp", &mut cache).unwrap();
+
+        assert_eq!(&first, second);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(cache.len(), 1);
+
+        cache.invalidate("test/rgbw.bmp");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn changed_files_are_decoded_again() {
+        let path = env::temp_dir().join("two-cache-test.bmp");
+        let mut cache = Cache::new();
+
+        Image::new(2, 2).save(&path).unwrap();
+        assert_eq!(open_cached(&path, &mut cache).unwrap().get_width(), 2);
+
+        let mut img = Image::new(3, 3);
+        img.fill(consts::RED);
+        img.save(&path).unwrap();
+        assert_eq!(open_cached(&path, &mut cache).unwrap(), &img);
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn error_when_the_file_is_missing() {
+        let mut cache = Cache::new();
+        assert!(open_cached("test/no_img.bmp", &mut cache).is_err());
+        assert!(cache.is_empty());
+    }
+}
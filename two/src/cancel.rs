@@ -0,0 +1,61 @@
+//! Stopping a decode from another thread.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::thread;
+//! use two::{CancellationToken, Decoder};
+//!
+//! let token = CancellationToken::new();
+//! let decoding = {
+//!     let token = token.clone();
+//!     thread::spawn(move || {
+//!         Decoder::new(File::open("huge.bmp").unwrap())
+//!             .cancel_with(token)
+//!             .decode()
+//!     })
+//! };
+//! // The user closed the window
+//! token.cancel();
+//! assert!(decoding.join().unwrap().is_err());
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared by its clones. Once cancelled, decodes given the token stop
+/// before their next row and fail with `BmpErrorKind::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels every decode given this token or one of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_flag() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+}
@@ -0,0 +1,146 @@
+//! Lookup tables mapping 8-bit intensities to colors.
+
+use crate::policy::ColorPolicy;
+use crate::Pixel;
+
+/// A mapping of the intensities `0..=255` to colors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColorMap {
+    /// Black to white.
+    Grayscale,
+    /// Black through red and yellow to white, common for thermal images.
+    Hot,
+    /// Dark blue through cyan, yellow and red to dark red.
+    Jet,
+    /// The perceptually uniform dark purple to yellow map.
+    Viridis,
+    /// Evenly spaced color stops, interpolated linearly.
+    ///
+    /// A table of 256 entries is used as-is.
+    Custom(Vec<Pixel>),
+}
+
+impl ColorMap {
+    /// Returns the color for `val`.
+    pub fn lookup(&self, val: u8) -> Pixel {
+        self.lut()[val as usize]
+    }
+
+    /// Returns the full lookup table.
+    pub fn lut(&self) -> [Pixel; 256] {
+        self.lut_with(ColorPolicy::default())
+    }
+
+    /// Like `lut`, converting the colors interpolated between stops with `policy`.
+    pub fn lut_with(&self, policy: ColorPolicy) -> [Pixel; 256] {
+        let gradient = |stops: &[(f32, Pixel)]| gradient(stops, policy);
+        match *self {
+            ColorMap::Grayscale => {
+                let mut lut = [px!(0, 0, 0); 256];
+                for (i, px) in lut.iter_mut().enumerate() {
+                    *px = px!(i, i, i);
+                }
+                lut
+            }
+            ColorMap::Hot => gradient(&[
+                (0.0, px!(0, 0, 0)),
+                (0.375, px!(255, 0, 0)),
+                (0.75, px!(255, 255, 0)),
+                (1.0, px!(255, 255, 255)),
+            ]),
+            ColorMap::Jet => gradient(&[
+                (0.0, px!(0, 0, 128)),
+                (0.125, px!(0, 0, 255)),
+                (0.375, px!(0, 255, 255)),
+                (0.625, px!(255, 255, 0)),
+                (0.875, px!(255, 0, 0)),
+                (1.0, px!(128, 0, 0)),
+            ]),
+            ColorMap::Viridis => gradient(&[
+                (0.0, px!(68, 1, 84)),
+                (0.125, px!(71, 44, 122)),
+                (0.25, px!(59, 81, 139)),
+                (0.375, px!(44, 113, 142)),
+                (0.5, px!(33, 144, 141)),
+                (0.625, px!(39, 173, 129)),
+                (0.75, px!(92, 200, 99)),
+                (0.875, px!(170, 220, 50)),
+                (1.0, px!(253, 231, 37)),
+            ]),
+            ColorMap::Custom(ref colors) => match colors.len() {
+                0 => [px!(0, 0, 0); 256],
+                1 => [colors[0]; 256],
+                256 => {
+                    let mut lut = [px!(0, 0, 0); 256];
+                    lut.copy_from_slice(colors);
+                    lut
+                }
+                n => {
+                    let step = 1.0 / (n - 1) as f32;
+                    let stops: Vec<(f32, Pixel)> = colors
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &px)| (i as f32 * step, px))
+                        .collect();
+                    gradient(&stops)
+                }
+            },
+        }
+    }
+}
+
+/// Interpolates between `(position, color)` stops that span `0.0..=1.0` in increasing order.
+fn gradient(stops: &[(f32, Pixel)], policy: ColorPolicy) -> [Pixel; 256] {
+    let mut lut = [px!(0, 0, 0); 256];
+    let mut segment = 0;
+    for (i, px) in lut.iter_mut().enumerate() {
+        let t = i as f32 / 255.0;
+        while segment + 2 < stops.len() && t > stops[segment + 1].0 {
+            segment += 1;
+        }
+
+        let (t0, a) = stops[segment];
+        let (t1, b) = stops[segment + 1];
+        let f = ((t - t0) / (t1 - t0)).clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| policy.to_u8(a as f32 + (b as f32 - a as f32) * f);
+        *px = Pixel::new(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b));
+    }
+    lut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    #[test]
+    fn built_in_maps_hit_their_end_points() {
+        assert_eq!(ColorMap::Grayscale.lookup(7), px!(7, 7, 7));
+        assert_eq!(ColorMap::Jet.lookup(0), px!(0, 0, 128));
+        assert_eq!(ColorMap::Jet.lookup(255), px!(128, 0, 0));
+        assert_eq!(ColorMap::Viridis.lookup(255), px!(253, 231, 37));
+        assert_eq!(ColorMap::Hot.lookup(255), consts::WHITE);
+    }
+
+    #[test]
+    fn custom_maps_interpolate_between_stops() {
+        let map = ColorMap::Custom(vec![consts::BLACK, consts::BLUE, consts::WHITE]);
+        assert_eq!(map.lookup(0), consts::BLACK);
+        assert_eq!(map.lookup(255), consts::WHITE);
+        assert_eq!(map.lookup(64), px!(0, 0, 128));
+
+        let mut table = vec![consts::RED; 256];
+        table[10] = consts::LIME;
+        assert_eq!(ColorMap::Custom(table).lookup(10), consts::LIME);
+        assert_eq!(ColorMap::Custom(vec![]).lookup(10), consts::BLACK);
+
+        let truncate = ColorPolicy {
+            rounding: crate::policy::Rounding::Down,
+            ..ColorPolicy::default()
+        };
+        // Two steps towards 100 make 0.78
+        let map = ColorMap::Custom(vec![consts::BLACK, px!(100, 100, 100)]);
+        assert_eq!(map.lut()[2], px!(1, 1, 1));
+        assert_eq!(map.lut_with(truncate)[2], px!(0, 0, 0));
+    }
+}
@@ -0,0 +1,137 @@
+//! Composites of two images for reviewing them against each other.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use two::compare;
+//!
+//! let mut before = File::open("before.bmp").unwrap();
+//! let mut after = File::open("after.bmp").unwrap();
+//! compare::side_by_side(&mut before, &mut after)
+//!     .unwrap()
+//!     .save("review.bmp")
+//!     .unwrap();
+//! ```
+
+use std::io::Read;
+
+use crate::decoder::BmpResult;
+use crate::{consts, draw, from_reader, Image};
+
+// Distance kept between the labels and the edges
+const MARGIN: u32 = 2;
+// Height of the band holding the labels above side-by-side images
+const LABEL_BAND: u32 = draw::GLYPH_HEIGHT + 2 * MARGIN;
+// Width of the gap between side-by-side images
+const GAP: u32 = 2;
+
+/// How two images are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// The images next to each other, labeled `A` and `B` in a band above them.
+    SideBySide,
+    /// The images on top of each other, showing `A` left and `B` right of a
+    /// divider at the given fraction of the width, like a slider.
+    Split(f32),
+}
+
+/// Decodes two images and places them next to each other, labeled `A` and `B`.
+pub fn side_by_side<A: Read, B: Read>(a: &mut A, b: &mut B) -> BmpResult<Image> {
+    Ok(compose(
+        &from_reader(a)?,
+        &from_reader(b)?,
+        Layout::SideBySide,
+    ))
+}
+
+/// Decodes two images and shows `A` left and `B` right of a divider at
+/// `position`, a fraction of the width.
+pub fn split<A: Read, B: Read>(a: &mut A, b: &mut B, position: f32) -> BmpResult<Image> {
+    Ok(compose(
+        &from_reader(a)?,
+        &from_reader(b)?,
+        Layout::Split(position),
+    ))
+}
+
+/// Combines `a` and `b` with `layout`.
+///
+/// Images of different sizes are aligned at their top-left corners, areas
+/// covered by neither of them are black.
+pub fn compose(a: &Image, b: &Image, layout: Layout) -> Image {
+    let height = a.height.max(b.height);
+    match layout {
+        Layout::SideBySide => {
+            let mut out = Image::new(a.width + GAP + b.width, LABEL_BAND + height);
+            let bx = a.width + GAP;
+            out.copy_from(a, (0, 0, a.width, a.height), (0, LABEL_BAND as i32));
+            out.copy_from(b, (0, 0, b.width, b.height), (bx as i32, LABEL_BAND as i32));
+            label(&mut out, "A", MARGIN);
+            label(&mut out, "B", bx + MARGIN);
+            out
+        }
+        Layout::Split(position) => {
+            let width = a.width.max(b.width);
+            let mut out = Image::new(width, height);
+            // NaN stays NaN through `clamp`, and casts to 0
+            let divider = (position.clamp(0.0, 1.0) * width as f32).round() as u32;
+            out.copy_from(b, (0, 0, b.width, b.height), (0, 0));
+            out.copy_from(a, (0, 0, divider, a.height), (0, 0));
+            if divider > 0 && divider < width {
+                out.fill_rect((divider, 0, 1, height), consts::WHITE);
+            }
+            label(&mut out, "A", MARGIN);
+            let (bw, _) = draw::text_size("B", 1);
+            label(&mut out, "B", width.saturating_sub(bw + MARGIN));
+            out
+        }
+    }
+}
+
+/// Writes `s` at the top of `img`, starting at column `x`.
+fn label(img: &mut Image, s: &str, x: u32) {
+    draw::text(img, (x as i32, MARGIN as i32), s, consts::WHITE, 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn filled(width: u32, height: u32, color: crate::Pixel) -> Image {
+        let mut img = Image::new(width, height);
+        img.fill(color);
+        img
+    }
+
+    #[test]
+    fn side_by_side_places_the_images_below_labels() {
+        let (a, b) = (filled(4, 3, consts::RED), filled(5, 6, consts::BLUE));
+        let mut bytes_a = Vec::new();
+        let mut bytes_b = Vec::new();
+        a.to_writer(&mut bytes_a).unwrap();
+        b.to_writer(&mut bytes_b).unwrap();
+
+        let out = side_by_side(&mut Cursor::new(bytes_a), &mut Cursor::new(bytes_b)).unwrap();
+        assert_eq!((out.get_width(), out.get_height()), (11, LABEL_BAND + 6));
+        assert_eq!(out.get_pixel(0, LABEL_BAND), consts::RED);
+        assert_eq!(out.get_pixel(4, LABEL_BAND), consts::BLACK);
+        assert_eq!(out.get_pixel(6, LABEL_BAND + 5), consts::BLUE);
+        // Below the shorter image
+        assert_eq!(out.get_pixel(0, LABEL_BAND + 5), consts::BLACK);
+        // The top of the `A`
+        assert_eq!(out.get_pixel(MARGIN + 1, MARGIN), consts::WHITE);
+    }
+
+    #[test]
+    fn split_divides_the_images_at_the_position() {
+        let (a, b) = (filled(20, 10, consts::RED), filled(20, 10, consts::BLUE));
+        let out = compose(&a, &b, Layout::Split(0.25));
+        assert_eq!((out.get_width(), out.get_height()), (20, 10));
+        assert_eq!(out.get_pixel(4, 9), consts::RED);
+        assert_eq!(out.get_pixel(5, 9), consts::WHITE);
+        assert_eq!(out.get_pixel(6, 9), consts::BLUE);
+
+        let out = compose(&a, &b, Layout::Split(1.5));
+        assert_eq!(out.get_pixel(19, 9), consts::RED);
+    }
+}
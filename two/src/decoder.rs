@@ -3,12 +3,14 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use std::convert::{AsRef, From};
 use std::fmt::{self, Debug};
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::OnceLock;
 
-const BMP_HEADER_SIZE: u64 = 14;
+const BMP_HEADER_SIZE: u64 = wire::FILE_HEADER_SIZE;
 const BITS: usize = 8;
 
 use self::BmpErrorKind::*;
 use super::*;
+use crate::pack::Layout;
 
 pub type BmpResult<T> = Result<T, BmpError>;
 
@@ -19,7 +21,7 @@ pub struct BmpError {
 }
 
 impl BmpError {
-    fn new<T: AsRef<str>>(kind: BmpErrorKind, details: T) -> BmpError {
+    pub(crate) fn new<T: AsRef<str>>(kind: BmpErrorKind, details: T) -> BmpError {
         BmpError {
             kind,
             details: String::from(details.as_ref()),
@@ -52,6 +54,13 @@ pub enum BmpErrorKind {
     UnsupportedCompressionType,
     UnsupportedBmpVersion,
     UnsupportedHeader,
+    DimensionMismatch,
+    LimitsExceeded,
+    PaletteIndexOutOfRange,
+    InconsistentHeader,
+    EmbeddedJpeg,
+    EmbeddedPng,
+    Cancelled,
     BmpIoError(io::Error),
 }
 
@@ -63,88 +72,667 @@ impl AsRef<str> for BmpErrorKind {
             UnsupportedCompressionType => "Unsupported compression type",
             UnsupportedBmpVersion => "Unsupported bmp version",
             UnsupportedHeader => "Unsupported header",
+            DimensionMismatch => "Dimension mismatch",
+            LimitsExceeded => "Limits exceeded",
+            PaletteIndexOutOfRange => "Palette index out of range",
+            InconsistentHeader => "Inconsistent header",
+            EmbeddedJpeg => "Embedded JPEG stream",
+            EmbeddedPng => "Embedded PNG stream",
+            Cancelled => "Cancelled",
             _ => "BMP Error",
         }
     }
 }
 
-pub fn decode_image(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<Image> {
-    read_bmp_id(bmp_data)?;
-    let header = read_bmp_header(bmp_data)?;
-    let dib_header = read_bmp_dib_header(bmp_data)?;
-    let color_palette = read_color_palette(bmp_data, &dib_header)?;
+/// A problem with a file that was worked around while decoding it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BmpWarning {
+    /// The pixel data ended early, the rows it lacked were filled with black.
+    TruncatedPixelData { missing_rows: u32 },
+    /// The file size in the header differs from the size of the file.
+    WrongFileSize { declared: u32, actual: u64 },
+    /// The palette holds fewer colors than declared before the pixel data
+    /// starts. The missing ones were taken from the pixel data, as is.
+    ShortPalette { declared: usize, available: usize },
+    /// The reserved fields of the file header are not zero.
+    NonzeroReserved { creator1: u16, creator2: u16 },
+    /// The horizontal and vertical resolutions differ by more than 1%, so the
+    /// image looks stretched unless resampled to square pixels.
+    NonSquarePixels { hres: i32, vres: i32 },
+    /// The number of color planes is not 1. It is ignored.
+    WrongPlanes { planes: u16 },
+    /// The size of the uncompressed pixel data in the header is neither zero
+    /// nor the size of the rows. It is ignored.
+    WrongDataSize { declared: u32, expected: u64 },
+}
+
+impl fmt::Display for BmpWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BmpWarning::TruncatedPixelData { missing_rows } => {
+                write!(f, "Truncated pixel data:{} rows are missing", missing_rows)
+            }
+            BmpWarning::WrongFileSize { declared, actual } => write!(
+                f,
+                "Wrong file size:{} bytes declared, but the file has {}",
+                declared, actual
+            ),
+            BmpWarning::ShortPalette {
+                declared,
+                available,
+            } => write!(
+                f,
+                "Short palette:{} colors declared, but only {} fit before the pixels",
+                declared, available
+            ),
+            BmpWarning::NonzeroReserved { creator1, creator2 } => {
+                write!(f, "Nonzero reserved fields:{} and {}", creator1, creator2)
+            }
+            BmpWarning::NonSquarePixels { hres, vres } => {
+                write!(f, "Non-square pixels:{} by {} pixels per meter", hres, vres)
+            }
+            BmpWarning::WrongPlanes { planes } => {
+                write!(f, "Wrong number of planes:{} instead of 1", planes)
+            }
+            BmpWarning::WrongDataSize { declared, expected } => write!(
+                f,
+                "Wrong pixel data size:{} bytes declared, but the rows take {}",
+                declared, expected
+            ),
+        }
+    }
+}
+
+/// Callbacks of a running `decode_image`.
+#[derive(Default)]
+pub struct Hooks<'a> {
+    /// Called with the rows decoded and the rows in all, see `Decoder::on_progress`.
+    pub progress: Option<&'a mut dyn FnMut(u32, u32)>,
+    /// Checked before every row, see `Decoder::cancel_with`.
+    pub cancel: Option<&'a CancellationToken>,
+}
+
+/// The number of rows between two calls of a progress hook.
+pub const PROGRESS_ROWS: u32 = 64;
+
+pub fn decode_image(
+    bmp_data: &mut Cursor<&[u8]>,
+    options: DecodeOptions,
+    warnings: &mut Vec<BmpWarning>,
+    stats: Option<&mut perf::Stats>,
+    mut hooks: Hooks<'_>,
+) -> BmpResult<Image> {
+    let mut timer = perf::Timer::new(stats);
+    let (header, dib_header, color_palette) = decode_headers(bmp_data, options.limits)?;
+    check_headers(
+        bmp_data.get_ref(),
+        &header,
+        &dib_header,
+        &color_palette,
+        warnings,
+    );
+    timer.lap(perf::Stage::Headers);
 
     let width = dib_header.width.unsigned_abs();
     let height = dib_header.height.unsigned_abs();
     let padding = width % 4;
 
-    let data = match color_palette {
-        Some(ref palette) => read_indexes(
-            bmp_data.get_mut(),
-            palette,
-            width as usize,
-            height as usize,
-            dib_header.bits_per_pixel,
-            header.pixel_offset as usize,
-        )?,
-        None => read_pixels(bmp_data, width, height, header.pixel_offset, padding as i64)?,
+    let (present, rows_read) = present_rows(bmp_data, &header, &dib_header, options, warnings);
+
+    // Without limits, the number of pixels may not fit in memory addresses
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| {
+            BmpError::new(
+                LimitsExceeded,
+                format!("The image is {}x{}, too large to address", width, height),
+            )
+        })?;
+    // Uncompressed bytes hold at most 8 pixels, compressed ones grow the pixels
+    // only as far as they decode
+    let mut data = Vec::with_capacity(len.min(bmp_data.get_ref().len().saturating_mul(8)));
+    let cancelled = |rows: u32| match hooks.cancel {
+        Some(token) if token.is_cancelled() => Err(BmpError::new(
+            Cancelled,
+            format!("Decoding was cancelled after {} of {} rows", rows, height),
+        )),
+        _ => Ok(()),
+    };
+    cancelled(0)?;
+    let mut on_row = |rows: u32| {
+        if let Some(progress) = hooks.progress.as_mut() {
+            if rows.is_multiple_of(PROGRESS_ROWS) || rows == height {
+                progress(rows, height);
+            }
+        }
+        cancelled(rows)
+    };
+    decode_pixels_with(
+        bmp_data,
+        &header,
+        &present,
+        color_palette.as_deref(),
+        |px| data.push(px),
+        &mut on_row,
+    )?;
+    if rows_read < height {
+        data.resize(len, px!(0, 0, 0));
+        on_row(height)?;
+    }
+    // Bottom-up files are flipped anyway, so a flip asked for undoes it
+    let flip = dib_header.is_bottom_up() != options.flip_vertical;
+    if flip {
+        flip_rows(&mut data, width as usize);
+    }
+    timer.lap(perf::Stage::Pixels);
+
+    let indices = match color_palette {
+        Some(_) if options.keep_indices => {
+            let mut indices = read_raw_indexes(bmp_data.get_ref(), &header, &present)?;
+            indices.resize(len, 0);
+            if flip {
+                flip_rows(&mut indices, width as usize);
+            }
+            Some(indices)
+        }
+        _ => None,
     };
 
+    let mut alpha = read_alpha(bmp_data, &header, &present, options.alpha)?;
+    if let Some(ref mut alpha) = alpha {
+        alpha.resize(len, 255);
+        if flip {
+            flip_rows(alpha, width as usize);
+        }
+    }
+    timer.lap(perf::Stage::Alpha);
+
+    let color_space = read_color_space(bmp_data, &dib_header)?;
+    let icc_profile = read_icc_profile(bmp_data, &dib_header)?;
+    timer.lap(perf::Stage::ColorProfile);
     let image = Image {
         header,
-        dib_header: BmpDibHeader::new(width as i32, height as i32),
+        dib_header,
         color_palette,
+        color_space,
+        icc_profile,
         width,
         height,
         padding,
         data,
+        alpha,
+        indices,
+        history: None,
+        source_headers: None,
     };
 
     Ok(image)
 }
 
-fn read_bmp_id(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<()> {
-    let mut bm = [0, 0];
-    bmp_data.read_exact(&mut bm)?;
+/// Decodes the pixels straight into `options.layout`, row by row from the top,
+/// without building an `Image` first.
+pub fn decode_packed(
+    bmp_data: &mut Cursor<&[u8]>,
+    options: DecodeOptions,
+    warnings: &mut Vec<BmpWarning>,
+) -> BmpResult<(Vec<u8>, ImageInfo)> {
+    let (header, dib_header, color_palette) = decode_headers(bmp_data, options.limits)?;
+    check_headers(
+        bmp_data.get_ref(),
+        &header,
+        &dib_header,
+        &color_palette,
+        warnings,
+    );
+    let width = dib_header.width.unsigned_abs();
+    let height = dib_header.height.unsigned_abs();
+    let (present, rows_read) = present_rows(bmp_data, &header, &dib_header, options, warnings);
 
-    if bm == b"BM"[..] {
-        Ok(())
-    } else {
-        Err(BmpError::new(
-            WrongMagicNumbers,
-            format!("Expacted [66, 77], but was {:?}", bm),
-        ))
+    let alpha = match options.layout {
+        Layout::Rgb8 => None,
+        _ => read_alpha(bmp_data, &header, &present, options.alpha)?,
+    };
+    let layout = options.layout;
+    let n = layout.bytes_per_pixel();
+    let (w, h) = (width as usize, height as usize);
+    let mut out = vec![0; w * h * n];
+    // Pixels come in file order, so each is written straight to its row
+    let mut pack = |i: usize, px: Pixel, a: u8| {
+        let (row, x) = (i / w, i % w);
+        let y = match dib_header.is_bottom_up() {
+            true => h - 1 - row,
+            false => row,
+        };
+        let at = (y * w + x) * n;
+        layout.pack(px, a, &mut out[at..at + n]);
+    };
+
+    let mut i = 0;
+    decode_pixels(
+        bmp_data,
+        &header,
+        &present,
+        color_palette.as_deref(),
+        |px| {
+            let a = alpha.as_ref().map_or(255, |alpha| alpha[i]);
+            pack(i, px, a);
+            i += 1;
+        },
+    )?;
+    for i in rows_read as usize * w..w * h {
+        pack(i, px!(0, 0, 0), 255);
     }
+    Ok((out, ImageInfo { width, height }))
 }
 
-fn read_bmp_header(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<BmpHeader> {
-    let header = BmpHeader {
-        file_size: bmp_data.read_u32::<LittleEndian>()?,
-        creator1: bmp_data.read_u16::<LittleEndian>()?,
-        creator2: bmp_data.read_u16::<LittleEndian>()?,
-        pixel_offset: bmp_data.read_u32::<LittleEndian>()?,
+/// Returns the header of the rows present in the file and their number. A
+/// short pixel array, or one starting past the end of the file, only has its
+/// complete rows read if `options` allow it. The rows that are missing should
+/// be made black once decoded.
+fn present_rows(
+    bmp_data: &Cursor<&[u8]>,
+    header: &BmpHeader,
+    dib_header: &BmpDibHeader,
+    options: DecodeOptions,
+    warnings: &mut Vec<BmpWarning>,
+) -> (BmpDibHeader, u32) {
+    let width = dib_header.width.unsigned_abs();
+    let height = dib_header.height.unsigned_abs();
+    let row = row_size(width, dib_header.bits_per_pixel) as u64;
+    let available = (bmp_data.get_ref().len() as u64).saturating_sub(header.pixel_offset as u64);
+    let truncated = options.allow_truncated
+        && !matches!(dib_header.compress_type, 1 | 2)
+        && !is_huffman(dib_header)
+        && available < row * height as u64;
+    if !truncated {
+        return (dib_header.clone(), height);
+    }
+
+    let rows_read = (available / row.max(1)) as u32;
+    warnings.push(BmpWarning::TruncatedPixelData {
+        missing_rows: height - rows_read,
+    });
+    // The rows read stay in file order, only their number changes
+    let present = BmpDibHeader {
+        height: match dib_header.height < 0 {
+            true => -(rows_read as i32),
+            false => rows_read as i32,
+        },
+        ..dib_header.clone()
     };
+    (present, rows_read)
+}
 
-    Ok(header)
+/// Reads everything up to the pixel data, failing if the image lies outside of `limits`.
+pub fn decode_headers(
+    bmp_data: &mut Cursor<&[u8]>,
+    limits: Limits,
+) -> BmpResult<(BmpHeader, BmpDibHeader, Option<Vec<Pixel>>)> {
+    let header = read_bmp_header(bmp_data)?;
+    let dib_header = read_bmp_dib_header(bmp_data)?;
+    check_limits(&dib_header, limits)?;
+    let color_palette = read_color_palette(bmp_data, &dib_header)?;
+    Ok((header, dib_header, color_palette))
+}
+
+/// Hands every pixel to `push`, row by row in file order, which is bottom-up
+/// unless the height is negative.
+pub fn decode_pixels<F: FnMut(Pixel)>(
+    bmp_data: &mut Cursor<&[u8]>,
+    header: &BmpHeader,
+    dib_header: &BmpDibHeader,
+    color_palette: Option<&[Pixel]>,
+    push: F,
+) -> BmpResult<()> {
+    decode_pixels_with(
+        bmp_data,
+        header,
+        dib_header,
+        color_palette,
+        push,
+        &mut |_| Ok(()),
+    )
+}
+
+/// Called with the number of rows decoded so far after each row. An error
+/// stops decoding.
+pub type RowHook<'a> = &'a mut dyn FnMut(u32) -> BmpResult<()>;
+
+/// Like `decode_pixels`, but also calls `on_row` after every row.
+pub fn decode_pixels_with<F: FnMut(Pixel)>(
+    bmp_data: &mut Cursor<&[u8]>,
+    header: &BmpHeader,
+    dib_header: &BmpDibHeader,
+    color_palette: Option<&[Pixel]>,
+    push: F,
+    on_row: RowHook<'_>,
+) -> BmpResult<()> {
+    let width = dib_header.width.unsigned_abs();
+    let height = dib_header.height.unsigned_abs();
+
+    match color_palette {
+        Some(palette) if is_huffman(dib_header) => {
+            let data = bmp_data.get_ref();
+            let indexes = huffman::decode(
+                data.get(header.pixel_offset as usize..).unwrap_or(&[]),
+                width as usize,
+                height as usize,
+            )?;
+            push_index_rows(&indexes, palette, width as usize, push, on_row)
+        }
+        Some(palette) if dib_header.compress_type == 1 || dib_header.compress_type == 2 => {
+            read_rle_rows(
+                bmp_data.get_ref(),
+                palette,
+                width as usize,
+                height as usize,
+                dib_header.bits_per_pixel,
+                header.pixel_offset as usize,
+                push,
+                on_row,
+            )
+        }
+        Some(palette) => read_indexes(
+            bmp_data.get_ref(),
+            palette,
+            width as usize,
+            height as usize,
+            dib_header.bits_per_pixel,
+            header.pixel_offset as usize,
+            push,
+            on_row,
+        ),
+        None if dib_header.bits_per_pixel == 16 || dib_header.compress_type == 3 => {
+            let masks = read_bitmasks(bmp_data, dib_header)?;
+            read_bitfield_pixels(
+                bmp_data,
+                width,
+                height,
+                dib_header.bits_per_pixel,
+                header.pixel_offset,
+                masks,
+                push,
+                on_row,
+            )
+        }
+        None => read_pixels(
+            bmp_data,
+            width,
+            height,
+            dib_header.bits_per_pixel,
+            header.pixel_offset,
+            push,
+            on_row,
+        ),
+    }
+}
+
+/// Records the fields that do not match the file, but do not keep it from being decoded.
+fn check_headers(
+    bmp_data: &[u8],
+    header: &BmpHeader,
+    dh: &BmpDibHeader,
+    palette: &Option<Vec<Pixel>>,
+    warnings: &mut Vec<BmpWarning>,
+) {
+    // Some writers leave the size at zero, which is allowed
+    let actual = bmp_data.len() as u64;
+    if header.file_size != 0 && header.file_size as u64 != actual {
+        warnings.push(BmpWarning::WrongFileSize {
+            declared: header.file_size,
+            actual,
+        });
+    }
+
+    if let Some(ref palette) = *palette {
+        let start = BMP_HEADER_SIZE + dh.header_size as u64;
+        let available = (header.pixel_offset as u64).saturating_sub(start) as usize / 4;
+        if available < palette.len() {
+            warnings.push(BmpWarning::ShortPalette {
+                declared: palette.len(),
+                available,
+            });
+        }
+    }
+
+    if header.creator1 != 0 || header.creator2 != 0 {
+        warnings.push(BmpWarning::NonzeroReserved {
+            creator1: header.creator1,
+            creator2: header.creator2,
+        });
+    }
+
+    // Unset resolutions say nothing about the shape of the pixels
+    let (hres, vres) = (dh.hres, dh.vres);
+    if hres > 0 && vres > 0 && (hres as f32 / vres as f32 - 1.0).abs() > 0.01 {
+        warnings.push(BmpWarning::NonSquarePixels { hres, vres });
+    }
+
+    if dh.num_planes != 1 {
+        warnings.push(BmpWarning::WrongPlanes {
+            planes: dh.num_planes,
+        });
+    }
+    // Compressed data is read up to its end marker, so its size does not matter either
+    if dh.data_size != 0 && matches!(dh.compress_type, 0 | 3) && !is_huffman(dh) {
+        let width = dh.width.unsigned_abs();
+        let expected = row_size(width, dh.bits_per_pixel) as u64 * dh.height.unsigned_abs() as u64;
+        if dh.data_size as u64 != expected {
+            warnings.push(BmpWarning::WrongDataSize {
+                declared: dh.data_size,
+                expected,
+            });
+        }
+    }
 }
 
-fn read_bmp_dib_header(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<BmpDibHeader> {
-    let dib_header = BmpDibHeader {
-        header_size: bmp_data.read_u32::<LittleEndian>()?,
-        width: bmp_data.read_i32::<LittleEndian>()?,
-        height: bmp_data.read_i32::<LittleEndian>()?,
-        num_planes: bmp_data.read_u16::<LittleEndian>()?,
-        bits_per_pixel: bmp_data.read_u16::<LittleEndian>()?,
-        compress_type: bmp_data.read_u32::<LittleEndian>()?,
-        data_size: bmp_data.read_u32::<LittleEndian>()?,
-        hres: bmp_data.read_i32::<LittleEndian>()?,
-        vres: bmp_data.read_i32::<LittleEndian>()?,
-        num_colors: bmp_data.read_u32::<LittleEndian>()?,
-        num_imp_colors: bmp_data.read_u32::<LittleEndian>()?,
+/// Rejects everything about the headers that the lenient decoder would guess
+/// around: sizes that do not add up, overlapping parts and data beyond the end
+/// of the file. All sizes are computed with checked arithmetic.
+///
+/// The number of planes must be 1, although decoding never uses it. The size
+/// of uncompressed pixel data is not checked, `check_headers` warns about it.
+pub fn check_untrusted(
+    bmp_data: &[u8],
+    header: &BmpHeader,
+    dh: &BmpDibHeader,
+    palette: Option<&[Pixel]>,
+) -> BmpResult<()> {
+    let inconsistent = |details: String| Err(BmpError::new(InconsistentHeader, details));
+    let overflow = || BmpError::new(InconsistentHeader, "The pixel data size overflows");
+    let len = bmp_data.len() as u64;
+
+    if header.file_size != 0 && header.file_size as u64 != len {
+        return inconsistent(format!(
+            "The file has {} bytes, but {} are declared",
+            len, header.file_size
+        ));
+    }
+    if dh.num_planes != 1 {
+        return inconsistent(format!("{} planes instead of 1", dh.num_planes));
+    }
+    if dh.width <= 0 || dh.height == 0 {
+        return inconsistent(format!("The image is {}x{}", dh.width, dh.height));
+    }
+    if dh.bits_per_pixel <= 8 && dh.num_colors as u64 > 1 << dh.bits_per_pixel {
+        return inconsistent(format!(
+            "{} colors for {} bits per pixel",
+            dh.num_colors, dh.bits_per_pixel
+        ));
+    }
+    let max_colors = match dh.bits_per_pixel {
+        bpp @ 1..=8 => 1 << bpp,
+        _ => dh.num_colors as u64,
+    };
+    if dh.num_imp_colors as u64 > max_colors {
+        return inconsistent(format!(
+            "{} important colors of at most {}",
+            dh.num_imp_colors, max_colors
+        ));
+    }
+
+    // The masks of version 3 headers follow them
+    let masks = match (dh.compress_type, dh.header_size) {
+        (3, 40) => 12,
+        _ => 0,
     };
+    let colors = palette.map_or(0, |p| p.len() as u64);
+    let headers_end = BMP_HEADER_SIZE + dh.header_size as u64 + masks + colors * 4;
+    let offset = header.pixel_offset as u64;
+    if offset < headers_end {
+        return inconsistent(format!(
+            "The pixels start at {}, before the headers end at {}",
+            offset, headers_end
+        ));
+    }
+
+    if dh.bits_per_pixel == 16 && dh.compress_type == 3 {
+        let masks = read_bitmasks(&mut Cursor::new(bmp_data), dh)?;
+        if masks.iter().any(|&mask| mask > 0xFFFF) {
+            return inconsistent(format!("The masks {:?} exceed 16 bits", masks));
+        }
+    }
+
+    let size = match dh.compress_type {
+        // The size of compressed data is only known from the header
+        1 | 2 => dh.data_size as u64,
+        3 if is_huffman(dh) => dh.data_size as u64,
+        _ => (dh.width.unsigned_abs() as u64)
+            .checked_mul(dh.bits_per_pixel as u64)
+            .map(|bits| bits.div_ceil(32) * 4)
+            .and_then(|row| row.checked_mul(dh.height.unsigned_abs() as u64))
+            .ok_or_else(overflow)?,
+    };
+    let end = offset.checked_add(size).ok_or_else(overflow)?;
+    if end > len {
+        return inconsistent(format!(
+            "The pixel data ends at {}, after the end of the file at {}",
+            end, len
+        ));
+    }
+
+    // Runs must stay within the image
+    if let 1 | 2 = dh.compress_type {
+        let (width, height) = (dh.width as usize, dh.height.unsigned_abs() as usize);
+        rle_indexes(
+            bmp_data,
+            width,
+            height,
+            dh.bits_per_pixel,
+            offset as usize,
+            true,
+        )?;
+    }
+    if is_huffman(dh) {
+        let (width, height) = (dh.width as usize, dh.height.unsigned_abs() as usize);
+        huffman::decode(&bmp_data[offset as usize..], width, height)?;
+    }
+    Ok(())
+}
+
+pub fn check_limits(dh: &BmpDibHeader, limits: Limits) -> BmpResult<()> {
+    let width = dh.width.unsigned_abs();
+    let height = dh.height.unsigned_abs();
+    let pixel_bytes = width as u64 * height as u64 * 4;
+    if width > limits.max_width || height > limits.max_height {
+        return Err(BmpError::new(
+            LimitsExceeded,
+            format!(
+                "The image is {}x{}, larger than the limit of {}x{}",
+                width, height, limits.max_width, limits.max_height
+            ),
+        ));
+    }
+    if pixel_bytes > limits.max_pixel_bytes {
+        return Err(BmpError::new(
+            LimitsExceeded,
+            format!(
+                "The pixels would take {} bytes, more than the limit of {}",
+                pixel_bytes, limits.max_pixel_bytes
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the JPEG or PNG file making up the pixel data, if there is one.
+pub fn embedded_stream(bmp_data: &[u8]) -> BmpResult<Option<EmbeddedStream<'_>>> {
+    let mut cursor = Cursor::new(bmp_data);
+    let header = read_bmp_header(&mut cursor)?;
+    let dib_header = wire::read_dib_header(&mut cursor)?;
+    let format = match CompressionType::from_u32(dib_header.compress_type) {
+        CompressionType::Jpeg => EmbeddedFormat::Jpeg,
+        CompressionType::Png => EmbeddedFormat::Png,
+        _ => return Ok(None),
+    };
+
+    let start = header.pixel_offset as usize;
+    if start > bmp_data.len() {
+        return Err(BmpError::new(
+            UnsupportedHeader,
+            format!("The embedded file cannot start at {}", start),
+        ));
+    }
+    // The size may be left at zero, the file then runs to the end
+    let end = match dib_header.data_size as usize {
+        0 => bmp_data.len(),
+        size => start.saturating_add(size).min(bmp_data.len()),
+    };
+    Ok(Some(EmbeddedStream {
+        format,
+        data: &bmp_data[start..end],
+    }))
+}
+
+fn read_bmp_header(bmp_data: &mut Cursor<&[u8]>) -> BmpResult<BmpHeader> {
+    let (magic, header) = wire::read_file_header(bmp_data)?;
+    if magic != wire::MAGIC {
+        return Err(BmpError::new(
+            WrongMagicNumbers,
+            format!("Expacted [66, 77], but was {:?}", magic),
+        ));
+    }
 
-    match BmpVersion::from_dib_header(&dib_header) {
-        Some(BmpVersion::Three) | Some(BmpVersion::Four) | Some(BmpVersion::Five) => (),
+    Ok(header)
+}
+
+fn read_bmp_dib_header(bmp_data: &mut Cursor<&[u8]>) -> BmpResult<BmpDibHeader> {
+    let dib_header = wire::read_dib_header(bmp_data)?;
+    check_dib_header(&dib_header)?;
+    Ok(dib_header)
+}
+
+/// Checks that the version, compression and bit depth of the header are ones
+/// the decoder supports.
+pub fn check_dib_header(dib_header: &BmpDibHeader) -> BmpResult<()> {
+    match BmpVersion::from_dib_header(dib_header) {
+        Some(BmpVersion::Three)
+        | Some(BmpVersion::ThreeNT)
+        | Some(BmpVersion::Four)
+        | Some(BmpVersion::Five) => (),
+        // Its first 40 bytes match version 3, but compression types 3 and 4
+        // stand for Huffman and 24-bit RLE instead
+        Some(BmpVersion::Os2Two) if dib_header.compress_type < 3 => (),
+        Some(BmpVersion::Os2Two) if is_huffman(dib_header) => {
+            if dib_header.bits_per_pixel != 1 {
+                return Err(BmpError::new(
+                    UnsupportedCompressionType,
+                    format!(
+                        "Huffman 1D compression requires 1 bpp, was: {}",
+                        dib_header.bits_per_pixel
+                    ),
+                ));
+            }
+        }
+        Some(BmpVersion::Os2Two) => {
+            return Err(BmpError::new(
+                UnsupportedCompressionType,
+                format!(
+                    "OS/2 compression type {} is not supported",
+                    dib_header.compress_type
+                ),
+            ))
+        }
         Some(other) => return Err(BmpError::new(UnsupportedBmpVersion, other)),
         None => {
             return Err(BmpError::new(
@@ -158,13 +746,37 @@ fn read_bmp_dib_header(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<BmpDibHeader
         }
     }
 
+    if dib_header.compress_type > 5 {
+        return Err(BmpError::new(
+            UnsupportedCompressionType,
+            format!("Unknown compression type {}", dib_header.compress_type),
+        ));
+    }
+
+    // The bit depth of embedded files is usually zero, so they are told apart first
+    match CompressionType::from_u32(dib_header.compress_type) {
+        CompressionType::Jpeg => {
+            return Err(BmpError::new(
+                EmbeddedJpeg,
+                "The pixel data is a JPEG file, see Image::embedded_stream",
+            ))
+        }
+        CompressionType::Png => {
+            return Err(BmpError::new(
+                EmbeddedPng,
+                "The pixel data is a PNG file, see Image::embedded_stream",
+            ))
+        }
+        _ => (),
+    }
+
     match dib_header.bits_per_pixel {
-        1 | 4 | 8 | 24 => (),
+        1 | 2 | 4 | 8 | 16 | 24 | 32 | 64 => (),
         other => {
             return Err(BmpError::new(
                 UnsupportedBitsPerPixel,
                 format!(
-                    "Only 1, 4, 8, and 24 bits per pixel are currently supported, was: {}",
+                    "Only 1, 2, 4, 8, 16, 24, 32 and 64 bits per pixel are supported, was: {}",
                     other
                 ),
             ))
@@ -172,21 +784,36 @@ fn read_bmp_dib_header(bmp_data: &mut Cursor<Vec<u8>>) -> BmpResult<BmpDibHeader
     }
 
     match CompressionType::from_u32(dib_header.compress_type) {
+        _ if is_huffman(dib_header) => (),
         CompressionType::Uncompressed => (),
+        CompressionType::BitfieldsEncoding if matches!(dib_header.bits_per_pixel, 16 | 32) => (),
+        CompressionType::Rle8bit if dib_header.bits_per_pixel == 8 => (),
+        CompressionType::Rle4bit if dib_header.bits_per_pixel == 4 => (),
         other => return Err(BmpError::new(UnsupportedCompressionType, other)),
     }
 
-    Ok(dib_header)
+    // Deltas in run-length encoded data can only move upwards
+    if dib_header.height < 0 && matches!(dib_header.compress_type, 1 | 2) {
+        return Err(BmpError::new(
+            UnsupportedHeader,
+            "Run-length encoded images cannot be stored top-down",
+        ));
+    }
+
+    Ok(())
 }
 
 fn read_color_palette(
-    bmp_data: &mut Cursor<Vec<u8>>,
+    bmp_data: &mut Cursor<&[u8]>,
     dh: &BmpDibHeader,
 ) -> BmpResult<Option<Vec<Pixel>>> {
     let num_entries = match dh.bits_per_pixel {
-        // We have a color_palette if the num_colors in the dib header is not zero {
-        _ if dh.num_colors != 0 => dh.num_colors as usize,
-        bpp @ 1 | bpp @ 4 | bpp @ 8 => 1 << bpp,
+        // Larger pixels hold their colors, any palette is only a hint for old displays
+        bpp if bpp > 8 => return Ok(None),
+        // The palette may be smaller than the 2^bpp colors the pixels can index,
+        // but never needs to be larger
+        bpp @ (1 | 2 | 4 | 8) if dh.num_colors != 0 => (dh.num_colors as usize).min(1 << bpp),
+        bpp @ (1 | 2 | 4 | 8) => 1 << bpp,
         _ => return Ok(None),
     };
 
@@ -207,55 +834,698 @@ fn read_color_palette(
     Ok(Some(color_palette))
 }
 
-fn read_indexes(
-    bmp_data: &mut [u8],
+pub fn read_color_space(
+    bmp_data: &mut Cursor<&[u8]>,
+    dh: &BmpDibHeader,
+) -> BmpResult<Option<ColorSpaceFields>> {
+    // Only version 4 headers and newer describe their color space
+    if dh.header_size < 108 {
+        return Ok(None);
+    }
+
+    bmp_data.seek(SeekFrom::Start(BMP_HEADER_SIZE + 56))?;
+    let cs_type = bmp_data.read_u32::<LittleEndian>()?;
+    let mut endpoints = [0; 9];
+    for val in &mut endpoints {
+        *val = bmp_data.read_i32::<LittleEndian>()?;
+    }
+    let mut gamma = [0; 3];
+    for val in &mut gamma {
+        *val = bmp_data.read_u32::<LittleEndian>()?;
+    }
+
+    Ok(Some(ColorSpaceFields {
+        cs_type,
+        endpoints,
+        gamma,
+    }))
+}
+
+fn read_icc_profile(
+    bmp_data: &mut Cursor<&[u8]>,
+    dh: &BmpDibHeader,
+) -> BmpResult<Option<IccProfile>> {
+    // Only version 5 headers have the profile fields
+    if dh.header_size < 124 {
+        return Ok(None);
+    }
+
+    bmp_data.seek(SeekFrom::Start(BMP_HEADER_SIZE + 56))?;
+    let cs_type = bmp_data.read_u32::<LittleEndian>()?;
+    bmp_data.seek(SeekFrom::Start(BMP_HEADER_SIZE + 112))?;
+    let offset = bmp_data.read_u32::<LittleEndian>()? as u64;
+    let size = bmp_data.read_u32::<LittleEndian>()? as u64;
+    if size == 0 || !matches!(cs_type, PROFILE_EMBEDDED | PROFILE_LINKED) {
+        return Ok(None);
+    }
+
+    // The offset counts from the start of the DIB header
+    let start = BMP_HEADER_SIZE + offset;
+    if start + size > bmp_data.get_ref().len() as u64 {
+        return Err(BmpError::new(
+            UnsupportedHeader,
+            format!(
+                "The {} byte color profile at {} lies outside of the file",
+                size, start
+            ),
+        ));
+    }
+    let mut data = vec![0; size as usize];
+    bmp_data.seek(SeekFrom::Start(start))?;
+    bmp_data.read_exact(&mut data)?;
+
+    if cs_type == PROFILE_EMBEDDED {
+        return Ok(Some(IccProfile::Embedded(data)));
+    }
+    // The file name is terminated by a zero
+    if let Some(end) = data.iter().position(|&b| b == 0) {
+        data.truncate(end);
+    }
+    Ok(Some(IccProfile::Linked(data)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_indexes<F: FnMut(Pixel)>(
+    bmp_data: &[u8],
     palette: &[Pixel],
     width: usize,
     height: usize,
     bpp: u16,
     offset: usize,
-) -> BmpResult<Vec<Pixel>> {
-    let mut data = Vec::with_capacity(height * width);
+    mut push: F,
+    on_row: RowHook<'_>,
+) -> BmpResult<()> {
     // Number of bytes to read from each row, varies based on bits_per_pixel
-    let bytes_per_row = (width as f64 / (8.0 / bpp as f64)).ceil() as usize;
+    let bytes_per_row = (width * bpp as usize).div_ceil(8);
+    let stride = row_size(width as u32, bpp);
     for y in 0..height {
-        let padding = match bytes_per_row % 4 {
-            0 => 0,
-            other => 4 - other,
+        let bytes = index_row(bmp_data, offset + stride * y, bytes_per_row)?;
+        for i in bit_index(bytes, bpp as usize, width) {
+            push(palette_color(palette, i)?);
+        }
+        on_row(y as u32 + 1)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the `len` bytes of a row of indexes starting at `start`, an error
+/// if the file ends before them.
+#[inline]
+fn index_row(bmp_data: &[u8], start: usize, len: usize) -> BmpResult<&[u8]> {
+    bmp_data
+        .get(start..start.saturating_add(len))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("The row at {} lies outside of the file", start),
+            )
+            .into()
+        })
+}
+
+/// Whether the pixel data is Huffman 1D encoded, which OS/2 2.x headers
+/// declare with the compression type that means bitfields elsewhere.
+pub fn is_huffman(dh: &BmpDibHeader) -> bool {
+    dh.compress_type == 3 && matches!(BmpVersion::from_dib_header(dh), Some(BmpVersion::Os2Two))
+}
+
+/// Returns color `i` of the palette, an error if the palette is smaller.
+#[inline]
+pub(crate) fn palette_color(palette: &[Pixel], i: usize) -> BmpResult<Pixel> {
+    palette.get(i).copied().ok_or_else(|| {
+        BmpError::new(
+            PaletteIndexOutOfRange,
+            format!("Index {} of a palette of {} colors", i, palette.len()),
+        )
+    })
+}
+
+/// Expands run-length encoded 8 or 4 bpp indexes.
+///
+/// Pixels skipped by delta codes or early ends of lines get the first palette color.
+pub fn read_rle<F: FnMut(Pixel)>(
+    bmp_data: &[u8],
+    palette: &[Pixel],
+    width: usize,
+    height: usize,
+    bpp: u16,
+    offset: usize,
+    push: F,
+) -> BmpResult<()> {
+    read_rle_rows(
+        bmp_data,
+        palette,
+        width,
+        height,
+        bpp,
+        offset,
+        push,
+        &mut |_| Ok(()),
+    )
+}
+
+/// Expands Huffman 1D encoded 1 bpp indexes.
+///
+/// Pixels after an early end of line get the first palette color.
+pub fn read_huffman<F: FnMut(Pixel)>(
+    bmp_data: &[u8],
+    palette: &[Pixel],
+    width: usize,
+    height: usize,
+    offset: usize,
+    push: F,
+) -> BmpResult<()> {
+    let indexes = huffman::decode(bmp_data.get(offset..).unwrap_or(&[]), width, height)?;
+    push_index_rows(&indexes, palette, width, push, &mut |_| Ok(()))
+}
+
+/// Like `read_rle`, but also calls `on_row` after every row.
+#[allow(clippy::too_many_arguments)]
+fn read_rle_rows<F: FnMut(Pixel)>(
+    bmp_data: &[u8],
+    palette: &[Pixel],
+    width: usize,
+    height: usize,
+    bpp: u16,
+    offset: usize,
+    push: F,
+    on_row: RowHook<'_>,
+) -> BmpResult<()> {
+    let indexes = rle_indexes(bmp_data, width, height, bpp, offset, false)?;
+    push_index_rows(&indexes, palette, width, push, on_row)
+}
+
+/// Looks up the colors of decompressed indexes, calling `on_row` after every row.
+///
+/// Indexes beyond the palette are an error, as in uncompressed rows.
+fn push_index_rows<F: FnMut(Pixel)>(
+    indexes: &[u8],
+    palette: &[Pixel],
+    width: usize,
+    mut push: F,
+    on_row: RowHook<'_>,
+) -> BmpResult<()> {
+    for (y, row) in indexes.chunks(width.max(1)).enumerate() {
+        for &i in row {
+            push(palette_color(palette, i as usize)?);
+        }
+        on_row(y as u32 + 1)?;
+    }
+    Ok(())
+}
+
+/// Returns the palette indexes of every pixel of an image, in file order.
+pub fn read_raw_indexes(
+    bmp_data: &[u8],
+    header: &BmpHeader,
+    dh: &BmpDibHeader,
+) -> BmpResult<Vec<u8>> {
+    let width = dh.width.unsigned_abs() as usize;
+    let height = dh.height.unsigned_abs() as usize;
+    let offset = header.pixel_offset as usize;
+    if is_huffman(dh) {
+        return huffman::decode(bmp_data.get(offset..).unwrap_or(&[]), width, height);
+    }
+    if let 1 | 2 = dh.compress_type {
+        return rle_indexes(bmp_data, width, height, dh.bits_per_pixel, offset, false);
+    }
+
+    let bpp = dh.bits_per_pixel;
+    let bytes_per_row = (width * bpp as usize).div_ceil(8);
+    let stride = row_size(width as u32, bpp);
+    let mut indexes = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let bytes = index_row(bmp_data, offset + stride * y, bytes_per_row)?;
+        indexes.extend(bit_index(bytes, bpp as usize, width).map(|i| i as u8));
+    }
+    Ok(indexes)
+}
+
+/// Expands run-length encoded 8 or 4 bpp data into one index per pixel.
+///
+/// Indexes outside of the image are dropped, unless `strict` makes them an error.
+fn rle_indexes(
+    bmp_data: &[u8],
+    width: usize,
+    height: usize,
+    bpp: u16,
+    offset: usize,
+    strict: bool,
+) -> BmpResult<Vec<u8>> {
+    let mut indexes = vec![0u8; width * height];
+    let mut rle = Cursor::new(bmp_data.get(offset..).unwrap_or(&[]));
+    let mut rows = RleRows::new(width, height, bpp, strict);
+    for row in indexes.chunks_exact_mut(width.max(1)).take(height) {
+        rows.next_row(&mut rle, &mut row[..width])?;
+    }
+    Ok(indexes)
+}
+
+/// Expands run-length encoded 8 or 4 bpp data one row of indexes at a time,
+/// reading no further than the codes of that row.
+///
+/// Indexes outside of the image are dropped, unless `strict` makes them an error.
+pub(crate) struct RleRows {
+    width: usize,
+    height: usize,
+    bpp: u16,
+    strict: bool,
+    // Where the next index goes, as moved by the codes read so far
+    x: usize,
+    y: usize,
+    // The row `next_row` fills
+    row: usize,
+    ended: bool,
+}
+
+impl RleRows {
+    pub(crate) fn new(width: usize, height: usize, bpp: u16, strict: bool) -> RleRows {
+        RleRows {
+            width,
+            height,
+            bpp,
+            strict,
+            x: 0,
+            y: 0,
+            row: 0,
+            ended: false,
+        }
+    }
+
+    /// Fills `row`, which is `width` long, with the indexes of the next row in
+    /// file order. Pixels skipped by a delta or the end of the bitmap are 0.
+    pub(crate) fn next_row<R: Read>(&mut self, rle: &mut R, row: &mut [u8]) -> BmpResult<()> {
+        row.fill(0);
+        let (width, bpp, strict) = (self.width, self.bpp, self.strict);
+        let y = self.row;
+        self.row += 1;
+        let mut set = |x: usize, i: u8| {
+            if x < width {
+                row[x] = i;
+            } else if strict {
+                return Err(BmpError::new(
+                    InconsistentHeader,
+                    format!(
+                        "Run-length encoded pixel {},{} lies outside of the image",
+                        x, y
+                    ),
+                ));
+            }
+            Ok(())
         };
-        let start = offset + (bytes_per_row + padding) * y;
-        let bytes = &bmp_data[start..start + bytes_per_row];
 
-        for i in bit_index(bytes, bpp as usize, width) {
-            data.push(palette[i]);
+        while !self.ended && self.y == y {
+            let count = rle.read_u8()?;
+            let code = rle.read_u8()?;
+            match (count, code) {
+                // A run of one index, or of two alternating ones for 4 bpp
+                (1..=255, i) => {
+                    for n in 0..count {
+                        let i = match bpp {
+                            4 if n % 2 == 0 => i >> 4,
+                            4 => i & 0x0F,
+                            _ => i,
+                        };
+                        set(self.x, i)?;
+                        self.x += 1;
+                    }
+                }
+                // End of line
+                (0, 0) => {
+                    self.x = 0;
+                    self.y += 1;
+                }
+                // End of bitmap
+                (0, 1) => self.ended = true,
+                // Delta, moves right and up, as rows are stored bottom-up
+                (0, 2) => {
+                    self.x += rle.read_u8()? as usize;
+                    self.y += rle.read_u8()? as usize;
+                    if strict && (self.x > width || self.y > self.height) {
+                        return Err(BmpError::new(
+                            InconsistentHeader,
+                            format!(
+                                "A delta moves to {},{}, outside of the image",
+                                self.x, self.y
+                            ),
+                        ));
+                    }
+                }
+                // Absolute mode, indexes stored as-is and padded to an even number of bytes
+                (0, n) if bpp == 4 => {
+                    let mut pair = 0;
+                    for k in 0..n {
+                        if k % 2 == 0 {
+                            pair = rle.read_u8()?;
+                            set(self.x, pair >> 4)?;
+                        } else {
+                            set(self.x, pair & 0x0F)?;
+                        }
+                        self.x += 1;
+                    }
+                    if n.div_ceil(2) % 2 == 1 {
+                        rle.read_u8()?;
+                    }
+                }
+                (0, n) => {
+                    for _ in 0..n {
+                        set(self.x, rle.read_u8()?)?;
+                        self.x += 1;
+                    }
+                    if n % 2 == 1 {
+                        rle.read_u8()?;
+                    }
+                }
+            }
         }
+        Ok(())
     }
+}
 
-    Ok(data)
+fn read_pixels<F: FnMut(Pixel)>(
+    bmp_data: &mut Cursor<&[u8]>,
+    width: u32,
+    height: u32,
+    bpp: u16,
+    offset: u32,
+    mut push: F,
+    on_row: RowHook<'_>,
+) -> BmpResult<()> {
+    // Pixels are stored as BGR, 32 bpp adds a fourth byte which `read_alpha` handles
+    let bytes_per_pixel = bpp as usize / 8;
+    let mut buf = [0; 8];
+    let px = &mut buf[..bytes_per_pixel];
+    for y in 0..height {
+        seek_row(bmp_data, offset, width, bpp, y)?;
+        for _ in 0..width {
+            bmp_data.read_exact(px)?;
+            push(match bpp {
+                64 => wide_pixel(px),
+                _ => px!(px[2], px[1], px[0]),
+            });
+        }
+        on_row(y + 1)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the alpha channel of 16 and 32 bpp pixels in file order, if they have one.
+///
+/// Plain 32 bpp pixels have a fourth byte that should be unused, but is often
+/// alpha nonetheless. Like browsers do, it is only taken as alpha when it is
+/// not zero everywhere, unless `mode` says otherwise.
+fn read_alpha(
+    bmp_data: &mut Cursor<&[u8]>,
+    header: &BmpHeader,
+    dh: &BmpDibHeader,
+    mode: AlphaMode,
+) -> BmpResult<Option<Vec<u8>>> {
+    let mask = alpha_mask(bmp_data, dh)?;
+    if mask == 0 || mode == AlphaMode::Ignore {
+        return Ok(None);
+    }
+
+    let alpha = Channel::from_mask(mask);
+    let (width, height) = (dh.width.unsigned_abs(), dh.height.unsigned_abs());
+    let mut values = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        seek_row(bmp_data, header.pixel_offset, width, dh.bits_per_pixel, y)?;
+        for _ in 0..width {
+            let val = match dh.bits_per_pixel {
+                16 => bmp_data.read_u16::<LittleEndian>()? as u32,
+                64 => {
+                    let val = bmp_data.read_u64::<LittleEndian>()?;
+                    values.push(wide_alpha((val >> 48) as u16));
+                    continue;
+                }
+                _ => bmp_data.read_u32::<LittleEndian>()?,
+            };
+            values.push(alpha.extract(val));
+        }
+    }
+
+    if mode == AlphaMode::Auto && values.iter().all(|&a| a == 0) {
+        return Ok(None);
+    }
+    Ok(Some(values))
+}
+
+/// Returns the bits of 16 and 32 bpp pixels that may hold alpha, 0 if none do.
+///
+/// 64 bpp pixels always have alpha, in their last 16 bits, which `WIDE_ALPHA`
+/// stands for.
+pub fn alpha_mask(bmp_data: &mut Cursor<&[u8]>, dh: &BmpDibHeader) -> BmpResult<u32> {
+    let compression = CompressionType::from_u32(dh.compress_type);
+    Ok(match (dh.bits_per_pixel, compression) {
+        (32, CompressionType::Uncompressed) => 0xFF00_0000,
+        (64, CompressionType::Uncompressed) => WIDE_ALPHA,
+        (16, CompressionType::BitfieldsEncoding) | (32, CompressionType::BitfieldsEncoding) => {
+            read_bitmasks(bmp_data, dh)?[3]
+        }
+        _ => 0,
+    })
+}
+
+/// Returns the number of bytes of a row of pixels, including the padding to 4 bytes.
+#[inline]
+pub fn row_size(width: u32, bpp: u16) -> usize {
+    ((bpp as u64 * width as u64).div_ceil(32) * 4) as usize
+}
+
+/// Returns the size of the headers of a version 3 file without a palette and
+/// of its uncompressed pixel array, an error if the file would be larger than
+/// the 4 GiB its header can describe.
+pub fn file_size(bpp: u16, width: u32, height: u32) -> BmpResult<(u32, u32)> {
+    let head_size = BMP_HEADER_SIZE as u32 + wire::DIB_HEADER_SIZE;
+    // The row cannot overflow, being at most 64 times a u32
+    let row = (bpp as u64 * width as u64).div_ceil(32) * 4;
+    let data_size = row
+        .checked_mul(height as u64)
+        .filter(|&size| size + head_size as u64 <= u32::MAX as u64)
+        .ok_or_else(|| {
+            BmpError::new(
+                LimitsExceeded,
+                format!(
+                    "A {}x{} image of {} bpp does not fit into a file",
+                    width, height, bpp
+                ),
+            )
+        })?;
+    Ok((head_size, data_size as u32))
+}
+
+/// Moves to the start of row `y` in file order. Rows start at the pixel offset
+/// of the file header, which may leave a gap after the palette, and are
+/// `row_size` bytes apart.
+fn seek_row(
+    bmp_data: &mut Cursor<&[u8]>,
+    offset: u32,
+    width: u32,
+    bpp: u16,
+    y: u32,
+) -> BmpResult<()> {
+    let start = offset as u64 + y as u64 * row_size(width, bpp) as u64;
+    bmp_data.seek(SeekFrom::Start(start))?;
+    Ok(())
+}
+
+/// Hands the pixels of an uncompressed row to `push`.
+///
+/// `masks` are only used by 16 and 32 bpp pixels stored as bitfields. Indexes
+/// outside of the palette are an error.
+pub fn decode_row<F: FnMut(Pixel)>(
+    row: &[u8],
+    dh: &BmpDibHeader,
+    color_palette: Option<&[Pixel]>,
+    masks: [u32; 4],
+    mut push: F,
+) -> BmpResult<()> {
+    let width = dh.width.unsigned_abs() as usize;
+    let bpp = dh.bits_per_pixel;
+    match color_palette {
+        Some(palette) => {
+            for i in bit_index(row, bpp as usize, width) {
+                push(palette_color(palette, i)?);
+            }
+        }
+        None if bpp == 64 => {
+            for px in row.chunks_exact(8).take(width) {
+                push(wide_pixel(px));
+            }
+        }
+        None if bpp == 16 || dh.compress_type == 3 => {
+            let [r, g, b, _] = masks.map(Channel::from_mask);
+            for val in row_values(row, width, bpp) {
+                push(px!(r.extract(val), g.extract(val), b.extract(val)));
+            }
+        }
+        None => {
+            for px in row.chunks_exact(bpp as usize / 8).take(width) {
+                push(px!(px[2], px[1], px[0]));
+            }
+        }
+    }
+    Ok(())
 }
 
-fn read_pixels(
-    bmp_data: &mut Cursor<Vec<u8>>,
+/// Hands the alpha of the pixels of an uncompressed 16, 32 or 64 bpp row to `push`.
+pub fn decode_row_alpha<F: FnMut(u8)>(row: &[u8], dh: &BmpDibHeader, mask: u32, mut push: F) {
+    let alpha = Channel::from_mask(mask);
+    let width = dh.width.unsigned_abs() as usize;
+    if dh.bits_per_pixel == 64 {
+        for px in row.chunks_exact(8).take(width) {
+            push(wide_alpha(u16::from_le_bytes([px[6], px[7]])));
+        }
+        return;
+    }
+    for val in row_values(row, width, dh.bits_per_pixel) {
+        push(alpha.extract(val));
+    }
+}
+
+/// Returns the 16 or 32 bit little-endian values of the first `width` pixels of `row`.
+fn row_values(row: &[u8], width: usize, bpp: u16) -> impl Iterator<Item = u32> + '_ {
+    let bytes = bpp as usize / 8;
+    row.chunks_exact(bytes)
+        .take(width)
+        .map(move |px| match bytes {
+            2 => u16::from_le_bytes([px[0], px[1]]) as u32,
+            _ => u32::from_le_bytes([px[0], px[1], px[2], px[3]]),
+        })
+}
+
+/// Returns the red, green, blue and alpha masks of 16 or 32 bpp pixels.
+pub fn read_bitmasks(bmp_data: &mut Cursor<&[u8]>, dh: &BmpDibHeader) -> BmpResult<[u32; 4]> {
+    match CompressionType::from_u32(dh.compress_type) {
+        // Directly after the 40 bytes of a version 3 header, or within the newer ones
+        CompressionType::BitfieldsEncoding => {
+            bmp_data.seek(SeekFrom::Start(BMP_HEADER_SIZE + 40))?;
+            let mut masks = [0; 4];
+            for mask in &mut masks[..3] {
+                *mask = bmp_data.read_u32::<LittleEndian>()?;
+            }
+            // Only the newer headers have room for an alpha mask
+            if dh.header_size >= 56 {
+                masks[3] = bmp_data.read_u32::<LittleEndian>()?;
+            }
+            Ok(masks)
+        }
+        // 5-5-5 is the default layout of 16 bpp
+        _ => Ok([0x7C00, 0x03E0, 0x001F, 0]),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_bitfield_pixels<F: FnMut(Pixel)>(
+    bmp_data: &mut Cursor<&[u8]>,
     width: u32,
     height: u32,
+    bpp: u16,
     offset: u32,
-    padding: i64,
-) -> BmpResult<Vec<Pixel>> {
-    let mut data = Vec::with_capacity((height * width) as usize);
-    // seek until data
-    bmp_data.seek(SeekFrom::Start(offset as u64))?;
-    // read pixels until padding
-    let mut px = [0; 3];
-    for _ in 0..height {
+    masks: [u32; 4],
+    mut push: F,
+    on_row: RowHook<'_>,
+) -> BmpResult<()> {
+    // The alpha channel is read by `read_alpha`
+    let [r, g, b, _] = masks.map(Channel::from_mask);
+    for y in 0..height {
+        seek_row(bmp_data, offset, width, bpp, y)?;
         for _ in 0..width {
-            bmp_data.read_exact(&mut px)?;
-            data.push(px!(px[2], px[1], px[0]));
+            let val = match bpp {
+                16 => bmp_data.read_u16::<LittleEndian>()? as u32,
+                _ => bmp_data.read_u32::<LittleEndian>()?,
+            };
+            push(px!(r.extract(val), g.extract(val), b.extract(val)));
+        }
+        on_row(y + 1)?;
+    }
+
+    Ok(())
+}
+
+/// The alpha mask of 64 bpp pixels, whose alpha does not fit in 32 bits.
+pub const WIDE_ALPHA: u32 = 0xFFFF;
+
+/// The value of 1.0 in the channels of 64 bpp pixels, which GDI+ stores as
+/// signed fixed point numbers with 13 fractional bits.
+pub const WIDE_ONE: u16 = 1 << 13;
+
+/// Converts a 64 bpp pixel, stored as blue, green, red and alpha, to 8 bits per
+/// channel.
+#[inline]
+pub fn wide_pixel(px: &[u8]) -> Pixel {
+    let channel = |i: usize| wide_color(u16::from_le_bytes([px[i], px[i + 1]]));
+    px!(channel(4), channel(2), channel(0))
+}
+
+/// Converts a linear color channel of a 64 bpp pixel to 8-bit sRGB. Values
+/// outside of 0.0 to 1.0 are clamped.
+#[inline]
+pub fn wide_color(val: u16) -> u8 {
+    wide_color_with(val, ColorPolicy::default())
+}
+
+/// Like `wide_color`, converting the sRGB value to 8 bits with `policy`.
+pub fn wide_color_with(val: u16, policy: ColorPolicy) -> u8 {
+    static TO_SRGB: OnceLock<Vec<u8>> = OnceLock::new();
+    // Negative values have the sign bit set
+    let val = (val as i16).clamp(0, WIDE_ONE as i16) as u16;
+    let convert = |val: u16, policy: ColorPolicy| {
+        let linear = val as f32 / WIDE_ONE as f32;
+        let srgb = if linear <= 0.003_130_8 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        policy.unit_to_u8(srgb)
+    };
+    if policy != ColorPolicy::default() {
+        return convert(val, policy);
+    }
+    let table = TO_SRGB.get_or_init(|| {
+        (0..=WIDE_ONE)
+            .map(|val| convert(val, ColorPolicy::default()))
+            .collect()
+    });
+    table[val as usize]
+}
+
+/// Converts the alpha of a 64 bpp pixel to 8 bits. Alpha is not gamma encoded.
+#[inline]
+pub fn wide_alpha(val: u16) -> u8 {
+    let val = (val as i16).clamp(0, WIDE_ONE as i16) as u32;
+    ((val * 255 + WIDE_ONE as u32 / 2) / WIDE_ONE as u32) as u8
+}
+
+/// A color channel stored in the bits set in a mask.
+#[derive(Clone, Copy, Debug)]
+struct Channel {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl Channel {
+    fn from_mask(mask: u32) -> Channel {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Channel {
+            mask,
+            shift,
+            max: mask >> shift,
         }
-        // seek padding
-        bmp_data.seek(SeekFrom::Current(padding))?;
     }
 
-    Ok(data)
+    /// Returns the channel's value in `val`, scaled to 8 bits.
+    #[inline]
+    fn extract(&self, val: u32) -> u8 {
+        if self.max == 0 {
+            return 0;
+        }
+        let c = ((val & self.mask) >> self.shift) as u64;
+        ((c * 255 + self.max as u64 / 2) / self.max as u64) as u8
+    }
 }
 
 #[derive(Debug)]
@@ -268,7 +1538,7 @@ struct BitIndex<'a> {
     index: usize,
 }
 
-fn bit_index(bytes: &[u8], nbits: usize, size: usize) -> BitIndex {
+fn bit_index(bytes: &[u8], nbits: usize, size: usize) -> BitIndex<'_> {
     let bits_left = BITS - nbits;
     BitIndex {
         size,
@@ -322,6 +1592,14 @@ fn test_calculate_bit_index() {
     assert_eq!(bi.next(), None);
     assert_eq!(bi.next(), None);
 
+    let mut bi = bit_index(&bytes, 2, 5);
+    assert_eq!(bi.next(), Some(0b10));
+    assert_eq!(bi.next(), Some(0b00));
+    assert_eq!(bi.next(), Some(0b00));
+    assert_eq!(bi.next(), Some(0b01));
+    assert_eq!(bi.next(), Some(0b11));
+    assert_eq!(bi.next(), None);
+
     let mut bi = bit_index(&bytes, 4, 4);
     assert_eq!(bi.next(), Some(0b1000));
     assert_eq!(bi.next(), Some(0b0001));
@@ -0,0 +1,300 @@
+//! Delta encoding between consecutive frames.
+//!
+//! A `Delta` only stores the runs of pixels that changed between two images
+//! of the same size, which keeps sequences of mostly static frames (e.g. screen
+//! recordings) small.
+//!
+//! # Example
+//!
+//! ```
+//! use two::{consts, delta, Image};
+//!
+//! let prev = Image::new(4, 4);
+//! let mut next = prev.clone();
+//! next.set_pixel(1, 2, consts::RED);
+//!
+//! let d = delta::diff(&prev, &next);
+//! let bytes = d.to_bytes();
+//! let d = delta::Delta::from_bytes(&bytes).unwrap();
+//! assert_eq!(delta::apply(&prev, &d).unwrap(), next);
+//! ```
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Cursor, Read};
+
+use crate::decoder::{BmpError, BmpErrorKind, BmpResult};
+use crate::{Image, Pixel};
+
+const MAGIC: &[u8; 2] = b"BD";
+
+// Unchanged gaps of up to this many pixels are folded into the surrounding run,
+// as they are cheaper to store than the offset and length of a new run.
+const MERGE_GAP: usize = 2;
+
+/// The changes between two frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delta {
+    width: u32,
+    height: u32,
+    runs: Vec<Run>,
+}
+
+/// A span of changed pixels, `offset` counts pixels in row-major order from the top-left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Run {
+    offset: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl Delta {
+    /// Returns the `width` of the frame this delta produces.
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the `height` of the frame this delta produces.
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `true` if both frames were identical.
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Returns the number of pixels stored in the delta.
+    pub fn changed_pixels(&self) -> usize {
+        self.runs.iter().map(|run| run.pixels.len()).sum()
+    }
+
+    fn is_full_frame(&self) -> bool {
+        self.changed_pixels() as u64 == self.width as u64 * self.height as u64
+    }
+
+    /// Serializes the delta into its compact binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(14 + self.runs.len() * 8 + self.changed_pixels() * 3);
+        bytes.extend_from_slice(MAGIC);
+        // Writing into a `Vec` cannot fail
+        bytes.write_u32::<LittleEndian>(self.width).unwrap();
+        bytes.write_u32::<LittleEndian>(self.height).unwrap();
+        bytes
+            .write_u32::<LittleEndian>(self.runs.len() as u32)
+            .unwrap();
+        for run in &self.runs {
+            bytes.write_u32::<LittleEndian>(run.offset).unwrap();
+            bytes
+                .write_u32::<LittleEndian>(run.pixels.len() as u32)
+                .unwrap();
+            for px in &run.pixels {
+                bytes.extend_from_slice(&[px.r, px.g, px.b]);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a delta previously serialized with `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> BmpResult<Delta> {
+        let mut source = Cursor::new(bytes);
+        let mut magic = [0; 2];
+        source.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(BmpError::new(
+                BmpErrorKind::WrongMagicNumbers,
+                format!("Expected {:?}, but was {:?}", MAGIC, magic),
+            ));
+        }
+
+        let width = source.read_u32::<LittleEndian>()?;
+        let height = source.read_u32::<LittleEndian>()?;
+        let num_runs = source.read_u32::<LittleEndian>()?;
+        let num_pixels = width as u64 * height as u64;
+
+        let mut runs = Vec::new();
+        let mut px = [0; 3];
+        for _ in 0..num_runs {
+            let offset = source.read_u32::<LittleEndian>()?;
+            let len = source.read_u32::<LittleEndian>()?;
+            if offset as u64 + len as u64 > num_pixels {
+                return Err(BmpError::new(
+                    BmpErrorKind::DimensionMismatch,
+                    format!("Run at {} of length {} exceeds the frame", offset, len),
+                ));
+            }
+
+            // Only the pixels the bytes left can hold are reserved
+            let left = (bytes.len() as u64).saturating_sub(source.position()) / 3;
+            let mut pixels = Vec::with_capacity((len as u64).min(left) as usize);
+            for _ in 0..len {
+                source.read_exact(&mut px)?;
+                pixels.push(px!(px[0], px[1], px[2]));
+            }
+            runs.push(Run { offset, pixels });
+        }
+
+        Ok(Delta {
+            width,
+            height,
+            runs,
+        })
+    }
+}
+
+/// Computes the changes needed to turn `prev` into `next`.
+///
+/// If the frames differ in size, the delta holds all of `next`.
+pub fn diff(prev: &Image, next: &Image) -> Delta {
+    let (width, height) = (next.width, next.height);
+    let same_size = prev.width == width && prev.height == height;
+
+    let mut runs: Vec<Run> = Vec::new();
+    let mut offset = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let px = next.get_pixel(x, y);
+            if same_size && prev.get_pixel(x, y) == px {
+                offset += 1;
+                continue;
+            }
+
+            match runs.last_mut() {
+                Some(run) if offset - (run.offset as usize + run.pixels.len()) <= MERGE_GAP => {
+                    let start = run.offset as usize + run.pixels.len();
+                    for i in start..offset {
+                        let (gx, gy) = ((i % width as usize) as u32, (i / width as usize) as u32);
+                        run.pixels.push(next.get_pixel(gx, gy));
+                    }
+                    run.pixels.push(px);
+                }
+                _ => runs.push(Run {
+                    offset: offset as u32,
+                    pixels: vec![px],
+                }),
+            }
+            offset += 1;
+        }
+    }
+
+    Delta {
+        width,
+        height,
+        runs,
+    }
+}
+
+/// Produces the next frame by applying `delta` on top of `prev`.
+///
+/// Fails with `DimensionMismatch` if `prev` has a different size and the delta
+/// does not cover the whole frame.
+pub fn apply(prev: &Image, delta: &Delta) -> BmpResult<Image> {
+    let mut next = if prev.width == delta.width && prev.height == delta.height {
+        prev.clone()
+    } else if delta.is_full_frame() {
+        Image::new(delta.width, delta.height)
+    } else {
+        return Err(BmpError::new(
+            BmpErrorKind::DimensionMismatch,
+            format!(
+                "Delta is {}x{}, but the previous frame is {}x{}",
+                delta.width, delta.height, prev.width, prev.height
+            ),
+        ));
+    };
+
+    let width = delta.width as usize;
+    for run in &delta.runs {
+        for (i, &px) in run.pixels.iter().enumerate() {
+            let pos = run.offset as usize + i;
+            next.set_pixel((pos % width) as u32, (pos / width) as u32, px);
+        }
+    }
+
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    #[test]
+    fn identical_frames_give_an_empty_delta() {
+        let img = Image::new(3, 2);
+        let d = diff(&img, &img);
+        assert!(d.is_empty());
+        assert_eq!(apply(&img, &d).unwrap(), img);
+    }
+
+    #[test]
+    fn nearby_changes_are_merged_into_one_run() {
+        let prev = Image::new(8, 2);
+        let mut next = prev.clone();
+        next.set_pixel(1, 0, consts::RED);
+        next.set_pixel(3, 0, consts::RED);
+        next.set_pixel(6, 1, consts::BLUE);
+
+        let d = diff(&prev, &next);
+        assert_eq!(d.runs.len(), 2);
+        assert_eq!(d.changed_pixels(), 4);
+        assert_eq!(apply(&prev, &d).unwrap(), next);
+    }
+
+    #[test]
+    fn delta_survives_serialization() {
+        let prev = Image::new(5, 5);
+        let mut next = prev.clone();
+        next.set_pixel(4, 4, consts::WHITE);
+        next.set_pixel(0, 2, consts::LIME);
+
+        let d = diff(&prev, &next);
+        assert_eq!(Delta::from_bytes(&d.to_bytes()).unwrap(), d);
+
+        // A run longer than the bytes left is an error, not an allocation
+        let mut bytes = MAGIC.to_vec();
+        for value in [65535, 65535, 1, 0, 0xF000_0000u32] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        match Delta::from_bytes(&bytes) {
+            Err(BmpError {
+                kind: BmpErrorKind::BmpIoError(_),
+                ..
+            }) => (/* Expected */),
+            _ => panic!("The run is cut short"),
+        }
+        let huge = Delta {
+            width: 65536,
+            height: 65536,
+            runs: Vec::new(),
+        };
+        assert!(!huge.is_full_frame());
+    }
+
+    #[test]
+    fn resized_frames_are_stored_in_full() {
+        let prev = Image::new(2, 2);
+        let mut next = Image::new(3, 1);
+        next.set_pixel(2, 0, consts::RED);
+
+        let d = diff(&prev, &next);
+        assert_eq!(d.changed_pixels(), 3);
+        assert_eq!(apply(&prev, &d).unwrap(), next);
+    }
+
+    #[test]
+    fn error_when_applying_partial_delta_to_other_size() {
+        let prev = Image::new(2, 2);
+        let mut next = prev.clone();
+        next.set_pixel(0, 0, consts::RED);
+        let d = diff(&prev, &next);
+
+        match apply(&Image::new(4, 4), &d) {
+            Err(BmpError {
+                kind: BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Delta should not apply to a frame of another size"),
+        }
+    }
+}
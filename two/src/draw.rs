@@ -0,0 +1,576 @@
+//! Drawing of lines, shapes, text and markers.
+//!
+//! Coordinates are signed so shapes may reach past the image edges, anything
+//! outside of the image is clipped.
+//!
+//! ```
+//! use two::{consts, draw, Image};
+//!
+//! let mut img = Image::new(64, 16);
+//! draw::line(&mut img, (0, 15), (63, 15), consts::WHITE);
+//! draw::text(&mut img, (1, 1), "42 UM", consts::WHITE, 2);
+//! ```
+
+use crate::{consts, Image, Pixel};
+
+/// Height of a glyph at scale 1.
+pub const GLYPH_HEIGHT: u32 = 5;
+/// Horizontal advance of a glyph at scale 1, including the spacing.
+pub const GLYPH_ADVANCE: u32 = 4;
+
+/// Sets the pixel at `(x, y)` if it lies within the image.
+#[inline]
+pub fn plot(img: &mut Image, x: i32, y: i32, color: Pixel) {
+    if x >= 0 && y >= 0 && (x as u32) < img.width && (y as u32) < img.height {
+        img.set_pixel(x as u32, y as u32, color);
+    }
+}
+
+/// Draws a one pixel wide line from `from` to `to`, both ends included.
+pub fn line(img: &mut Image, from: (i32, i32), to: (i32, i32), color: Pixel) {
+    // Only the part within the image is walked, however far the ends lie outside
+    let ((mut x, mut y), to) = match clip(img, from, to) {
+        Some(ends) => ends,
+        None => return,
+    };
+    // Bresenham's algorithm, for all octants
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    loop {
+        plot(img, x as i32, y as i32, color);
+        if (x, y) == to {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Returns the ends of the part of the line from `from` to `to` that covers
+/// pixels of the image, rounded to the nearest pixels, or `None` if it misses
+/// the image.
+fn clip(img: &Image, from: (i32, i32), to: (i32, i32)) -> Option<((i64, i64), (i64, i64))> {
+    if img.width == 0 || img.height == 0 {
+        return None;
+    }
+    // Liang-Barsky, against the outer edges of the border pixels
+    let (x0, y0) = (from.0 as f64, from.1 as f64);
+    let (dx, dy) = (to.0 as f64 - x0, to.1 as f64 - y0);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    // Every edge keeps the points with `p * t <= q`
+    for &(p, q) in &[
+        (-dx, x0 + 0.5),
+        (dx, img.width as f64 - 0.5 - x0),
+        (-dy, y0 + 0.5),
+        (dy, img.height as f64 - 0.5 - y0),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+
+    let at = |t: f64| ((x0 + t * dx).round() as i64, (y0 + t * dy).round() as i64);
+    Some((at(t0), at(t1)))
+}
+
+/// Draws the one pixel wide outline of the `(x, y, width, height)` region.
+pub fn rect(img: &mut Image, rect: (i32, i32, u32, u32), color: Pixel) {
+    let (x, y, w, h) = rect;
+    if w == 0 || h == 0 {
+        return;
+    }
+    let far = |start: i32, len: u32| (start as i64 + len as i64 - 1).min(i32::MAX as i64) as i32;
+    let (x1, y1) = (far(x, w), far(y, h));
+    line(img, (x, y), (x1, y), color);
+    line(img, (x, y1), (x1, y1), color);
+    line(img, (x, y), (x, y1), color);
+    line(img, (x1, y), (x1, y1), color);
+}
+
+/// Draws the one pixel wide outline of a circle around `center`.
+pub fn circle(img: &mut Image, center: (i32, i32), radius: u32, color: Pixel) {
+    // Midpoint circle algorithm, mirrored into all eight octants
+    let (cx, cy) = center;
+    let (mut x, mut y) = (radius as i32, 0);
+    let mut err = 1 - x;
+    while x >= y {
+        for &(dx, dy) in &[(x, y), (y, x), (-y, x), (-x, y)] {
+            plot(img, cx + dx, cy + dy, color);
+            plot(img, cx - dx, cy - dy, color);
+        }
+        y += 1;
+        if err < 0 {
+            err += 2 * y + 1;
+        } else {
+            x -= 1;
+            err += 2 * (y - x) + 1;
+        }
+    }
+}
+
+/// Draws lines connecting the `points` in order, the outline is not closed.
+pub fn polyline(img: &mut Image, points: &[(i32, i32)], color: Pixel) {
+    if let [point] = points {
+        plot(img, point.0, point.1, color);
+    }
+    for pair in points.windows(2) {
+        line(img, pair[0], pair[1], color);
+    }
+}
+
+/// Fills the polygon with the corners `points`, using the even-odd rule.
+///
+/// A pixel is filled when its center lies inside the polygon.
+pub fn fill_polygon(img: &mut Image, points: &[(i32, i32)], color: Pixel) {
+    if points.len() < 3 || img.width == 0 {
+        return;
+    }
+    let min_y = points.iter().map(|p| p.1).min().unwrap_or(0).max(0);
+    let max_y = points
+        .iter()
+        .map(|p| p.1)
+        .max()
+        .unwrap_or(0)
+        .min(img.height as i32 - 1);
+
+    let mut crossings = Vec::new();
+    for y in min_y..=max_y {
+        // Intersect the scanline through the pixel centers with every edge
+        let cy = y as f32 + 0.5;
+        crossings.clear();
+        let edges = points.iter().zip(points.iter().cycle().skip(1));
+        for (&(x0, y0), &(x1, y1)) in edges {
+            let (y0f, y1f) = (y0 as f32, y1 as f32);
+            if (y0f <= cy) != (y1f <= cy) {
+                let t = (cy - y0f) / (y1f - y0f);
+                crossings.push(x0 as f32 + t * (x1 - x0) as f32);
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for span in crossings.chunks_exact(2) {
+            // Pixels whose centers lie between the two crossings
+            let x0 = (span[0] - 0.5).ceil().max(0.0);
+            let x1 = (span[1] - 0.5).ceil().min(img.width as f32);
+            if x0 < x1 {
+                img.fill_rect((x0 as u32, y as u32, (x1 - x0) as u32, 1), color);
+            }
+        }
+    }
+}
+
+/// Draws a quadratic Bezier curve from `p0` to `p2`, pulled towards `p1`.
+pub fn quad_bezier(img: &mut Image, p0: (i32, i32), p1: (i32, i32), p2: (i32, i32), color: Pixel) {
+    let steps = segments(&[p0, p1, p2]);
+    let points = (0..=steps).map(|i| {
+        let t = i as f32 / steps as f32;
+        let u = 1.0 - t;
+        let at =
+            |a: i32, b: i32, c: i32| u * u * a as f32 + 2.0 * u * t * b as f32 + t * t * c as f32;
+        (at(p0.0, p1.0, p2.0), at(p0.1, p1.1, p2.1))
+    });
+    stroke(img, points, color);
+}
+
+/// Draws a cubic Bezier curve from `p0` to `p3`, with the control points `p1` and `p2`.
+pub fn cubic_bezier(
+    img: &mut Image,
+    p0: (i32, i32),
+    p1: (i32, i32),
+    p2: (i32, i32),
+    p3: (i32, i32),
+    color: Pixel,
+) {
+    let steps = segments(&[p0, p1, p2, p3]);
+    let points = (0..=steps).map(|i| {
+        let t = i as f32 / steps as f32;
+        let u = 1.0 - t;
+        let at = |a: i32, b: i32, c: i32, d: i32| {
+            u * u * u * a as f32
+                + 3.0 * u * u * t * b as f32
+                + 3.0 * u * t * t * c as f32
+                + t * t * t * d as f32
+        };
+        (at(p0.0, p1.0, p2.0, p3.0), at(p0.1, p1.1, p2.1, p3.1))
+    });
+    stroke(img, points, color);
+}
+
+/// Draws the part of the ellipse around `center` with the `(horizontal, vertical)`
+/// radii that runs from the angle `start` to `end`, in radians.
+///
+/// Angles grow clockwise from the positive x axis, since y grows downwards.
+/// Passing `0.0` and `2.0 * PI` draws the whole ellipse.
+pub fn arc(
+    img: &mut Image,
+    center: (i32, i32),
+    radii: (u32, u32),
+    start: f32,
+    end: f32,
+    color: Pixel,
+) {
+    let (rx, ry) = (radii.0 as f32, radii.1 as f32);
+    // About one segment per two pixels along the longer radius
+    let sweep = end - start;
+    let steps = ((sweep.abs() * rx.max(ry) / 2.0).ceil() as u32).clamp(1, 4096);
+    let points = (0..=steps).map(|i| {
+        let angle = start + sweep * i as f32 / steps as f32;
+        (
+            center.0 as f32 + rx * angle.cos(),
+            center.1 as f32 + ry * angle.sin(),
+        )
+    });
+    stroke(img, points, color);
+}
+
+/// Returns how many lines approximate a curve with the given control points,
+/// about one per two pixels of the control polygon.
+fn segments(points: &[(i32, i32)]) -> u32 {
+    let length: f32 = points
+        .windows(2)
+        .map(|w| {
+            let (dx, dy) = (w[1].0 as f32 - w[0].0 as f32, w[1].1 as f32 - w[0].1 as f32);
+            (dx * dx + dy * dy).sqrt()
+        })
+        .sum();
+    ((length / 2.0).ceil() as u32).clamp(1, 4096)
+}
+
+/// Connects the points, rounded to the nearest pixels, with lines.
+fn stroke<I: Iterator<Item = (f32, f32)>>(img: &mut Image, points: I, color: Pixel) {
+    let points: Vec<(i32, i32)> = points
+        .map(|(x, y)| (x.round() as i32, y.round() as i32))
+        .collect();
+    polyline(img, &points, color);
+}
+
+/// Returns the size of `s` when drawn by `text` with `scale`.
+pub fn text_size(s: &str, scale: u32) -> (u32, u32) {
+    let chars = s.chars().count() as u32;
+    if chars == 0 {
+        return (0, 0);
+    }
+    // The spacing after the last glyph is not part of the text
+    ((chars * GLYPH_ADVANCE - 1) * scale, GLYPH_HEIGHT * scale)
+}
+
+/// Draws `s` with its top-left corner at `pos`, using a built-in 3x5 pixel font
+/// magnified `scale` times.
+///
+/// The font covers digits, ASCII letters shown as capitals and `. , : - + / % ( ) =`.
+/// Other characters are left blank.
+pub fn text(img: &mut Image, pos: (i32, i32), s: &str, color: Pixel, scale: u32) {
+    let scale = scale as i32;
+    let mut x0 = pos.0;
+    for c in s.chars() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = x0 + col * scale + dx;
+                        let y = pos.1 + row as i32 * scale + dy;
+                        plot(img, x, y, color);
+                    }
+                }
+            }
+        }
+        x0 += GLYPH_ADVANCE as i32 * scale;
+    }
+}
+
+/// The shape drawn by `Image::draw_markers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkerShape {
+    Cross,
+    Box,
+    Circle,
+}
+
+/// The look of the markers drawn by `Image::draw_markers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarkerStyle {
+    pub shape: MarkerShape,
+    /// Distance from the point to the edge of the marker.
+    pub radius: u32,
+    pub color: Pixel,
+    /// Writes the index of each point next to its marker.
+    pub labels: bool,
+}
+
+impl Default for MarkerStyle {
+    fn default() -> MarkerStyle {
+        MarkerStyle {
+            shape: MarkerShape::Cross,
+            radius: 4,
+            color: consts::RED,
+            labels: false,
+        }
+    }
+}
+
+impl Image {
+    /// Draws a marker centered on every point, for example to show the results
+    /// of a detector on the frame it ran on.
+    pub fn draw_markers(&mut self, points: &[(u32, u32)], style: &MarkerStyle) {
+        let r = style.radius as i32;
+        for (i, &(x, y)) in points.iter().enumerate() {
+            let (x, y) = (x as i32, y as i32);
+            match style.shape {
+                MarkerShape::Cross => {
+                    line(self, (x - r, y), (x + r, y), style.color);
+                    line(self, (x, y - r), (x, y + r), style.color);
+                }
+                MarkerShape::Box => {
+                    let size = 2 * style.radius + 1;
+                    rect(self, (x - r, y - r, size, size), style.color);
+                }
+                MarkerShape::Circle => circle(self, (x, y), style.radius, style.color),
+            }
+            if style.labels {
+                // Above and to the right of the marker
+                let pos = (x + r + 2, y - r - GLYPH_HEIGHT as i32);
+                text(self, pos, &i.to_string(), style.color, 1);
+            }
+        }
+    }
+}
+
+/// Returns the rows of the glyph for `c`, from the top, three bits per row.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        _ => [0; 5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    #[test]
+    fn lines_are_clipped_to_the_image() {
+        let mut img = Image::new(4, 4);
+        line(&mut img, (-2, -2), (5, 5), consts::WHITE);
+        for i in 0..4 {
+            assert_eq!(img.get_pixel(i, i), consts::WHITE);
+        }
+        assert_eq!(img.get_pixel(1, 0), consts::BLACK);
+
+        line(&mut img, (3, 0), (0, 0), consts::RED);
+        assert_eq!(img.get_pixel(0, 0), consts::RED);
+        assert_eq!(img.get_pixel(3, 0), consts::RED);
+
+        // Only the few pixels within the image are walked
+        line(&mut img, (i32::MIN, 1), (i32::MAX, 1), consts::LIME);
+        line(
+            &mut img,
+            (i32::MIN, i32::MIN),
+            (i32::MAX, i32::MAX),
+            consts::BLUE,
+        );
+        line(
+            &mut img,
+            (-1_000_000_000, 9),
+            (1_000_000_000, 9),
+            consts::LIME,
+        );
+        for x in 0..4 {
+            assert_eq!(
+                img.get_pixel(x, 1),
+                match x {
+                    1 => consts::BLUE,
+                    _ => consts::LIME,
+                }
+            );
+        }
+        assert_eq!(img.get_pixel(2, 2), consts::BLUE);
+        assert_eq!(img.get_pixel(0, 3), consts::BLACK);
+    }
+
+    #[test]
+    fn rect_draws_only_the_outline() {
+        let mut img = Image::new(5, 5);
+        rect(&mut img, (1, 1, 3, 3), consts::WHITE);
+        assert_eq!(img.get_pixel(1, 1), consts::WHITE);
+        assert_eq!(img.get_pixel(3, 3), consts::WHITE);
+        assert_eq!(img.get_pixel(2, 2), consts::BLACK);
+        assert_eq!(img.get_pixel(4, 4), consts::BLACK);
+
+        // Outlines reaching past `i32::MAX` end there
+        rect(&mut img, (i32::MAX - 1, 0, u32::MAX, 2), consts::WHITE);
+        polyline(&mut img, &[(i32::MIN, 0), (i32::MAX, 0)], consts::RED);
+        assert_eq!(img.get_pixel(4, 0), consts::RED);
+    }
+
+    #[test]
+    fn text_is_scaled() {
+        assert_eq!(text_size("10", 2), (14, 10));
+        assert_eq!(text_size("", 2), (0, 0));
+
+        let mut img = Image::new(14, 10);
+        text(&mut img, (0, 0), "10", consts::WHITE, 2);
+        // The top of the "1" is its middle column
+        assert_eq!(img.get_pixel(2, 0), consts::WHITE);
+        assert_eq!(img.get_pixel(3, 1), consts::WHITE);
+        assert_eq!(img.get_pixel(0, 0), consts::BLACK);
+        // The "0" starts after the advance
+        assert_eq!(img.get_pixel(8, 0), consts::WHITE);
+        assert_eq!(img.get_pixel(10, 4), consts::BLACK);
+    }
+
+    #[test]
+    fn circles_pass_through_the_axes() {
+        let mut img = Image::new(11, 11);
+        circle(&mut img, (5, 5), 4, consts::WHITE);
+        for &(x, y) in &[(9, 5), (1, 5), (5, 9), (5, 1)] {
+            assert_eq!(img.get_pixel(x, y), consts::WHITE);
+        }
+        assert_eq!(img.get_pixel(5, 5), consts::BLACK);
+        assert_eq!(img.get_pixel(1, 1), consts::BLACK);
+    }
+
+    #[test]
+    fn markers_are_drawn_around_each_point() {
+        let mut img = Image::new(20, 20);
+        let style = MarkerStyle {
+            shape: MarkerShape::Box,
+            radius: 2,
+            color: consts::LIME,
+            labels: true,
+        };
+        img.draw_markers(&[(5, 10), (0, 0)], &style);
+
+        assert_eq!(img.get_pixel(3, 8), consts::LIME);
+        assert_eq!(img.get_pixel(7, 12), consts::LIME);
+        assert_eq!(img.get_pixel(5, 10), consts::BLACK);
+        assert_eq!(img.get_pixel(2, 2), consts::LIME);
+        // The "0" label to the upper right of the first marker
+        assert_eq!(img.get_pixel(9, 3), consts::LIME);
+    }
+
+    #[test]
+    fn polyline_connects_the_points() {
+        let mut img = Image::new(5, 5);
+        polyline(&mut img, &[(0, 0), (4, 0), (4, 4)], consts::WHITE);
+        assert_eq!(img.get_pixel(2, 0), consts::WHITE);
+        assert_eq!(img.get_pixel(4, 2), consts::WHITE);
+        // Not closed
+        assert_eq!(img.get_pixel(2, 2), consts::BLACK);
+    }
+
+    #[test]
+    fn fill_polygon_covers_pixel_centers_inside() {
+        let mut img = Image::new(10, 10);
+        fill_polygon(&mut img, &[(2, 2), (6, 2), (6, 5), (2, 5)], consts::WHITE);
+        let filled = img
+            .coordinates()
+            .filter(|&(x, y)| img.get_pixel(x, y) == consts::WHITE)
+            .count();
+        assert_eq!(filled, 4 * 3);
+        assert_eq!(img.get_pixel(2, 2), consts::WHITE);
+        assert_eq!(img.get_pixel(6, 2), consts::BLACK);
+
+        // Triangles partly outside are clipped
+        let mut img = Image::new(4, 4);
+        fill_polygon(&mut img, &[(-4, -4), (4, 0), (0, 4)], consts::RED);
+        assert_eq!(img.get_pixel(0, 0), consts::RED);
+        assert_eq!(img.get_pixel(3, 3), consts::BLACK);
+    }
+
+    #[test]
+    fn bezier_curves_hit_their_end_points() {
+        let mut img = Image::new(20, 20);
+        quad_bezier(&mut img, (0, 19), (10, -19), (19, 19), consts::WHITE);
+        assert_eq!(img.get_pixel(0, 19), consts::WHITE);
+        assert_eq!(img.get_pixel(19, 19), consts::WHITE);
+        // The curve only reaches halfway to the control point
+        assert_eq!(img.get_pixel(10, 0), consts::WHITE);
+
+        let mut img = Image::new(20, 20);
+        cubic_bezier(&mut img, (0, 0), (0, 19), (19, 19), (19, 0), consts::WHITE);
+        assert_eq!(img.get_pixel(0, 0), consts::WHITE);
+        assert_eq!(img.get_pixel(19, 0), consts::WHITE);
+        assert_eq!(img.get_pixel(10, 10), consts::BLACK);
+    }
+
+    #[test]
+    fn arcs_follow_the_ellipse() {
+        use std::f32::consts::PI;
+
+        let mut img = Image::new(21, 11);
+        arc(&mut img, (10, 5), (10, 5), 0.0, PI, consts::WHITE);
+        assert_eq!(img.get_pixel(20, 5), consts::WHITE);
+        assert_eq!(img.get_pixel(10, 10), consts::WHITE);
+        assert_eq!(img.get_pixel(0, 5), consts::WHITE);
+        // Only the lower half was drawn
+        assert_eq!(img.get_pixel(10, 0), consts::BLACK);
+    }
+}
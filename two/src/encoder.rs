@@ -1,55 +1,635 @@
-extern crate byteorder;
-use byteorder::{LittleEndian, WriteBytesExt};
-use crate::Image;
-use std::io::{self, Write};
+use crate::buffer::PixelBuffer;
+use crate::decoder;
+use crate::{
+    wire, BmpDibHeader, BmpHeader, BmpVersion, Compression, EncoderOptions, Image, IndexedImage,
+    Pixel,
+};
+use std::collections::HashMap;
+use std::io;
 
-const B: u8 = 66;
-const M: u8 = 77;
-
-pub fn encode_image(bmp_image: &Image) -> io::Result<Vec<u8>> {
+/// Encodes the image as `options` ask for. The default options write an
+/// uncompressed 24 bpp file, as `Image::save` does.
+pub fn encode_image<B: PixelBuffer>(
+    bmp_image: &Image<B>,
+    options: &EncoderOptions,
+) -> io::Result<Vec<u8>> {
     let mut bmp_data = Vec::with_capacity(bmp_image.header.file_size as usize);
+    write_image(&mut bmp_data, bmp_image, options)?;
+    Ok(bmp_data)
+}
 
-    write_header(&mut bmp_data, bmp_image)?;
-    write_data(&mut bmp_data, bmp_image)?;
+/// Encodes the image with its palette, at 8 bpp unless `options` ask for
+/// another depth. The resolution and hotspot are left at 1000 pixels per
+/// meter and zero unless `options` set them.
+pub fn encode_indexed(img: &IndexedImage, options: &EncoderOptions) -> io::Result<Vec<u8>> {
+    let bits_per_pixel = options.bits_per_pixel.unwrap_or(8);
+    if !matches!(bits_per_pixel, 1 | 2 | 4 | 8) {
+        return encode_image(&img.to_image(), options);
+    }
+    let (hres, vres) = options.resolution.unwrap_or((1000, 1000));
+    let mut fields = HeaderFields {
+        width: img.get_width(),
+        height: img.get_height(),
+        bits_per_pixel,
+        hres,
+        vres,
+        num_colors: 0,
+        compress_type: 0,
+        data_size: None,
+        top_down: options.top_down,
+        version: header_version(options),
+        masks: [0; 4],
+        profile_size: profile_size(options)?,
+    };
+    let creators = options.hotspot.unwrap_or((0, 0));
+    let mut bmp_data = Vec::new();
+    write_indexes(
+        &mut bmp_data,
+        &mut fields,
+        creators,
+        img.palette(),
+        img.as_slice(),
+        options.compression,
+    )?;
+    bmp_data.extend_from_slice(options.icc_profile.as_deref().unwrap_or_default());
+    Ok(bmp_data)
+}
 
+/// Writes the whole file: the headers, the pixels at the bit depth asked for
+/// and the profile, if any.
+fn write_image<B: PixelBuffer>(
+    bmp_data: &mut Vec<u8>,
+    img: &Image<B>,
+    options: &EncoderOptions,
+) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let alpha = writes_alpha(img, options);
+    let bits_per_pixel = options.bits_per_pixel.unwrap_or(match options.compression {
+        Compression::Rle8 => 8,
+        Compression::None if alpha => 32,
+        Compression::None => 24,
+    });
+    let (hres, vres) = options
+        .resolution
+        .unwrap_or((img.dib_header.hres, img.dib_header.vres));
+    let creators = options
+        .hotspot
+        .unwrap_or((img.header.creator1, img.header.creator2));
+    let mut fields = HeaderFields {
+        width: img.width,
+        height: img.height,
+        bits_per_pixel,
+        hres,
+        vres,
+        num_colors: 0,
+        compress_type: 0,
+        data_size: None,
+        top_down: options.top_down,
+        version: header_version(options),
+        masks: [0; 4],
+        profile_size: profile_size(options)?,
+    };
+
+    match (bits_per_pixel, options.compression) {
+        (1 | 2 | 4 | 8, compression) => {
+            let indexed = match options.palette {
+                Some(ref palette) if palette.is_empty() || palette.len() > 256 => {
+                    return Err(invalid("Palettes have 1 to 256 colors".into()))
+                }
+                Some(ref palette) => img.map_to_palette(palette),
+                None => img.quantize_to(1 << bits_per_pixel),
+            };
+            write_indexes(
+                bmp_data,
+                &mut fields,
+                creators,
+                indexed.palette(),
+                indexed.as_slice(),
+                compression,
+            )?;
+        }
+        (24 | 32, Compression::None) => {
+            // Alpha needs the masks of the newer headers, older ones keep it in
+            // the unused byte
+            if alpha && bits_per_pixel == 32 {
+                fields.compress_type = 3;
+                fields.masks = RGBA_MASKS;
+            }
+            write_headers(bmp_data, creators, &fields)?;
+            write_data(bmp_data, img, bits_per_pixel, options.top_down);
+        }
+        (bpp @ (24 | 32), Compression::Rle8) => {
+            return Err(invalid(format!(
+                "{} bpp files cannot be run-length encoded",
+                bpp
+            )))
+        }
+        (bpp, _) => return Err(invalid(format!("{} bpp files cannot be written", bpp))),
+    }
+    bmp_data.extend_from_slice(options.icc_profile.as_deref().unwrap_or_default());
+    Ok(())
+}
+
+/// Encodes an image with a palette at the bit depth it was decoded from, every
+/// pixel mapped to the palette entry of exactly its color.
+///
+/// Fails with `InvalidInput` if the image has no palette or a pixel has a
+/// color outside of it.
+pub fn encode_with_palette(img: &Image) -> io::Result<Vec<u8>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let palette = match img.palette() {
+        Some(palette) if !palette.is_empty() && palette.len() <= 256 => palette,
+        _ => {
+            return Err(invalid(
+                "The image has no palette of 1 to 256 colors".into(),
+            ))
+        }
+    };
+
+    let indexes = palette_indexes(img, palette)?;
+
+    let bpp = img.dib_header.bits_per_pixel;
+    let bits_per_pixel = match bpp {
+        1 | 2 | 4 | 8 if palette.len() <= 1 << bpp => bpp,
+        _ => [1, 2, 4, 8]
+            .into_iter()
+            .find(|&bpp| palette.len() <= 1 << bpp)
+            .unwrap_or(8),
+    };
+    let mut fields = HeaderFields {
+        width: img.width,
+        height: img.height,
+        bits_per_pixel,
+        hres: img.dib_header.hres,
+        vres: img.dib_header.vres,
+        num_colors: 0,
+        compress_type: 0,
+        data_size: None,
+        top_down: false,
+        version: BmpVersion::Three,
+        masks: [0; 4],
+        profile_size: 0,
+    };
+    let creators = (img.header.creator1, img.header.creator2);
+    let mut bmp_data = Vec::new();
+    write_indexes(
+        &mut bmp_data,
+        &mut fields,
+        creators,
+        palette,
+        &indexes,
+        Compression::None,
+    )?;
     Ok(bmp_data)
 }
 
-fn write_header(bmp_data: &mut Vec<u8>, img: &Image) -> io::Result<()> {
-    let header = &img.header;
-    let dib_header = &img.dib_header;
-    let (header_size, data_size) = file_size!(24, img.width, img.height);
-
-    io::Write::write(bmp_data, &[B, M])?;
-
-    bmp_data.write_u32::<LittleEndian>(header_size + data_size)?;
-    bmp_data.write_u16::<LittleEndian>(header.creator1)?;
-    bmp_data.write_u16::<LittleEndian>(header.creator2)?;
-    bmp_data.write_u32::<LittleEndian>(header_size)?; // pixel_offset
-
-    bmp_data.write_u32::<LittleEndian>(dib_header.header_size)?;
-    bmp_data.write_i32::<LittleEndian>(dib_header.width)?;
-    bmp_data.write_i32::<LittleEndian>(dib_header.height)?;
-    bmp_data.write_u16::<LittleEndian>(1)?; // num_planes
-    bmp_data.write_u16::<LittleEndian>(24)?; // bits_per_pixel
-    bmp_data.write_u32::<LittleEndian>(0)?; // compress_type
-    bmp_data.write_u32::<LittleEndian>(data_size)?;
-    bmp_data.write_i32::<LittleEndian>(dib_header.hres)?;
-    bmp_data.write_i32::<LittleEndian>(dib_header.vres)?;
-    bmp_data.write_u32::<LittleEndian>(0)?; // num_colors
-    bmp_data.write_u32::<LittleEndian>(0)?; // num_imp_colors
+/// Encodes the image in the layout of the file it was decoded from, whose bytes
+/// up to the pixel data are `headers`: the same header version, bit depth,
+/// palette, compression and row order. Only the sizes are updated.
+///
+/// Fails with `InvalidInput` if the image no longer has the size of its
+/// headers, a pixel is not in its palette, or the layout cannot be written.
+pub fn encode_preserving(img: &Image, headers: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let dh = &img.dib_header;
+    if (dh.width.unsigned_abs(), dh.height.unsigned_abs()) != (img.width, img.height) {
+        return Err(invalid(format!(
+            "The image is {}x{}, but its headers are for {}x{}",
+            img.width,
+            img.height,
+            dh.width.unsigned_abs(),
+            dh.height.unsigned_abs()
+        )));
+    }
+
+    // The fields patched below are those of the headers passed, whose size
+    // tells which of them exist
+    let file_header_size = wire::FILE_HEADER_SIZE as usize;
+    let header_size = match headers.get(file_header_size..file_header_size + 4) {
+        Some(&[a, b, c, d]) => u32::from_le_bytes([a, b, c, d]) as usize,
+        _ => return Err(invalid("The headers are cut short".into())),
+    };
+    if headers.len() < file_header_size + header_size {
+        return Err(invalid(format!(
+            "The headers are cut short of their {} bytes",
+            header_size
+        )));
+    }
+
+    let width = img.width.max(1) as usize;
+    let bottom_up = dh.is_bottom_up();
+    let mut bmp_data = headers.to_vec();
+    match (dh.bits_per_pixel, dh.compress_type) {
+        (bpp @ (1 | 2 | 4 | 8), 0) => {
+            let indexes = palette_indexes(img, img.palette().unwrap_or(&[]))?;
+            write_packed(&mut bmp_data, bpp, file_rows(&indexes, width, bottom_up));
+        }
+        (bpp @ (4 | 8), 1 | 2) => {
+            let indexes = palette_indexes(img, img.palette().unwrap_or(&[]))?;
+            write_rle(&mut bmp_data, bpp, file_rows(&indexes, width, bottom_up));
+        }
+        (bpp @ (24 | 32), 0) => {
+            let row_size = decoder::row_size(img.width, bpp);
+            let opaque = vec![0; width];
+            let alpha = match img.alpha() {
+                Some(alpha) => file_rows(alpha, width, bottom_up),
+                None => vec![&opaque[..]; img.height as usize],
+            };
+            for (row, alpha) in file_rows(img.pixels(), width, bottom_up)
+                .into_iter()
+                .zip(alpha)
+            {
+                let start = bmp_data.len();
+                for (px, &a) in row.iter().zip(alpha) {
+                    bmp_data.extend_from_slice(&[px.b, px.g, px.r]);
+                    if bpp == 32 {
+                        bmp_data.push(a);
+                    }
+                }
+                bmp_data.resize(start + row_size, 0);
+            }
+        }
+        (bpp, compression) => {
+            return Err(invalid(format!(
+                "{} bpp pixels of compression type {} cannot be written back",
+                bpp, compression
+            )))
+        }
+    }
+
+    let too_large = || invalid("The file would exceed 4 GiB".into());
+    let data_size = u32::try_from(bmp_data.len() - headers.len()).map_err(|_| too_large())?;
+    // Sizes left at zero may stay so, except that of compressed data
+    if header_size >= wire::DIB_HEADER_SIZE as usize {
+        if dh.data_size != 0 || dh.compress_type != 0 {
+            bmp_data[34..38].copy_from_slice(&data_size.to_le_bytes());
+        }
+    } else if dh.compress_type != 0 {
+        return Err(invalid(format!(
+            "Headers of {} bytes have no room for the size of compressed pixels",
+            header_size
+        )));
+    }
+
+    // Profiles after the pixels move with their end, those before stay in the headers
+    let profile = img
+        .icc_profile()
+        .map(|data| data.to_vec())
+        .or_else(|| img.linked_icc_profile().map(|name| [name, &[0]].concat()));
+    if let Some(profile) = profile {
+        if header_size < wire::V5_HEADER_SIZE as usize {
+            return Err(invalid(format!(
+                "Headers of {} bytes have no room for a profile",
+                header_size
+            )));
+        }
+        let field = file_header_size + 112;
+        let offset = &headers[field..field + 4];
+        let start = file_header_size
+            + u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+        if start >= headers.len() {
+            // Any gap after the pixels is kept, zeroed
+            let stored = match dh.data_size {
+                0 => decoder::row_size(img.width, dh.bits_per_pixel) * img.height as usize,
+                size => size as usize,
+            };
+            let gap = start.saturating_sub(headers.len() + stored);
+            bmp_data.resize(bmp_data.len() + gap, 0);
+            let start = (bmp_data.len() - file_header_size) as u32;
+            bmp_data[field..field + 4].copy_from_slice(&start.to_le_bytes());
+            bmp_data[field + 4..field + 8].copy_from_slice(&(profile.len() as u32).to_le_bytes());
+            bmp_data.extend_from_slice(&profile);
+        }
+    }
+
+    if img.header.file_size != 0 {
+        let file_size = u32::try_from(bmp_data.len()).map_err(|_| too_large())?;
+        bmp_data[2..6].copy_from_slice(&file_size.to_le_bytes());
+    }
+    Ok(bmp_data)
+}
+
+/// Splits `data` into rows of `width`, in the order they are stored.
+fn file_rows<T>(data: &[T], width: usize, bottom_up: bool) -> Vec<&[T]> {
+    let mut rows: Vec<&[T]> = data.chunks_exact(width).collect();
+    if bottom_up {
+        rows.reverse();
+    }
+    rows
+}
+
+/// Maps every pixel to the palette entry of exactly its color, an
+/// `InvalidInput` error if there is none.
+fn palette_indexes(img: &Image, palette: &[Pixel]) -> io::Result<Vec<u8>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    // The decoded indexes are kept when they still match, as palettes may
+    // repeat a color
+    Ok(match img.indices() {
+        Some(indexes)
+            if indexes
+                .iter()
+                .zip(img.pixels())
+                .all(|(&i, &px)| palette.get(i as usize) == Some(&px)) =>
+        {
+            indexes.to_vec()
+        }
+        _ => {
+            let mut entries = HashMap::new();
+            for (i, &px) in palette.iter().enumerate().rev() {
+                entries.insert(<[u8; 3]>::from(px), i as u8);
+            }
+            let mut indexes = Vec::with_capacity(img.pixels().len());
+            for (n, &px) in img.pixels().iter().enumerate() {
+                let i = entries.get(&<[u8; 3]>::from(px)).ok_or_else(|| {
+                    let (x, y) = (n as u32 % img.width, n as u32 / img.width);
+                    invalid(format!(
+                        "The color of pixel {},{} is not in the palette",
+                        x, y
+                    ))
+                })?;
+                indexes.push(*i);
+            }
+            indexes
+        }
+    })
+}
+
+/// Writes the headers, the palette and the indexes, packed to
+/// `fields.bits_per_pixel` or run-length encoded.
+fn write_indexes(
+    bmp_data: &mut Vec<u8>,
+    fields: &mut HeaderFields,
+    creators: (u16, u16),
+    palette: &[Pixel],
+    indexes: &[u8],
+    compression: Compression,
+) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let bpp = fields.bits_per_pixel;
+    if palette.len() > 1 << bpp {
+        return Err(invalid(format!(
+            "{} colors do not fit in {} bpp",
+            palette.len(),
+            bpp
+        )));
+    }
+    fields.num_colors = palette.len() as u32;
+    let width = fields.width.max(1) as usize;
+    match compression {
+        Compression::None => {
+            write_headers(bmp_data, creators, fields)?;
+            write_palette(bmp_data, palette);
+            write_packed(bmp_data, bpp, file_rows(indexes, width, !fields.top_down));
+        }
+        Compression::Rle8 if bpp != 8 => {
+            return Err(invalid(format!("RLE8 needs 8 bpp, not {}", bpp)))
+        }
+        // Deltas in run-length encoded data can only move upwards
+        Compression::Rle8 if fields.top_down => {
+            return Err(invalid(
+                "Run-length encoded files cannot be stored top-down".into(),
+            ))
+        }
+        Compression::Rle8 => {
+            let mut pixels = Vec::new();
+            write_rle(&mut pixels, 8, indexes.chunks_exact(width).rev());
+            fields.compress_type = 1;
+            fields.data_size = Some(
+                u32::try_from(pixels.len())
+                    .map_err(|_| invalid("The pixels exceed 4 GiB".into()))?,
+            );
+            write_headers(bmp_data, creators, fields)?;
+            write_palette(bmp_data, palette);
+            bmp_data.extend_from_slice(&pixels);
+        }
+    }
     Ok(())
 }
 
-fn write_data(bmp_data: &mut Vec<u8>, img: &Image) -> io::Result<()> {
-    let padding = &[0; 4][0..img.padding as usize];
-    for y in 0..img.height {
-        for x in 0..img.width {
-            let index = (y * img.width + x) as usize;
-            let px = &img.data[index];
-            Write::write(bmp_data, &[px.b, px.g, px.r])?;
+fn write_palette(bmp_data: &mut Vec<u8>, palette: &[Pixel]) {
+    for px in palette {
+        bmp_data.extend_from_slice(&[px.b, px.g, px.r, 0]);
+    }
+}
+
+/// Writes rows of indexes packed to `bpp` bits, each padded to 4 bytes.
+fn write_packed<'a>(bmp_data: &mut Vec<u8>, bpp: u16, rows: impl IntoIterator<Item = &'a [u8]>) {
+    let bpp = bpp as usize;
+    for row in rows {
+        let start = bmp_data.len();
+        bmp_data.resize(start + (bpp * row.len()).div_ceil(32) * 4, 0);
+        // The leftmost pixel is in the high bits of the first byte
+        for (x, &i) in row.iter().enumerate() {
+            let bit = x * bpp;
+            bmp_data[start + bit / 8] |= i << (8 - bpp - bit % 8);
+        }
+    }
+}
+
+/// Run-length encodes rows of 8 or 4 bpp indexes. Every row ends with an end
+/// of line code, except for the last one, which ends the bitmap.
+fn write_rle<'a>(bmp_data: &mut Vec<u8>, bpp: u16, rows: impl IntoIterator<Item = &'a [u8]>) {
+    // A run of one index, which 4 bpp runs alternate with itself
+    let run_code = |i: u8| if bpp == 4 { i << 4 | i } else { i };
+    let mut rows = rows.into_iter().peekable();
+    while let Some(row) = rows.next() {
+        let mut x = 0;
+        while x < row.len() {
+            let rest = &row[x..];
+            // Absolute mode pays off for three or more indexes without a run
+            let mut literal = 0;
+            while literal < rest.len() && literal < 255 && run_length(&rest[literal..], 3) < 3 {
+                literal += 1;
+            }
+            if literal >= 3 {
+                bmp_data.extend_from_slice(&[0, literal as u8]);
+                let start = bmp_data.len();
+                match bpp {
+                    4 => bmp_data.extend(
+                        rest[..literal]
+                            .chunks(2)
+                            .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)),
+                    ),
+                    _ => bmp_data.extend_from_slice(&rest[..literal]),
+                }
+                // Absolute runs are padded to an even number of bytes
+                if (bmp_data.len() - start) % 2 == 1 {
+                    bmp_data.push(0);
+                }
+                x += literal;
+            } else {
+                let run = run_length(rest, 255);
+                bmp_data.extend_from_slice(&[run as u8, run_code(rest[0])]);
+                x += run;
+            }
         }
-        Write::write(bmp_data, padding)?;
+        let end = if rows.peek().is_some() { 0 } else { 1 };
+        bmp_data.extend_from_slice(&[0, end]);
+    }
+}
+
+/// Counts the indexes equal to the first one, up to `max`.
+fn run_length(indexes: &[u8], max: usize) -> usize {
+    indexes
+        .iter()
+        .take(max)
+        .take_while(|&&i| i == indexes[0])
+        .count()
+}
+
+/// Encodes the image into `bmp_data`, replacing its contents but keeping its capacity.
+pub fn encode_into<B: PixelBuffer>(bmp_image: &Image<B>, bmp_data: &mut Vec<u8>) -> io::Result<()> {
+    bmp_data.clear();
+    bmp_data.reserve(bmp_image.header.file_size as usize);
+    write_image(bmp_data, bmp_image, &EncoderOptions::default())
+}
+
+/// Whether the image is written as 32 bpp with alpha, which only the newer
+/// headers have masks for.
+fn writes_alpha<B: PixelBuffer>(img: &Image<B>, options: &EncoderOptions) -> bool {
+    img.alpha.is_some() && matches!(header_version(options), BmpVersion::Four | BmpVersion::Five)
+}
+
+/// The version of the header written, 5 for files with a profile.
+fn header_version(options: &EncoderOptions) -> BmpVersion {
+    match options.icc_profile {
+        Some(_) => BmpVersion::Five,
+        None => options.version,
+    }
+}
+
+fn profile_size(options: &EncoderOptions) -> io::Result<u32> {
+    let size = options
+        .icc_profile
+        .as_ref()
+        .map_or(0, |profile| profile.len());
+    u32::try_from(size)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "The profile exceeds 4 GiB"))
+}
+
+// Red, green, blue and alpha of 32 bpp pixels stored as BGRA
+const RGBA_MASKS: [u32; 4] = [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000];
+// The rendering intent of version 5 headers, LCS_GM_IMAGES
+const INTENT_PERCEPTUAL: u32 = 4;
+
+/// The fields that differ between the files written.
+struct HeaderFields {
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    hres: i32,
+    vres: i32,
+    num_colors: u32,
+    compress_type: u32,
+    /// The size of compressed pixels, that of uncompressed ones follows from
+    /// the other fields.
+    data_size: Option<u32>,
+    /// Stores the rows from the top, with a negative height.
+    top_down: bool,
+    version: BmpVersion,
+    /// Masks of version 4 and 5 headers, only used by bitfields.
+    masks: [u32; 4],
+    /// The size of the ICC profile following the pixels, if any.
+    profile_size: u32,
+}
+
+fn write_headers(
+    bmp_data: &mut Vec<u8>,
+    creators: (u16, u16),
+    fields: &HeaderFields,
+) -> io::Result<()> {
+    let too_large = |details: String| io::Error::new(io::ErrorKind::InvalidInput, details);
+    let (head_size, rows_size) =
+        decoder::file_size(fields.bits_per_pixel, fields.width, fields.height)
+            .map_err(|err| too_large(err.to_string()))?;
+    let data_size = fields.data_size.unwrap_or(rows_size);
+    let dib_size = match fields.version {
+        BmpVersion::Three => wire::DIB_HEADER_SIZE,
+        BmpVersion::Four => wire::V4_HEADER_SIZE,
+        BmpVersion::Five => wire::V5_HEADER_SIZE,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Files cannot be written with {} headers", other.as_ref()),
+            ))
+        }
+    };
+    // The palette sits between the headers and the pixels
+    let header_size = head_size - wire::DIB_HEADER_SIZE + dib_size + 4 * fields.num_colors;
+    // The profile follows the pixels
+    let pixels_end = header_size
+        .checked_add(data_size)
+        .ok_or_else(|| too_large("The pixels and the palette exceed 4 GiB".into()))?;
+    let file_size = pixels_end
+        .checked_add(fields.profile_size)
+        .ok_or_else(|| too_large("The pixels and the profile exceed 4 GiB".into()))?;
+
+    let header = BmpHeader {
+        file_size,
+        creator1: creators.0,
+        creator2: creators.1,
+        pixel_offset: header_size,
+    };
+    // The version asked for, whatever the image was decoded from
+    let dib_header = BmpDibHeader {
+        header_size: dib_size,
+        width: fields.width as i32,
+        height: if fields.top_down {
+            -(fields.height as i32)
+        } else {
+            fields.height as i32
+        },
+        num_planes: 1,
+        bits_per_pixel: fields.bits_per_pixel,
+        compress_type: fields.compress_type,
+        data_size,
+        hres: fields.hres,
+        vres: fields.vres,
+        num_colors: fields.num_colors,
+        num_imp_colors: 0,
+    };
+    wire::write_file_header(bmp_data, &header)?;
+    wire::write_dib_header(bmp_data, &dib_header)?;
+    if dib_size > wire::DIB_HEADER_SIZE {
+        let mut color = wire::ColorFields {
+            masks: fields.masks,
+            cs_type: crate::LCS_SRGB,
+            intent: INTENT_PERCEPTUAL,
+            ..wire::ColorFields::default()
+        };
+        // Its offset counts from the start of the DIB header
+        if fields.profile_size > 0 {
+            color.cs_type = crate::PROFILE_EMBEDDED;
+            color.profile_offset = pixels_end - wire::FILE_HEADER_SIZE as u32;
+            color.profile_size = fields.profile_size;
+        }
+        wire::write_color_fields(bmp_data, &color, dib_size)?;
     }
     Ok(())
 }
+
+/// Writes the pixels as BGR, or as BGRA at 32 bpp with zero alpha if the
+/// image has none.
+fn write_data<B: PixelBuffer>(bmp_data: &mut Vec<u8>, img: &Image<B>, bpp: u16, top_down: bool) {
+    let width = img.width.max(1) as usize;
+    let row_size = decoder::row_size(img.width, bpp);
+    let transparent = vec![0; width];
+    // Rows are stored from the top, but written bottom-up unless asked otherwise
+    let alpha = match img.alpha {
+        Some(ref alpha) => file_rows(alpha, width, !top_down),
+        None => vec![&transparent[..]; img.height as usize],
+    };
+    for (row, alpha) in file_rows(img.pixels(), width, !top_down)
+        .into_iter()
+        .zip(alpha)
+    {
+        let start = bmp_data.len();
+        for (px, &a) in row.iter().zip(alpha) {
+            bmp_data.extend_from_slice(&[px.b, px.g, px.r]);
+            if bpp == 32 {
+                bmp_data.push(a);
+            }
+        }
+        bmp_data.resize(start + row_size, 0);
+    }
+}
@@ -0,0 +1,170 @@
+//! Points, sizes and rectangles shared by the APIs working on parts of an image.
+//!
+//! Every function taking a region accepts a `Rect` as well as the older
+//! `(x, y, width, height)` tuples, and positions accept `(x, y)` tuples.
+//!
+//! ```
+//! use two::geom::{Rect, Size};
+//! use two::{consts, Image};
+//!
+//! let mut img = Image::new(8, 8);
+//! let rect = Rect::new(6, 6, 4, 4);
+//! img.fill_rect(rect, consts::RED);
+//! assert_eq!(rect.clamp(img.size()), Rect::new(6, 6, 2, 2));
+//! assert_eq!(img.crop(rect).size(), Size::new(2, 2));
+//! ```
+
+/// A position, which may lie outside of an image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    #[inline]
+    pub fn new(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+}
+
+impl From<(i32, i32)> for Point {
+    #[inline]
+    fn from((x, y): (i32, i32)) -> Point {
+        Point { x, y }
+    }
+}
+
+/// The dimensions of an image or a region.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    #[inline]
+    pub fn new(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+
+    /// Returns the number of pixels.
+    #[inline]
+    pub fn area(self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+impl From<(u32, u32)> for Size {
+    #[inline]
+    fn from((width, height): (u32, u32)) -> Size {
+        Size { width, height }
+    }
+}
+
+/// A region of an image, from its top-left corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    #[inline]
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the region covering all of an image of `size`.
+    #[inline]
+    pub fn from_size(size: Size) -> Rect {
+        Rect::new(0, 0, size.width, size.height)
+    }
+
+    #[inline]
+    pub fn size(self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    /// Returns the column just right of the region.
+    #[inline]
+    pub fn right(self) -> u32 {
+        self.x.saturating_add(self.width)
+    }
+
+    /// Returns the row just below the region.
+    #[inline]
+    pub fn bottom(self) -> u32 {
+        self.y.saturating_add(self.height)
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    #[inline]
+    pub fn contains(self, x: u32, y: u32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Returns the part of the region that lies in `other`, `None` if there is none.
+    pub fn intersect(self, other: Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if x >= right || y >= bottom {
+            return None;
+        }
+        Some(Rect::new(x, y, right - x, bottom - y))
+    }
+
+    /// Returns the part of the region that lies in an image of `size`. Regions
+    /// entirely outside keep their origin, clamped to the image, and become empty.
+    pub fn clamp(self, size: Size) -> Rect {
+        let x = self.x.min(size.width);
+        let y = self.y.min(size.height);
+        Rect::new(
+            x,
+            y,
+            self.right().min(size.width) - x,
+            self.bottom().min(size.height) - y,
+        )
+    }
+}
+
+impl From<(u32, u32, u32, u32)> for Rect {
+    #[inline]
+    fn from((x, y, width, height): (u32, u32, u32, u32)) -> Rect {
+        Rect::new(x, y, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersect_and_clamp() {
+        let a = Rect::new(2, 2, 4, 4);
+        assert_eq!(
+            a.intersect(Rect::new(4, 0, 10, 3)),
+            Some(Rect::new(4, 2, 2, 1))
+        );
+        assert_eq!(a.intersect(Rect::new(6, 2, 1, 1)), None);
+        assert_eq!(a.clamp(Size::new(3, 10)), Rect::new(2, 2, 1, 4));
+        assert_eq!(a.clamp(Size::new(1, 1)), Rect::new(1, 1, 0, 0));
+        assert!(a.clamp(Size::new(1, 1)).is_empty());
+        let huge = Rect::new(u32::MAX, 0, u32::MAX, 1);
+        assert_eq!(huge.right(), u32::MAX);
+        assert!(a.contains(5, 2) && !a.contains(6, 2));
+    }
+}
@@ -0,0 +1,506 @@
+//! Single channel images.
+
+use std::io;
+use std::path::Path;
+
+use crate::colormap::ColorMap;
+use crate::policy::ColorPolicy;
+use crate::{Image, ImageIndex, Pixel};
+
+/// An 8-bit grayscale image, stored row by row from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrayImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl GrayImage {
+    pub fn new(width: u32, height: u32) -> GrayImage {
+        GrayImage {
+            width,
+            height,
+            data: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Creates an image from row-major samples, returns `None` if `data` is not `width * height` long.
+    pub fn from_vec(width: u32, height: u32, data: Vec<u8>) -> Option<GrayImage> {
+        if data.len() != (width * height) as usize {
+            return None;
+        }
+
+        Some(GrayImage {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Converts `img` using the Rec. 601 luma weights.
+    pub fn from_image(img: &Image) -> GrayImage {
+        let mut gray = GrayImage::new(img.width, img.height);
+        for (x, y) in img.coordinates() {
+            gray.set_pixel(x, y, luma(img.get_pixel(x, y)));
+        }
+        gray
+    }
+
+    /// Returns the `width` of the image.
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the `height` of the image.
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: u32, y: u32, val: u8) {
+        self.data[(y * self.width + x) as usize] = val;
+    }
+
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> u8 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    #[inline]
+    pub fn coordinates(&self) -> ImageIndex {
+        ImageIndex::new(self.width, self.height)
+    }
+
+    /// Returns the samples, row by row from the top.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Expands the samples into gray RGB pixels.
+    pub fn to_image(&self) -> Image {
+        self.apply_colormap(&ColorMap::Grayscale)
+    }
+
+    /// Returns the signed Euclidean distance of every pixel to the nearest pixel on
+    /// the other side of the mask, row by row from the top.
+    ///
+    /// Samples of 128 and above are inside the mask and get positive distances,
+    /// the others are outside and get negative ones. Pixels along the edge are
+    /// 1 or -1 away. Uses the exact linear-time algorithm of Felzenszwalb and Huttenlocher.
+    pub fn distance_transform(&self) -> Vec<f32> {
+        let inside: Vec<bool> = self.data.iter().map(|&val| val >= MASK_THRESHOLD).collect();
+        let to_inside = squared_distances(self.width, self.height, |i| inside[i]);
+        let to_outside = squared_distances(self.width, self.height, |i| !inside[i]);
+        to_outside
+            .iter()
+            .zip(to_inside.iter())
+            .map(|(&o, &i)| o.sqrt() - i.sqrt())
+            .collect()
+    }
+
+    /// Traces the outer boundary of every 8-connected region of the mask, with
+    /// samples of 128 and above being inside.
+    ///
+    /// Regions are met row by row from the top. Each contour lists the boundary
+    /// pixels clockwise, starting at the region's top-left pixel, using Moore
+    /// neighbor tracing. Pixels on one pixel wide parts of a region are visited
+    /// twice. Holes are not traced.
+    pub fn find_contours(&self) -> Vec<Vec<(u32, u32)>> {
+        let inside = |x: i32, y: i32| {
+            x >= 0
+                && y >= 0
+                && (x as u32) < self.width
+                && (y as u32) < self.height
+                && self.get_pixel(x as u32, y as u32) >= MASK_THRESHOLD
+        };
+
+        let mut visited = vec![false; self.data.len()];
+        let mut contours = Vec::new();
+        let mut stack = Vec::new();
+        for (x, y) in self.coordinates() {
+            let i = (y * self.width + x) as usize;
+            if visited[i] || !inside(x as i32, y as i32) {
+                continue;
+            }
+            contours.push(trace_contour((x as i32, y as i32), &inside));
+
+            // Mark the whole region so it is traced only once
+            visited[i] = true;
+            stack.push((x as i32, y as i32));
+            while let Some((x, y)) = stack.pop() {
+                for &(dx, dy) in &NEIGHBORS {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if inside(nx, ny) {
+                        let j = (ny as u32 * self.width + nx as u32) as usize;
+                        if !visited[j] {
+                            visited[j] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+        }
+        contours
+    }
+
+    /// Encodes `distance_transform` into samples, mapping the edge to 128 and
+    /// distances of `spread` pixels inside or outside of it to 255 or 0.
+    pub fn sdf(&self, spread: f32) -> GrayImage {
+        self.sdf_with(spread, ColorPolicy::default())
+    }
+
+    /// Like `sdf`, converting the scaled distances to samples with `policy`.
+    pub fn sdf_with(&self, spread: f32, policy: ColorPolicy) -> GrayImage {
+        let data = self
+            .distance_transform()
+            .iter()
+            .map(|d| policy.to_u8(128.0 + d / spread * 127.0))
+            .collect();
+        GrayImage {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Turns the samples into false-color RGB pixels by looking them up in `map`.
+    pub fn apply_colormap(&self, map: &ColorMap) -> Image {
+        let lut = map.lut();
+        let mut img = Image::new(self.width, self.height);
+        for (x, y) in self.coordinates() {
+            img.set_pixel(x, y, lut[self.get_pixel(x, y) as usize]);
+        }
+        img
+    }
+}
+
+// Samples at or above this are inside of a mask
+const MASK_THRESHOLD: u8 = 128;
+
+// The 8-neighborhood clockwise from the west, as y grows downwards
+const NEIGHBORS: [(i32, i32); 8] = [
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+];
+
+/// Follows the boundary of the region whose top-left pixel is `start`.
+fn trace_contour<F: Fn(i32, i32) -> bool>(start: (i32, i32), inside: &F) -> Vec<(u32, u32)> {
+    let mut contour = vec![(start.0 as u32, start.1 as u32)];
+    // The west neighbor of the top-left pixel is always outside
+    let (mut p, mut back) = (start, 0);
+    loop {
+        let next = (1..=8).map(|k| (back + k) % 8).find(|&d| {
+            let (dx, dy) = NEIGHBORS[d];
+            inside(p.0 + dx, p.1 + dy)
+        });
+        let d = match next {
+            Some(d) => d,
+            // A single pixel
+            None => break,
+        };
+
+        // Continue searching from the last outside neighbor, as seen from the new pixel
+        let c = (p.0 + NEIGHBORS[d].0, p.1 + NEIGHBORS[d].1);
+        let (ox, oy) = NEIGHBORS[(d + 7) % 8];
+        let outside = (p.0 + ox - c.0, p.1 + oy - c.1);
+        back = NEIGHBORS.iter().position(|&n| n == outside).unwrap_or(0);
+        p = c;
+
+        // Jacob's stopping criterion, the start is entered the same way as initially
+        if p == start && back == 0 {
+            break;
+        }
+        contour.push((p.0 as u32, p.1 as u32));
+    }
+    contour
+}
+
+// Stands in for an infinite distance, without overflowing when squared
+const FAR: f32 = 1e20;
+
+/// Returns the squared distance of every pixel to the nearest one where `is_target` holds.
+fn squared_distances<F: Fn(usize) -> bool>(width: u32, height: u32, is_target: F) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    if w == 0 || h == 0 {
+        return Vec::new();
+    }
+    let mut dist: Vec<f32> = (0..w * h)
+        .map(|i| if is_target(i) { 0.0 } else { FAR })
+        .collect();
+
+    // Columns first, then rows, reusing the buffers for every line
+    let mut line = vec![0.0; w.max(h)];
+    let mut out = vec![0.0; w.max(h)];
+    for x in 0..w {
+        for y in 0..h {
+            line[y] = dist[y * w + x];
+        }
+        squared_distances_1d(&line[..h], &mut out[..h]);
+        for y in 0..h {
+            dist[y * w + x] = out[y];
+        }
+    }
+    for row in dist.chunks_exact_mut(w) {
+        line[..w].copy_from_slice(row);
+        squared_distances_1d(&line[..w], row);
+    }
+    dist
+}
+
+/// One dimensional pass, computing the lower envelope of the parabolas rooted at `f`.
+fn squared_distances_1d(f: &[f32], out: &mut [f32]) {
+    let n = f.len();
+    // Positions of the parabolas in the envelope and where each one takes over
+    let mut v = vec![0usize; n];
+    let mut z = vec![0f32; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    for q in 1..n {
+        let intersection =
+            |p: usize| ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32;
+        let mut s = intersection(v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, d) in out.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let dq = q as f32 - v[k] as f32;
+        *d = dq * dq + f[v[k]];
+    }
+}
+
+/// How 16-bit samples are mapped onto the 8-bit range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Stretches the smallest and largest sample of the image to black and white.
+    MinMax,
+    /// Maps `low` to black and `high` to white, samples outside are clamped.
+    Fixed(u16, u16),
+}
+
+/// A 16-bit grayscale image, stored row by row from the top.
+///
+/// BMP files cannot hold 16-bit samples, so these images are tone-mapped to
+/// 8 bits when saved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrayImage16 {
+    width: u32,
+    height: u32,
+    data: Vec<u16>,
+}
+
+impl GrayImage16 {
+    pub fn new(width: u32, height: u32) -> GrayImage16 {
+        GrayImage16 {
+            width,
+            height,
+            data: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Creates an image from row-major samples, returns `None` if `data` is not `width * height` long.
+    pub fn from_vec(width: u32, height: u32, data: Vec<u16>) -> Option<GrayImage16> {
+        if data.len() != (width * height) as usize {
+            return None;
+        }
+
+        Some(GrayImage16 {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Returns the `width` of the image.
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the `height` of the image.
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: u32, y: u32, val: u16) {
+        self.data[(y * self.width + x) as usize] = val;
+    }
+
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> u16 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    #[inline]
+    pub fn coordinates(&self) -> ImageIndex {
+        ImageIndex::new(self.width, self.height)
+    }
+
+    /// Returns the samples, row by row from the top.
+    #[inline]
+    pub fn as_slice(&self) -> &[u16] {
+        &self.data
+    }
+
+    /// Tone-maps the samples linearly into 8 bits.
+    pub fn scaled(&self, mode: ScaleMode) -> GrayImage {
+        let (low, high) = match mode {
+            ScaleMode::MinMax => {
+                let min = self.data.iter().copied().min().unwrap_or(0);
+                let max = self.data.iter().copied().max().unwrap_or(0);
+                (min, max)
+            }
+            ScaleMode::Fixed(low, high) => (low, high),
+        };
+
+        let range = high.saturating_sub(low) as u32;
+        let data = self
+            .data
+            .iter()
+            .map(|&val| {
+                if range == 0 {
+                    return if val > low { 255 } else { 0 };
+                }
+                let val = (val.clamp(low, high) - low) as u32;
+                ((val * 255 + range / 2) / range) as u8
+            })
+            .collect();
+        GrayImage {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Saves a quick-look 8-bit rendition of the image, see `scaled`.
+    pub fn save_scaled<P: AsRef<Path>>(&self, path: P, mode: ScaleMode) -> io::Result<()> {
+        self.scaled(mode).to_image().save(path)
+    }
+}
+
+#[inline]
+pub(crate) fn luma(px: Pixel) -> u8 {
+    ((px.r as u32 * 299 + px.g as u32 * 587 + px.b as u32 * 114 + 500) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    #[test]
+    fn convert_from_rgb_using_luma() {
+        let img = crate::open("test/rgbw.bmp").unwrap();
+        let gray = GrayImage::from_image(&img);
+        assert_eq!(gray.as_slice(), &[76, 150, 29, 255]);
+    }
+
+    #[test]
+    fn from_vec_checks_the_length() {
+        assert!(GrayImage::from_vec(2, 2, vec![0; 3]).is_none());
+        let gray = GrayImage::from_vec(2, 1, vec![0, 255]).unwrap();
+        assert_eq!(gray.get_pixel(1, 0), 255);
+    }
+
+    #[test]
+    fn apply_colormap_maps_every_sample() {
+        let gray = GrayImage::from_vec(3, 1, vec![0, 128, 255]).unwrap();
+
+        let img = gray.apply_colormap(&ColorMap::Hot);
+        assert_eq!(img.get_pixel(0, 0), consts::BLACK);
+        assert_eq!(img.get_pixel(2, 0), consts::WHITE);
+
+        let img = gray.to_image();
+        assert_eq!(img.get_pixel(1, 0), px!(128, 128, 128));
+    }
+
+    #[test]
+    fn min_max_stretches_to_the_full_range() {
+        let img = GrayImage16::from_vec(3, 1, vec![1000, 1500, 2000]).unwrap();
+        assert_eq!(img.scaled(ScaleMode::MinMax).as_slice(), &[0, 128, 255]);
+        assert_eq!(
+            GrayImage16::new(2, 2).scaled(ScaleMode::MinMax),
+            GrayImage::new(2, 2)
+        );
+    }
+
+    #[test]
+    fn fixed_range_clamps_outliers() {
+        let img = GrayImage16::from_vec(4, 1, vec![0, 256, 511, 65535]).unwrap();
+        let gray = img.scaled(ScaleMode::Fixed(256, 511));
+        assert_eq!(gray.as_slice(), &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn distance_transform_is_signed_and_euclidean() {
+        let mut mask = GrayImage::new(7, 7);
+        mask.set_pixel(3, 3, 255);
+        let dist = mask.distance_transform();
+
+        assert_eq!(dist[3 * 7 + 3], 1.0);
+        assert_eq!(dist[3 * 7 + 6], -3.0);
+        assert_eq!(dist[0], -(18f32).sqrt());
+
+        let sdf = mask.sdf(3.0);
+        assert_eq!(sdf.get_pixel(3, 3), 170);
+        assert_eq!(sdf.get_pixel(6, 3), 1);
+
+        // 128 - 127 / 3 lies just below 86
+        assert_eq!(sdf.get_pixel(3, 4), 86);
+        let truncate = ColorPolicy {
+            rounding: crate::policy::Rounding::Down,
+            ..ColorPolicy::default()
+        };
+        assert_eq!(mask.sdf_with(3.0, truncate).get_pixel(3, 4), 85);
+    }
+
+    #[test]
+    fn find_contours_traces_each_region_clockwise() {
+        let mut mask = GrayImage::new(8, 5);
+        for (x, y) in mask.coordinates() {
+            if (1..4).contains(&x) && (1..4).contains(&y) {
+                mask.set_pixel(x, y, 255);
+            }
+        }
+        mask.set_pixel(2, 2, 0);
+        mask.set_pixel(6, 0, 200);
+
+        let contours = mask.find_contours();
+        assert_eq!(contours.len(), 2);
+        // In the order the regions are met, row by row
+        assert_eq!(contours[0], vec![(6, 0)]);
+        let square = [
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (3, 2),
+            (3, 3),
+            (2, 3),
+            (1, 3),
+            (1, 2),
+        ];
+        assert_eq!(contours[1], square);
+    }
+}
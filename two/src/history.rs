@@ -0,0 +1,261 @@
+//! Recording the operations an image went through.
+//!
+//! Recording is off unless `Image::enable_history` turns it on. From then on,
+//! the transforms of this crate append an `OpRecord` to the history, and the
+//! images they return inherit it, so an output can be traced back to its
+//! source. Steps done elsewhere can be added with `Image::record`.
+//!
+//! ```
+//! use two::history::OpRecord;
+//! use two::sample::Filter;
+//! use two::{consts, Image};
+//!
+//! let mut img = Image::new(8, 8);
+//! img.enable_history();
+//! img.record(OpRecord::new("open").param("path", "scan.bmp"));
+//! let out = img.crop((2, 2, 4, 4)).rotate(90.0, Filter::Nearest, consts::BLACK);
+//! let ops: Vec<&str> = out.history().unwrap().iter().map(|r| r.op.as_str()).collect();
+//! assert_eq!(ops, ["open", "crop", "rotate"]);
+//! assert!(out.history_json().unwrap().starts_with(r#"[{"op":"open","params":{"path":"scan.bmp"}}"#));
+//! ```
+
+use std::fmt::Write;
+
+use crate::{Image, Pixel};
+
+/// The value of a parameter of an operation.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+// Floats are compared by their bits, so that a record equals its copies
+// even if it holds NaN, and images with a history can still be `Eq`
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Text(a), Value::Text(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl From<u32> for Value {
+    fn from(val: u32) -> Value {
+        Value::Int(val as i64)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(val: i32) -> Value {
+        Value::Int(val as i64)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(val: u8) -> Value {
+        Value::Int(val as i64)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(val: f32) -> Value {
+        // Through the shortest decimal, so 2.2 does not become 2.200000047683716
+        Value::Float(val.to_string().parse().unwrap_or(f64::NAN))
+    }
+}
+
+impl From<Pixel> for Value {
+    /// Stores the color as `#rrggbb`.
+    fn from(val: Pixel) -> Value {
+        Value::Text(format!("#{:02x}{:02x}{:02x}", val.r, val.g, val.b))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(val: &str) -> Value {
+        Value::Text(val.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(val: String) -> Value {
+        Value::Text(val)
+    }
+}
+
+/// An operation and the parameters it was applied with, in the order given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpRecord {
+    pub op: String,
+    pub params: Vec<(String, Value)>,
+}
+
+impl OpRecord {
+    pub fn new<S: Into<String>>(op: S) -> OpRecord {
+        OpRecord {
+            op: op.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds the parameter `name`.
+    pub fn param<S: Into<String>, V: Into<Value>>(mut self, name: S, val: V) -> OpRecord {
+        self.params.push((name.into(), val.into()));
+        self
+    }
+
+    /// Returns the record as a JSON object with the fields `op` and `params`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"op\":");
+        push_str(&mut json, &self.op);
+        json.push_str(",\"params\":{");
+        for (i, (name, val)) in self.params.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            push_str(&mut json, name);
+            json.push(':');
+            match *val {
+                Value::Int(val) => write!(json, "{}", val).unwrap(),
+                // JSON has no infinities or NaN
+                Value::Float(val) if !val.is_finite() => json.push_str("null"),
+                Value::Float(val) => write!(json, "{:?}", val).unwrap(),
+                Value::Text(ref val) => push_str(&mut json, val),
+            }
+        }
+        json.push_str("}}");
+        json
+    }
+}
+
+/// Returns `records` as a JSON array, oldest first.
+pub fn to_json(records: &[OpRecord]) -> String {
+    let objects: Vec<String> = records.iter().map(OpRecord::to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+/// Appends `s` as a quoted JSON string.
+fn push_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+impl Image {
+    /// Starts recording the operations applied to the image, keeping any
+    /// history recorded so far.
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(Vec::new());
+        }
+    }
+
+    /// Returns the operations applied since recording was enabled, oldest
+    /// first, or `None` if it is not.
+    #[inline]
+    pub fn history(&self) -> Option<&[OpRecord]> {
+        self.history.as_deref()
+    }
+
+    /// Returns the history as a JSON array, see `OpRecord::to_json`.
+    pub fn history_json(&self) -> Option<String> {
+        self.history().map(to_json)
+    }
+
+    /// Appends `record` to the history, if it is being recorded.
+    pub fn record(&mut self, record: OpRecord) {
+        if let Some(ref mut history) = self.history {
+            history.push(record);
+        }
+    }
+
+    /// Gives `out`, which was made from this image, its history followed by
+    /// `record`. The record is only built if there is a history to add it to.
+    pub(crate) fn derive_history<F: FnOnce() -> OpRecord>(&self, out: &mut Image, record: F) {
+        if let Some(ref history) = self.history {
+            let mut history = history.clone();
+            history.push(record());
+            out.history = Some(history);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+    use crate::sample::Filter;
+
+    #[test]
+    fn transforms_extend_the_history() {
+        let img = Image::new(4, 4);
+        assert_eq!(img.crop((0, 0, 2, 2)).history(), None);
+
+        let mut img = Image::new(4, 4);
+        img.enable_history();
+        img.record(OpRecord::new("open"));
+        let out = img
+            .crop((1, 0, 3, 4))
+            .resample_to_square_pixels(Filter::Bilinear)
+            .unwrap()
+            .warp_perspective(
+                [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+                2,
+                2,
+                Filter::Nearest,
+                consts::RED,
+            );
+        // The source keeps its own history
+        assert_eq!(img.history().unwrap().len(), 1);
+        let history = out.history().unwrap();
+        let ops: Vec<&str> = history.iter().map(|r| r.op.as_str()).collect();
+        assert_eq!(
+            ops,
+            [
+                "open",
+                "crop",
+                "resample_to_square_pixels",
+                "warp_perspective"
+            ]
+        );
+        assert_eq!(
+            history[1].params,
+            [
+                ("x".to_string(), Value::Int(1)),
+                ("y".to_string(), Value::Int(0)),
+                ("width".to_string(), Value::Int(3)),
+                ("height".to_string(), Value::Int(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn records_as_json() {
+        let record = OpRecord::new("gamma")
+            .param("value", 2.2f32)
+            .param("note", "a \"quoted\"\nline")
+            .param("nan", f32::NAN)
+            .param("count", 3u32);
+        assert_eq!(
+            to_json(&[record, OpRecord::new("flip")]),
+            r#"[{"op":"gamma","params":{"value":2.2,"note":"a \"quoted\"\nline","nan":null,"count":3}},{"op":"flip","params":{}}]"#
+        );
+    }
+}
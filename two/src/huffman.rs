@@ -0,0 +1,402 @@
+//! The modified Huffman code of ITU T.4 (Group 3 fax, one-dimensional), which
+//! OS/2 2.x bitmaps use as compression type 3 for 1 bpp images.
+//!
+//! Every row is a sequence of runs, alternating between white and black and
+//! always starting with a white one, possibly of length zero. Runs of 64
+//! pixels or more are written as a makeup code followed by a terminating code.
+//! Rows may be preceded by an end of line code, eleven or more zeros followed
+//! by a one. White pixels are palette index 0, black ones index 1.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+use crate::decoder::BmpResult;
+
+/// Terminating and makeup codes of white runs.
+const WHITE: &[(u16, &str)] = &[
+    (0, "00110101"),
+    (1, "000111"),
+    (2, "0111"),
+    (3, "1000"),
+    (4, "1011"),
+    (5, "1100"),
+    (6, "1110"),
+    (7, "1111"),
+    (8, "10011"),
+    (9, "10100"),
+    (10, "00111"),
+    (11, "01000"),
+    (12, "001000"),
+    (13, "000011"),
+    (14, "110100"),
+    (15, "110101"),
+    (16, "101010"),
+    (17, "101011"),
+    (18, "0100111"),
+    (19, "0001100"),
+    (20, "0001000"),
+    (21, "0010111"),
+    (22, "0000011"),
+    (23, "0000100"),
+    (24, "0101000"),
+    (25, "0101011"),
+    (26, "0010011"),
+    (27, "0100100"),
+    (28, "0011000"),
+    (29, "00000010"),
+    (30, "00000011"),
+    (31, "00011010"),
+    (32, "00011011"),
+    (33, "00010010"),
+    (34, "00010011"),
+    (35, "00010100"),
+    (36, "00010101"),
+    (37, "00010110"),
+    (38, "00010111"),
+    (39, "00101000"),
+    (40, "00101001"),
+    (41, "00101010"),
+    (42, "00101011"),
+    (43, "00101100"),
+    (44, "00101101"),
+    (45, "00000100"),
+    (46, "00000101"),
+    (47, "00001010"),
+    (48, "00001011"),
+    (49, "01010010"),
+    (50, "01010011"),
+    (51, "01010100"),
+    (52, "01010101"),
+    (53, "00100100"),
+    (54, "00100101"),
+    (55, "01011000"),
+    (56, "01011001"),
+    (57, "01011010"),
+    (58, "01011011"),
+    (59, "01001010"),
+    (60, "01001011"),
+    (61, "00110010"),
+    (62, "00110011"),
+    (63, "00110100"),
+    (64, "11011"),
+    (128, "10010"),
+    (192, "010111"),
+    (256, "0110111"),
+    (320, "00110110"),
+    (384, "00110111"),
+    (448, "01100100"),
+    (512, "01100101"),
+    (576, "01101000"),
+    (640, "01100111"),
+    (704, "011001100"),
+    (768, "011001101"),
+    (832, "011010010"),
+    (896, "011010011"),
+    (960, "011010100"),
+    (1024, "011010101"),
+    (1088, "011010110"),
+    (1152, "011010111"),
+    (1216, "011011000"),
+    (1280, "011011001"),
+    (1344, "011011010"),
+    (1408, "011011011"),
+    (1472, "010011000"),
+    (1536, "010011001"),
+    (1600, "010011010"),
+    (1664, "011000"),
+    (1728, "010011011"),
+];
+
+/// Terminating and makeup codes of black runs.
+const BLACK: &[(u16, &str)] = &[
+    (0, "0000110111"),
+    (1, "010"),
+    (2, "11"),
+    (3, "10"),
+    (4, "011"),
+    (5, "0011"),
+    (6, "0010"),
+    (7, "00011"),
+    (8, "000101"),
+    (9, "000100"),
+    (10, "0000100"),
+    (11, "0000101"),
+    (12, "0000111"),
+    (13, "00000100"),
+    (14, "00000111"),
+    (15, "000011000"),
+    (16, "0000010111"),
+    (17, "0000011000"),
+    (18, "0000001000"),
+    (19, "00001100111"),
+    (20, "00001101000"),
+    (21, "00001101100"),
+    (22, "00000110111"),
+    (23, "00000101000"),
+    (24, "00000010111"),
+    (25, "00000011000"),
+    (26, "000011001010"),
+    (27, "000011001011"),
+    (28, "000011001100"),
+    (29, "000011001101"),
+    (30, "000001101000"),
+    (31, "000001101001"),
+    (32, "000001101010"),
+    (33, "000001101011"),
+    (34, "000011010010"),
+    (35, "000011010011"),
+    (36, "000011010100"),
+    (37, "000011010101"),
+    (38, "000011010110"),
+    (39, "000011010111"),
+    (40, "000001101100"),
+    (41, "000001101101"),
+    (42, "000011011010"),
+    (43, "000011011011"),
+    (44, "000001010100"),
+    (45, "000001010101"),
+    (46, "000001010110"),
+    (47, "000001010111"),
+    (48, "000001100100"),
+    (49, "000001100101"),
+    (50, "000001010010"),
+    (51, "000001010011"),
+    (52, "000000100100"),
+    (53, "000000110111"),
+    (54, "000000111000"),
+    (55, "000000100111"),
+    (56, "000000101000"),
+    (57, "000001011000"),
+    (58, "000001011001"),
+    (59, "000000101011"),
+    (60, "000000101100"),
+    (61, "000001011010"),
+    (62, "000001100110"),
+    (63, "000001100111"),
+    (64, "0000001111"),
+    (128, "000011001000"),
+    (192, "000011001001"),
+    (256, "000001011011"),
+    (320, "000000110011"),
+    (384, "000000110100"),
+    (448, "000000110101"),
+    (512, "0000001101100"),
+    (576, "0000001101101"),
+    (640, "0000001001010"),
+    (704, "0000001001011"),
+    (768, "0000001001100"),
+    (832, "0000001001101"),
+    (896, "0000001110010"),
+    (960, "0000001110011"),
+    (1024, "0000001110100"),
+    (1088, "0000001110101"),
+    (1152, "0000001110110"),
+    (1216, "0000001110111"),
+    (1280, "0000001010010"),
+    (1344, "0000001010011"),
+    (1408, "0000001010100"),
+    (1472, "0000001010101"),
+    (1536, "0000001011010"),
+    (1600, "0000001011011"),
+    (1664, "0000001100100"),
+    (1728, "0000001100101"),
+];
+
+/// Makeup codes of long runs of either color.
+const EXTENDED: &[(u16, &str)] = &[
+    (1792, "00000001000"),
+    (1856, "00000001100"),
+    (1920, "00000001101"),
+    (1984, "000000010010"),
+    (2048, "000000010011"),
+    (2112, "000000010100"),
+    (2176, "000000010101"),
+    (2240, "000000010110"),
+    (2304, "000000010111"),
+    (2368, "000000011100"),
+    (2432, "000000011101"),
+    (2496, "000000011110"),
+    (2560, "000000011111"),
+];
+
+// No code starts with more zeros than this, but end of line codes do
+const EOL_ZEROS: u8 = 11;
+const MAX_CODE_LEN: u8 = 13;
+
+/// Maps the length and bits of every code of one color to its run length.
+type CodeTable = HashMap<(u8, u16), u16>;
+
+fn code_table(codes: &[(u16, &str)]) -> CodeTable {
+    codes
+        .iter()
+        .chain(EXTENDED)
+        .map(|&(run, code)| {
+            let bits = u16::from_str_radix(code, 2).unwrap();
+            ((code.len() as u8, bits), run)
+        })
+        .collect()
+}
+
+fn tables() -> &'static [CodeTable; 2] {
+    static TABLES: OnceLock<[CodeTable; 2]> = OnceLock::new();
+    TABLES.get_or_init(|| [code_table(WHITE), code_table(BLACK)])
+}
+
+enum Code {
+    Run(usize),
+    EndOfLine,
+}
+
+/// Reads the data one bit at a time, the most significant bit of each byte first.
+struct Bits {
+    byte: u8,
+    pos: usize,
+}
+
+impl Bits {
+    fn next<R: Read>(&mut self, data: &mut R) -> io::Result<u16> {
+        if self.pos.is_multiple_of(8) {
+            let mut byte = [0];
+            data.read_exact(&mut byte).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The Huffman encoded data ends before the image",
+                ),
+                _ => e,
+            })?;
+            self.byte = byte[0];
+        }
+        let bit = (self.byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit as u16)
+    }
+
+    /// Reads one code of `table`, or an end of line.
+    fn code<R: Read>(&mut self, data: &mut R, table: &CodeTable) -> io::Result<Code> {
+        let start = self.pos;
+        let (mut len, mut bits) = (0, 0);
+        loop {
+            bits = bits << 1 | self.next(data)?;
+            len += 1;
+            if let Some(&run) = table.get(&(len, bits)) {
+                return Ok(Code::Run(run as usize));
+            }
+            if bits == 0 && len == EOL_ZEROS {
+                // Fill bits may add any number of zeros before the final one
+                while self.next(data)? == 0 {}
+                return Ok(Code::EndOfLine);
+            }
+            if len == MAX_CODE_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("No Huffman code matches the bits at {}", start),
+                ));
+            }
+        }
+    }
+
+    /// Reads the makeup codes and the terminating code of one run.
+    fn run<R: Read>(&mut self, data: &mut R, table: &CodeTable) -> io::Result<Code> {
+        let mut total = 0;
+        loop {
+            match self.code(data, table)? {
+                Code::Run(run) if run >= 64 => total += run,
+                Code::Run(run) => return Ok(Code::Run(total + run)),
+                Code::EndOfLine => return Ok(Code::EndOfLine),
+            }
+        }
+    }
+}
+
+/// Expands Huffman 1D encoded data into one index per pixel, in file order.
+///
+/// Runs beyond the width of the image are cut off, and pixels after an early
+/// end of line stay white.
+pub fn decode(mut data: &[u8], width: usize, height: usize) -> BmpResult<Vec<u8>> {
+    let mut indexes = vec![0u8; width * height];
+    let mut rows = Rows::new();
+    for row in indexes.chunks_exact_mut(width.max(1)).take(height) {
+        rows.next_row(&mut data, &mut row[..width])?;
+    }
+    Ok(indexes)
+}
+
+/// Expands Huffman 1D encoded data one row of indexes at a time, reading no
+/// further than the byte holding the last code of that row.
+pub struct Rows {
+    bits: Bits,
+}
+
+impl Rows {
+    pub fn new() -> Rows {
+        Rows {
+            bits: Bits { byte: 0, pos: 0 },
+        }
+    }
+
+    /// Fills `row` with the indexes of the next row, as `decode` does.
+    pub fn next_row<R: Read>(&mut self, data: &mut R, row: &mut [u8]) -> BmpResult<()> {
+        let [white, black] = tables();
+        let width = row.len();
+        row.fill(0);
+        let (mut x, mut is_black) = (0, false);
+        while x < width {
+            let table = if is_black { black } else { white };
+            match self.bits.run(data, table)? {
+                Code::Run(run) => {
+                    let end = (x + run).min(width);
+                    if is_black {
+                        row[x..end].fill(1);
+                    }
+                    x = end;
+                    is_black = !is_black;
+                }
+                // Ends of line before the first run only mark where the row starts
+                Code::EndOfLine if x == 0 && !is_black => (),
+                Code::EndOfLine => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_prefix_free() {
+        for codes in [WHITE, BLACK] {
+            let all: Vec<&str> = codes.iter().chain(EXTENDED).map(|c| c.1).collect();
+            for (i, a) in all.iter().enumerate() {
+                assert!(a.len() as u8 <= MAX_CODE_LEN);
+                assert!(a.contains('1'));
+                for b in &all[i + 1..] {
+                    assert!(!a.starts_with(b) && !b.starts_with(a), "{} {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn runs_alternate_starting_with_white() {
+        // EOL, white 3, black 2, white 3
+        let data = [0b0000_0000, 0b0001_1000, 0b1110_0000];
+        assert_eq!(decode(&data, 8, 1).unwrap(), [0, 0, 0, 1, 1, 0, 0, 0]);
+        // EOL, white 0, black 64 + 6
+        let data = [
+            0b0000_0000,
+            0b0001_0011,
+            0b0101_0000,
+            0b0011_1100,
+            0b1000_0000,
+        ];
+        assert_eq!(decode(&data, 70, 1).unwrap(), [1; 70]);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        assert!(decode(&[0b1000_1100], 8, 1).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Writing of Windows icon files, as used for favicons.
+//!
+//! ```no_run
+//! use std::fs;
+//! use two::ico;
+//!
+//! let logo = two::open("logo.bmp").unwrap();
+//! fs::write("favicon.ico", ico::favicon_bundle(&logo)).unwrap();
+//! ```
+
+use std::io;
+
+use crate::{wire, BmpDibHeader, Image, Pixel};
+
+/// The sizes browsers pick favicons from.
+pub const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+
+// Sizes of the icon directory and of each of its entries
+const DIR_SIZE: usize = 6;
+const ENTRY_SIZE: usize = 16;
+
+/// Creates an icon file holding `img` at 16x16, 32x32 and 48x48 pixels.
+///
+/// Images that are not square are scaled to fit and centered on a transparent
+/// background. The icons are stored as 32 bpp with alpha, so no colors are lost
+/// to a palette.
+pub fn favicon_bundle(img: &Image) -> Vec<u8> {
+    let icons: Vec<Image> = FAVICON_SIZES.iter().map(|&size| fit(img, size)).collect();
+    let mut ico = Vec::new();
+    write_ico(&mut ico, &icons).expect("writing to a Vec cannot fail");
+    ico
+}
+
+/// Writes `icons` as the images of an icon file, each at most 256 pixels wide and high.
+fn write_ico(ico: &mut Vec<u8>, icons: &[Image]) -> io::Result<()> {
+    let bitmaps = icons
+        .iter()
+        .map(icon_bitmap)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    ico.extend_from_slice(&[0, 0, 1, 0]); // reserved, type 1 for icons
+    ico.extend_from_slice(&(icons.len() as u16).to_le_bytes());
+    let mut offset = DIR_SIZE + ENTRY_SIZE * icons.len();
+    for (icon, bitmap) in icons.iter().zip(bitmaps.iter()) {
+        // 256 pixels are stored as 0
+        ico.push(icon.width as u8);
+        ico.push(icon.height as u8);
+        ico.extend_from_slice(&[0, 0]); // no palette, reserved
+        ico.extend_from_slice(&1u16.to_le_bytes()); // planes
+        ico.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+        ico.extend_from_slice(&(bitmap.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += bitmap.len();
+    }
+    for bitmap in bitmaps {
+        ico.extend_from_slice(&bitmap);
+    }
+    Ok(())
+}
+
+/// Encodes `icon` as a BMP without file header, followed by the 1 bpp mask of
+/// its transparent pixels, which old readers use instead of the alpha channel.
+fn icon_bitmap(icon: &Image) -> io::Result<Vec<u8>> {
+    let (width, height) = (icon.width, icon.height);
+    let mask_row = (width as usize).div_ceil(32) * 4;
+    let data_size = width * height * 4 + (mask_row as u32) * height;
+    // The height covers both the colors and the mask
+    let dib_header = BmpDibHeader {
+        header_size: wire::DIB_HEADER_SIZE,
+        width: width as i32,
+        height: 2 * height as i32,
+        num_planes: 1,
+        bits_per_pixel: 32,
+        compress_type: 0,
+        data_size,
+        hres: 0,
+        vres: 0,
+        num_colors: 0,
+        num_imp_colors: 0,
+    };
+    let mut bitmap = Vec::with_capacity(wire::DIB_HEADER_SIZE as usize + data_size as usize);
+    wire::write_dib_header(&mut bitmap, &dib_header)?;
+
+    let alpha = |i: usize| icon.alpha.as_ref().map_or(255, |alpha| alpha[i]);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let i = icon.index(x, y);
+            let px = icon.data[i];
+            bitmap.extend_from_slice(&[px.b, px.g, px.r, alpha(i)]);
+        }
+    }
+    for y in (0..height).rev() {
+        let mut mask = vec![0u8; mask_row];
+        for x in 0..width {
+            if alpha(icon.index(x, y)) < 128 {
+                mask[x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        bitmap.extend_from_slice(&mask);
+    }
+    Ok(bitmap)
+}
+
+/// Scales `img` to fit into a `size` by `size` square, averaging the source
+/// pixels covered by each output pixel.
+fn fit(img: &Image, size: u32) -> Image {
+    let mut out = Image::new(size, size);
+    let mut alpha = vec![0u8; (size * size) as usize];
+    let longer = img.width.max(img.height);
+    if longer == 0 {
+        out.alpha = Some(alpha);
+        return out;
+    }
+
+    let scale = longer as f32 / size as f32;
+    let w = ((img.width as f32 / scale).round() as u32).clamp(1, size);
+    let h = ((img.height as f32 / scale).round() as u32).clamp(1, size);
+    let (left, top) = ((size - w) / 2, (size - h) / 2);
+    for y in 0..h {
+        for x in 0..w {
+            // The source pixels covered by the output pixel, at least one
+            let x0 = (x as f32 * scale) as u32;
+            let y0 = (y as f32 * scale) as u32;
+            let x1 = (((x + 1) as f32 * scale) as u32).clamp(x0 + 1, img.width);
+            let y1 = (((y + 1) as f32 * scale) as u32).clamp(y0 + 1, img.height);
+
+            let mut sums = [0u64; 4];
+            for sy in y0..y1 {
+                for sx in x0..x1 {
+                    let i = img.index(sx, sy);
+                    let px = img.data[i];
+                    let a = img.alpha.as_ref().map_or(255, |alpha| alpha[i]) as u64;
+                    // Weighted by alpha so transparent colors do not bleed in
+                    sums[0] += px.r as u64 * a;
+                    sums[1] += px.g as u64 * a;
+                    sums[2] += px.b as u64 * a;
+                    sums[3] += a;
+                }
+            }
+            let n = ((x1 - x0) * (y1 - y0)) as u64;
+            let c = |sum: u64| match sums[3] {
+                0 => 0,
+                total => ((sum + total / 2) / total) as u8,
+            };
+            out.set_pixel(left + x, top + y, px!(c(sums[0]), c(sums[1]), c(sums[2])));
+            alpha[((top + y) * size + left + x) as usize] = ((sums[3] + n / 2) / n) as u8;
+        }
+    }
+    out.alpha = Some(alpha);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    fn u32_at(bytes: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+    }
+
+    #[test]
+    fn bundle_holds_the_favicon_sizes() {
+        let mut img = Image::new(64, 64);
+        img.fill(consts::RED);
+        let ico = favicon_bundle(&img);
+
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 3, 0]);
+        for (i, &size) in FAVICON_SIZES.iter().enumerate() {
+            let entry = &ico[DIR_SIZE + i * ENTRY_SIZE..];
+            assert_eq!((entry[0] as u32, entry[1] as u32), (size, size));
+            let (len, offset) = (u32_at(entry, 8) as usize, u32_at(entry, 12) as usize);
+
+            let bitmap = &ico[offset..offset + len];
+            assert_eq!(u32_at(bitmap, 4), size);
+            assert_eq!(u32_at(bitmap, 8), 2 * size);
+            // The bottom left pixel, opaque red
+            assert_eq!(&bitmap[40..44], &[0, 0, 255, 255]);
+        }
+        let last = u32_at(&ico, DIR_SIZE + 2 * ENTRY_SIZE + 8) as usize;
+        let offset = u32_at(&ico, DIR_SIZE + 2 * ENTRY_SIZE + 12) as usize;
+        assert_eq!(ico.len(), offset + last);
+    }
+
+    #[test]
+    fn wide_images_are_centered_on_transparency() {
+        let mut img = Image::new(32, 16);
+        img.fill(consts::BLUE);
+        let icon = fit(&img, 16);
+        let alpha = icon.alpha.as_ref().unwrap();
+        assert_eq!(alpha[icon.index(0, 0)], 0);
+        assert_eq!(alpha[icon.index(0, 4)], 255);
+        assert_eq!(icon.get_pixel(15, 8), consts::BLUE);
+        assert_eq!(alpha[icon.index(15, 12)], 0);
+
+        // Transparent pixels are masked
+        let bitmap = icon_bitmap(&icon).unwrap();
+        let mask = &bitmap[40 + 16 * 16 * 4..];
+        assert_eq!(&mask[..4], &[0xFF, 0xFF, 0, 0]);
+        assert_eq!(&mask[4 * 8..4 * 9], &[0, 0, 0, 0]);
+    }
+}
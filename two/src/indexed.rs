@@ -0,0 +1,525 @@
+//! Images whose pixels are indexes into a palette.
+//!
+//! ```
+//! use two::{consts, IndexedImage};
+//!
+//! let palette = vec![consts::BLACK, consts::RED];
+//! let img = IndexedImage::from_vec(2, 1, palette, vec![0, 1]).unwrap();
+//! assert_eq!(img.to_image().get_pixel(1, 0), consts::RED);
+//! assert_eq!(img.to_image().palette(), Some(img.palette()));
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::buffer::PixelBuffer;
+use crate::geom::{Rect, Size};
+use crate::{encoder, EncoderOptions, Image, ImageIndex, Pixel};
+
+/// An image of up to 256 colors, stored as one palette index per pixel, row by
+/// row from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexedImage {
+    width: u32,
+    height: u32,
+    palette: Vec<Pixel>,
+    data: Vec<u8>,
+}
+
+impl IndexedImage {
+    /// Creates an image of the first color of `palette`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `palette` is empty or has more than 256 colors.
+    pub fn new(width: u32, height: u32, palette: Vec<Pixel>) -> IndexedImage {
+        assert!(
+            !palette.is_empty() && palette.len() <= 256,
+            "palette must have 1 to 256 colors"
+        );
+        IndexedImage {
+            width,
+            height,
+            palette,
+            data: vec![0; (width * height) as usize],
+        }
+    }
+
+    /// Creates an image from row-major indexes into `palette`.
+    ///
+    /// Returns `None` if `data` is not `width * height` long, if the palette has
+    /// more than 256 colors or if an index lies outside of it.
+    pub fn from_vec(
+        width: u32,
+        height: u32,
+        palette: Vec<Pixel>,
+        data: Vec<u8>,
+    ) -> Option<IndexedImage> {
+        if data.len() != (width * height) as usize
+            || palette.len() > 256
+            || data.iter().any(|&i| i as usize >= palette.len())
+        {
+            return None;
+        }
+
+        Some(IndexedImage {
+            width,
+            height,
+            palette,
+            data,
+        })
+    }
+
+    /// Returns the `width` of the image.
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the `height` of the image.
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn palette(&self) -> &[Pixel] {
+        &self.palette
+    }
+
+    /// Returns the palette index of the pixel at `(x, y)`.
+    #[inline]
+    pub fn get_index(&self, x: u32, y: u32) -> u8 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    /// Sets the pixel at `(x, y)` to palette entry `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` lies outside of the palette.
+    #[inline]
+    pub fn set_index(&mut self, x: u32, y: u32, index: u8) {
+        assert!(
+            (index as usize) < self.palette.len(),
+            "index outside of the palette"
+        );
+        self.data[(y * self.width + x) as usize] = index;
+    }
+
+    /// Sets every pixel in the `(x, y, width, height)` region to palette entry `index`.
+    ///
+    /// Any part of the region lying outside of the image is clipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` lies outside of the palette.
+    pub fn fill_index<R: Into<Rect>>(&mut self, rect: R, index: u8) {
+        assert!(
+            (index as usize) < self.palette.len(),
+            "index outside of the palette"
+        );
+        let rect = rect.into().clamp(Size::new(self.width, self.height));
+        let (x0, x1) = (rect.x as usize, rect.right() as usize);
+        for row in rect.y..rect.bottom() {
+            let start = (row * self.width) as usize;
+            self.data[start + x0..start + x1].fill(index);
+        }
+    }
+
+    /// Returns the color of the pixel at `(x, y)`.
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> Pixel {
+        self.palette[self.get_index(x, y) as usize]
+    }
+
+    #[inline]
+    pub fn coordinates(&self) -> ImageIndex {
+        ImageIndex::new(self.width, self.height)
+    }
+
+    /// Returns the palette indexes, row by row from the top.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Saves the image as an 8 bpp file with its palette.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut bmp_file = fs::File::create(path)?;
+        self.to_writer(&mut bmp_file)
+    }
+
+    pub fn to_writer<W: Write>(&self, destination: &mut W) -> io::Result<()> {
+        let bmp_data = encoder::encode_indexed(self, &EncoderOptions::default())?;
+        destination.write_all(&bmp_data)
+    }
+
+    /// Saves the image with its palette as `options` ask for, at 8 bpp unless
+    /// they set another depth.
+    pub fn save_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &EncoderOptions,
+    ) -> io::Result<()> {
+        fs::write(path, encoder::encode_indexed(self, options)?)
+    }
+
+    /// Like `save_with_options`, but writes to `destination`.
+    pub fn to_writer_with_options<W: Write>(
+        &self,
+        destination: &mut W,
+        options: &EncoderOptions,
+    ) -> io::Result<()> {
+        destination.write_all(&encoder::encode_indexed(self, options)?)
+    }
+
+    /// Expands the indexes into their colors, keeping the palette with the image.
+    pub fn to_image(&self) -> Image {
+        let mut img = Image::new(self.width, self.height);
+        for (px, &i) in img.data.iter_mut().zip(self.data.iter()) {
+            *px = self.palette[i as usize];
+        }
+        img.color_palette = Some(self.palette.clone());
+        img.indices = Some(self.data.clone());
+        img
+    }
+}
+
+impl Image {
+    /// Returns the palette indices the image was decoded from as an
+    /// `IndexedImage`, which is saved with exactly these indices and palette.
+    ///
+    /// Returns `None` unless the image has `indices`.
+    pub fn to_indexed(&self) -> Option<IndexedImage> {
+        let palette = self.palette()?.to_vec();
+        IndexedImage::from_vec(self.width, self.height, palette, self.indices()?.to_vec())
+    }
+
+    /// Saves the image with its palette, at the bit depth of the file it was
+    /// decoded from, so that an indexed file is written back as it was read.
+    ///
+    /// Every pixel must have the color of a palette entry. Fails with
+    /// `InvalidInput` if the image has no palette or a pixel was changed to
+    /// another color; `save` writes any image as 24 bpp.
+    pub fn save_with_palette<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let bmp_data = encoder::encode_with_palette(self)?;
+        fs::write(path, bmp_data)
+    }
+
+    /// Like `save_with_palette`, but writes to `destination`.
+    pub fn to_writer_with_palette<W: Write>(&self, destination: &mut W) -> io::Result<()> {
+        let bmp_data = encoder::encode_with_palette(self)?;
+        destination.write_all(&bmp_data)
+    }
+}
+
+impl<B: PixelBuffer> Image<B> {
+    /// Returns the image as indexes into a palette of at most 256 colors.
+    ///
+    /// Images of up to 256 colors keep them exactly, in the order they first
+    /// appear. Others are reduced by median cut: their colors are split into
+    /// 256 boxes, and every pixel gets the average color of its box.
+    pub fn quantize(&self) -> IndexedImage {
+        self.quantize_to(256)
+    }
+
+    /// Like `quantize`, with a palette of at most `max` colors.
+    pub(crate) fn quantize_to(&self, max: usize) -> IndexedImage {
+        let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+        let mut order = Vec::new();
+        for &px in self.pixels() {
+            let count = counts.entry(px.into()).or_insert_with(|| {
+                order.push(<[u8; 3]>::from(px));
+                0
+            });
+            *count += 1;
+        }
+
+        let boxes = if order.len() <= max {
+            order.iter().map(|&c| vec![(c, counts[&c])]).collect()
+        } else {
+            median_cut(order.iter().map(|&c| (c, counts[&c])).collect(), max)
+        };
+        let mut palette = Vec::with_capacity(boxes.len().max(1));
+        let mut entries = HashMap::with_capacity(order.len());
+        for (i, colors) in boxes.iter().enumerate() {
+            palette.push(average(colors));
+            entries.extend(colors.iter().map(|&(c, _)| (c, i as u8)));
+        }
+        if palette.is_empty() {
+            palette.push(px!(0, 0, 0));
+        }
+
+        let data = self
+            .pixels()
+            .iter()
+            .map(|&px| entries[&<[u8; 3]>::from(px)])
+            .collect();
+        IndexedImage {
+            width: self.width,
+            height: self.height,
+            palette,
+            data,
+        }
+    }
+
+    /// Maps every pixel to the nearest color of `palette`, which must have 1
+    /// to 256 of them.
+    pub(crate) fn map_to_palette(&self, palette: &[Pixel]) -> IndexedImage {
+        let distance = |a: Pixel, b: Pixel| {
+            let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+            d(a.r, b.r) + d(a.g, b.g) + d(a.b, b.b)
+        };
+        let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+        let data = self
+            .pixels()
+            .iter()
+            .map(|&px| {
+                *nearest.entry(px.into()).or_insert_with(|| {
+                    (0..palette.len())
+                        .min_by_key(|&i| distance(px, palette[i]))
+                        .unwrap_or(0) as u8
+                })
+            })
+            .collect();
+        IndexedImage {
+            width: self.width,
+            height: self.height,
+            palette: palette.to_vec(),
+            data,
+        }
+    }
+
+    /// Saves the image as an 8 bpp file with a palette of at most 256 colors,
+    /// quantized by `quantize` if the image has more.
+    pub fn save_as_8bpp<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.quantize().save(path)
+    }
+
+    /// Like `save_as_8bpp`, but writes to `destination`.
+    pub fn to_writer_as_8bpp<W: Write>(&self, destination: &mut W) -> io::Result<()> {
+        self.quantize().to_writer(destination)
+    }
+}
+
+/// Distinct colors and how many pixels have them.
+type ColorBox = Vec<([u8; 3], u32)>;
+
+/// Splits `colors` into at most `max` boxes, each time halving the box whose
+/// widest channel range times its number of pixels is the largest, at the
+/// median pixel along that channel.
+fn median_cut(colors: ColorBox, max: usize) -> Vec<ColorBox> {
+    let mut boxes = vec![colors];
+    while boxes.len() < max {
+        // Distinct colors always differ in some channel
+        let largest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| {
+                let (channel, range) = (0..3)
+                    .map(|ch| {
+                        let values = colors.iter().map(|c| c.0[ch]);
+                        let range = values.clone().max().unwrap() - values.min().unwrap();
+                        (ch, range)
+                    })
+                    .max_by_key(|&(_, range)| range)
+                    .unwrap();
+                let pixels: u64 = colors.iter().map(|c| c.1 as u64).sum();
+                (i, channel, range as u64 * pixels)
+            })
+            .max_by_key(|&(_, _, range)| range);
+        let (i, channel, _) = match largest {
+            Some(largest) => largest,
+            None => break,
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by_key(|c| c.0[channel]);
+        let total: u64 = colors.iter().map(|c| c.1 as u64).sum();
+        let mut seen = 0;
+        let median = colors
+            .iter()
+            .position(|c| {
+                seen += c.1 as u64;
+                seen * 2 >= total
+            })
+            .unwrap_or(0);
+        let upper = colors.split_off((median + 1).clamp(1, colors.len() - 1));
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+    boxes
+}
+
+/// Returns the mean color of the pixels in `colors`.
+fn average(colors: &[([u8; 3], u32)]) -> Pixel {
+    let mut sums = [0u64; 3];
+    let mut total = 0u64;
+    for &(c, count) in colors {
+        for ch in 0..3 {
+            sums[ch] += c[ch] as u64 * count as u64;
+        }
+        total += count as u64;
+    }
+    let mean = |ch: usize| ((sums[ch] + total / 2) / total.max(1)) as u8;
+    px!(mean(0), mean(1), mean(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts, Compression};
+
+    #[test]
+    fn from_vec_checks_the_indexes() {
+        let palette = vec![consts::BLACK, consts::WHITE];
+        assert!(IndexedImage::from_vec(2, 2, palette.clone(), vec![0, 1, 1, 0]).is_some());
+        assert!(IndexedImage::from_vec(2, 2, palette.clone(), vec![0, 1, 1]).is_none());
+        assert!(IndexedImage::from_vec(2, 2, palette, vec![0, 1, 2, 0]).is_none());
+        assert!(IndexedImage::from_vec(1, 1, vec![consts::RED; 257], vec![0]).is_none());
+    }
+
+    #[test]
+    fn edited_indexes_are_saved_as_8_bpp() {
+        let palette = vec![consts::BLACK, consts::RED, consts::BLUE];
+        let mut img = IndexedImage::new(5, 3, palette);
+        img.fill_index((1, 1, 10, 1), 2);
+        img.set_index(0, 0, 1);
+        assert_eq!(img.get_index(4, 1), 2);
+        assert_eq!(img.get_index(0, 1), 0);
+
+        let mut bytes = Vec::new();
+        img.to_writer(&mut bytes).unwrap();
+        let decoded = crate::from_reader(&mut io::Cursor::new(bytes)).unwrap();
+        assert_eq!(decoded.metadata().bits_per_pixel, 8);
+        assert_eq!(decoded.palette(), Some(img.palette()));
+        assert_eq!(decoded.data, img.to_image().data);
+    }
+
+    #[test]
+    fn decoded_palettes_are_written_back_unchanged() {
+        for name in ["pal1", "pal4", "pal8", "pal8w126"] {
+            let path = format!("test/bmpsuite-2.5/g/{}.bmp", name);
+            let img = crate::open(&path).unwrap();
+            let mut bytes = Vec::new();
+            img.to_writer_with_palette(&mut bytes).unwrap();
+            let decoded = crate::from_bytes(&bytes).unwrap();
+            let (meta, saved) = (img.metadata(), decoded.metadata());
+            assert_eq!(saved.bits_per_pixel, meta.bits_per_pixel, "{}", name);
+            assert_eq!(decoded.palette(), img.palette(), "{}", name);
+            assert_eq!(decoded.data, img.data, "{}", name);
+            assert_eq!(bytes.len(), fs::metadata(&path).unwrap().len() as usize);
+        }
+
+        let mut img = crate::open("test/bmpsuite-2.5/g/pal4.bmp").unwrap();
+        let color = img.palette().unwrap()[3];
+        img.set_pixel(0, 0, color);
+        let mut bytes = Vec::new();
+        img.to_writer_with_palette(&mut bytes).unwrap();
+        assert_eq!(crate::from_bytes(&bytes).unwrap().get_pixel(0, 0), color);
+
+        img.set_pixel(1, 0, Pixel::new(1, 2, 3));
+        let err = img.to_writer_with_palette(&mut bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = Image::new(1, 1)
+            .to_writer_with_palette(&mut bytes)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let mut img = Image::new(4, 2);
+        img.set_pixel(1, 0, consts::RED);
+        img.set_pixel(3, 1, consts::BLUE);
+        let indexed = img.quantize();
+        assert_eq!(
+            indexed.palette(),
+            &[consts::BLACK, consts::RED, consts::BLUE]
+        );
+        assert_eq!(indexed.as_slice(), &[0, 1, 0, 0, 0, 0, 0, 2]);
+
+        let mut bytes = Vec::new();
+        img.to_writer_as_8bpp(&mut bytes).unwrap();
+        let decoded = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata().bits_per_pixel, 8);
+        assert_eq!(decoded.data, img.data);
+        // A third of the size of 24 bpp, plus the palette
+        assert_eq!(bytes.len(), 54 + 3 * 4 + 2 * 4);
+    }
+
+    #[test]
+    fn many_colors_are_quantized_to_256() {
+        let img = crate::open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        let indexed = img.quantize();
+        assert_eq!(indexed.palette().len(), 256);
+
+        let quantized = indexed.to_image();
+        let error: u32 = img
+            .pixels()
+            .iter()
+            .zip(quantized.pixels())
+            .map(|(a, b)| {
+                a.r.abs_diff(b.r) as u32 + a.g.abs_diff(b.g) as u32 + a.b.abs_diff(b.b) as u32
+            })
+            .sum();
+        let mean = error as f32 / (3 * img.pixels().len()) as f32;
+        assert!(mean < 6.0, "{}", mean);
+    }
+
+    #[test]
+    fn rle8_keeps_every_index() {
+        let palette = (0..=255).map(|i| px!(i, 0, 255 - i)).collect();
+        let mut img = IndexedImage::new(300, 4, palette);
+        for x in 0..300 {
+            // Runs of one, runs of two and no runs at all
+            img.set_index(x, 1, (x % 7) as u8);
+            img.set_index(x, 2, (x / 2) as u8);
+            img.set_index(x, 3, (x * 3 % 256) as u8);
+        }
+        let options = EncoderOptions::default().compression(Compression::Rle8);
+        let mut bytes = Vec::new();
+        img.to_writer_with_options(&mut bytes, &options).unwrap();
+
+        let decoded = crate::decode_untrusted(&bytes).unwrap();
+        let meta = decoded.metadata();
+        assert_eq!((meta.bits_per_pixel, meta.compression), (8, 1));
+        assert_eq!(meta.data_size as usize, bytes.len() - 54 - 256 * 4);
+        assert_eq!(decoded.data, img.to_image().data);
+    }
+
+    #[test]
+    fn rle8_shrinks_flat_images() {
+        let mut img = Image::new(200, 100);
+        img.fill_rect((20, 10, 50, 50), consts::RED);
+        let options = EncoderOptions::default().compression(Compression::Rle8);
+        let mut bytes = Vec::new();
+        img.to_writer_with_options(&mut bytes, &options).unwrap();
+        assert!(bytes.len() < 2000, "{}", bytes.len());
+
+        let decoded = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata().compression, 1);
+        assert_eq!(decoded.data, img.data);
+    }
+
+    #[test]
+    fn to_image_expands_the_palette() {
+        let palette = vec![consts::BLUE, consts::RED, consts::WHITE];
+        let img = IndexedImage::from_vec(3, 1, palette, vec![2, 0, 1]).unwrap();
+        assert_eq!(img.get_pixel(0, 0), consts::WHITE);
+
+        let rgb = img.to_image();
+        assert_eq!(rgb.get_pixel(1, 0), consts::BLUE);
+        assert_eq!(rgb.get_pixel(2, 0), consts::RED);
+        assert_eq!(rgb.palette().map(|p| p.len()), Some(3));
+        assert_eq!(rgb.to_indexed(), Some(img));
+        assert_eq!(
+            IndexedImage::new(2, 2, vec![consts::RED]).get_pixel(1, 1),
+            consts::RED
+        );
+    }
+}
@@ -1,5 +1,6 @@
 #![deny(warnings)]
 #![cfg_attr(test, deny(warnings))]
+#![allow(clippy::duplicated_attributes)]
 
 //! A small library for reading and writing BMP images.
 //!
@@ -8,10 +9,13 @@
 //!
 //! |Scheme | Decoding | Encoding | Compression |
 //! |-------|----------|----------|-------------|
+//! | 64 bpp| ✓        | ✗        | No          |
+//! | 32 bpp| ✓        | ✓        | Bitfields   |
 //! | 24 bpp| ✓        | ✓        | No          |
-//! | 8 bpp | ✓        | ✗        | No          |
-//! | 4 bpp | ✓        | ✗        | No          |
-//! | 1 bpp | ✓        | ✗        | No          |
+//! | 16 bpp| ✓        | ✗        | Bitfields   |
+//! | 8 bpp | ✓        | ✓        | RLE8        |
+//! | 4 bpp | ✓        | ✓        | RLE4        |
+//! | 1 bpp | ✓        | ✓        | Huffman 1D  |
 //!
 //! # Example
 //!
@@ -41,9 +45,18 @@ use std::io::{Cursor, Read, Write};
 use std::iter::Iterator;
 use std::path::Path;
 
+use buffer::PixelBuffer;
+use geom::{Point, Rect, Size};
+use policy::ColorPolicy;
 
 // Expose decoder's public types, structs, and enums
-pub use decoder::{BmpError, BmpErrorKind, BmpResult};
+pub use cancel::CancellationToken;
+pub use decoder::{BmpError, BmpErrorKind, BmpResult, BmpWarning};
+pub use gray::{GrayImage, GrayImage16};
+pub use indexed::IndexedImage;
+pub use os2::open_array;
+pub use pack::RowOrder;
+pub use region::open_region;
 
 #[macro_export]
 macro_rules! px {
@@ -56,18 +69,44 @@ macro_rules! px {
     };
 }
 
-macro_rules! file_size {
-    ($bpp:expr, $width:expr, $height:expr) => {{
-        let head_size = 2 + 12 + 40;
-        let row_size = (($bpp as f32 * $width as f32 + 31.0) / 32.0).floor() as u32 * 4;
-        (head_size as u32, $height as u32 * row_size)
-    }};
-}
-
+pub mod accumulate;
+pub mod analysis;
+pub mod annotate;
+#[cfg(feature = "ndarray")]
+pub mod array;
+pub mod buffer;
+pub mod cache;
+pub mod cancel;
+pub mod colormap;
+pub mod compare;
 pub mod consts;
+pub mod delta;
+pub mod draw;
+pub mod geom;
+pub mod gray;
+pub mod history;
+pub mod ico;
+pub mod indexed;
+pub mod merge;
+pub mod os2;
+pub mod pack;
+pub mod perf;
+pub mod policy;
+pub mod preserve;
+pub mod raw;
+pub mod region;
+pub mod sample;
+pub mod sequence;
+pub mod stream;
+pub mod tensor;
+pub mod tile;
+pub mod warp;
+pub mod wide;
 
 mod decoder;
 mod encoder;
+mod huffman;
+mod wire;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Pixel {
@@ -80,6 +119,51 @@ impl Pixel {
     pub fn new(r: u8, g: u8, b: u8) -> Pixel {
         Pixel { r, g, b }
     }
+
+    /// Creates a pixel from channels in `0.0..=1.0`, values outside are clamped
+    /// and NaN becomes 0.
+    pub fn from_f32(r: f32, g: f32, b: f32) -> Pixel {
+        Pixel::from_f32_with(r, g, b, ColorPolicy::default())
+    }
+
+    /// Creates a pixel from channels in `0.0..=1.0`, converted with `policy`.
+    pub fn from_f32_with(r: f32, g: f32, b: f32, policy: ColorPolicy) -> Pixel {
+        let c = |v: f32| policy.unit_to_u8(v);
+        Pixel::new(c(r), c(g), c(b))
+    }
+
+    /// Returns the channels scaled to `0.0..=1.0`.
+    pub fn to_f32(self) -> [f32; 3] {
+        [
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+        ]
+    }
+}
+
+impl From<[u8; 3]> for Pixel {
+    fn from(rgb: [u8; 3]) -> Pixel {
+        Pixel::new(rgb[0], rgb[1], rgb[2])
+    }
+}
+
+impl From<(u8, u8, u8)> for Pixel {
+    fn from((r, g, b): (u8, u8, u8)) -> Pixel {
+        Pixel::new(r, g, b)
+    }
+}
+
+impl From<Pixel> for [u8; 3] {
+    fn from(px: Pixel) -> [u8; 3] {
+        [px.r, px.g, px.b]
+    }
+}
+
+impl From<Pixel> for (u8, u8, u8) {
+    fn from(px: Pixel) -> (u8, u8, u8) {
+        (px.r, px.g, px.b)
+    }
 }
 
 impl fmt::Display for Pixel {
@@ -100,12 +184,21 @@ impl fmt::UpperHex for Pixel {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum BmpVersion {
+/// The version of a file, told by the size of its DIB header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BmpVersion {
+    /// The 12 byte header of Windows 2 and OS/2 1.
     Two,
+    /// The 40 byte header, the most common one.
+    #[default]
     Three,
+    /// The 40 byte header followed by bitfield masks.
     ThreeNT,
+    /// The 64 byte header of OS/2 2.
+    Os2Two,
+    /// The 108 byte header, adding masks and a color space.
     Four,
+    /// The 124 byte header, adding ICC profiles.
     Five,
 }
 
@@ -115,6 +208,7 @@ impl BmpVersion {
             12 => Some(BmpVersion::Two),
             40 if dib_header.compress_type == 3 => Some(BmpVersion::ThreeNT),
             40 => Some(BmpVersion::Three),
+            64 => Some(BmpVersion::Os2Two),
             108 => Some(BmpVersion::Four),
             124 => Some(BmpVersion::Five),
             _ => None,
@@ -128,6 +222,7 @@ impl AsRef<str> for BmpVersion {
             BmpVersion::Two => "BMP Version 2",
             BmpVersion::Three => "BMP Version 3",
             BmpVersion::ThreeNT => "BMP Version 3 NT",
+            BmpVersion::Os2Two => "OS/2 BMP Version 2",
             BmpVersion::Four => "BMP Version 4",
             BmpVersion::Five => "BMP Version 5",
         }
@@ -141,6 +236,9 @@ enum CompressionType {
     Rle4bit,
     // Only for BMP version 4
     BitfieldsEncoding,
+    // The pixel data is a whole JPEG or PNG file, meant for printers
+    Jpeg,
+    Png,
 }
 
 impl CompressionType {
@@ -149,6 +247,8 @@ impl CompressionType {
             1 => CompressionType::Rle8bit,
             2 => CompressionType::Rle4bit,
             3 => CompressionType::BitfieldsEncoding,
+            4 => CompressionType::Jpeg,
+            5 => CompressionType::Png,
             _ => CompressionType::Uncompressed,
         }
     }
@@ -160,6 +260,8 @@ impl AsRef<str> for CompressionType {
             CompressionType::Rle8bit => "RLE 8-bit",
             CompressionType::Rle4bit => "RLE 4-bit",
             CompressionType::BitfieldsEncoding => "Bitfields Encoding",
+            CompressionType::Jpeg => "JPEG",
+            CompressionType::Png => "PNG",
             CompressionType::Uncompressed => "Uncompressed",
         }
     }
@@ -200,8 +302,17 @@ struct BmpDibHeader {
 }
 
 impl BmpDibHeader {
+    /// Returns whether the rows are stored from the bottom, as is usual,
+    /// which is signalled by a positive height.
+    #[inline]
+    fn is_bottom_up(&self) -> bool {
+        self.height > 0
+    }
+
     fn new(width: i32, height: i32) -> BmpDibHeader {
-        let (_, pixel_array_size) = file_size!(24, width, height);
+        // Zero is allowed for uncompressed data, and left for images too large for a file
+        let (_, pixel_array_size) =
+            decoder::file_size(24, width.unsigned_abs(), height.unsigned_abs()).unwrap_or_default();
         BmpDibHeader {
             header_size: 40,
             width,
@@ -218,37 +329,969 @@ impl BmpDibHeader {
     }
 }
 
+/// How the colors of a version 4 or 5 header are to be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpaceType {
+    /// Calibrated by the endpoints and gammas.
+    Calibrated,
+    Srgb,
+    /// The default color space of the system, in practice sRGB.
+    WindowsDefault,
+    /// Given by a profile in another file, see `Image::linked_icc_profile`.
+    Linked,
+    /// Given by a profile in the file, see `Image::icc_profile`.
+    Embedded,
+    Other(u32),
+}
+
+/// The color space fields of a version 4 or 5 header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorSpaceInfo {
+    pub cs_type: ColorSpaceType,
+    /// The CIE XYZ coordinates of the red, green and blue endpoints, only
+    /// meaningful for calibrated color spaces.
+    pub endpoints: [[f32; 3]; 3],
+    /// The gamma of the red, green and blue channels, only meaningful for
+    /// calibrated color spaces.
+    pub gamma: [f32; 3],
+}
+
+/// The color space fields of a version 4 or 5 header, as stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ColorSpaceFields {
+    cs_type: u32,
+    endpoints: [i32; 9],
+    gamma: [u32; 3],
+}
+
+/// How the alpha channel of 16 and 32 bpp images is decoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Keeps alpha given by a mask, or the fourth byte of plain 32 bpp pixels,
+    /// unless it is zero everywhere. Such images are meant to be opaque.
+    #[default]
+    Auto,
+    /// Keeps alpha even if it is zero everywhere.
+    Keep,
+    /// Discards any alpha.
+    Ignore,
+}
+
+/// How forgiving decoding is. The default decodes like `open`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    pub alpha: AlphaMode,
+    /// Fills the rows missing from a short pixel array with black instead of
+    /// failing. Run-length encoded data is not affected.
+    pub allow_truncated: bool,
+    /// The interleaving of the bytes returned by `from_bytes_packed`.
+    pub layout: pack::Layout,
+    pub limits: Limits,
+    /// Keeps the palette index of every pixel of 1, 2, 4 and 8 bpp files, see
+    /// `Image::indices`.
+    pub keep_indices: bool,
+    /// Flips decoded images upside down, so that the first row is the bottom
+    /// one. Only `Image`s are affected.
+    pub flip_vertical: bool,
+}
+
+impl DecodeOptions {
+    /// Sets how the alpha channel is decoded.
+    pub fn alpha(mut self, mode: AlphaMode) -> DecodeOptions {
+        self.alpha = mode;
+        self
+    }
+
+    /// Sets whether rows missing from a short pixel array are filled with black.
+    pub fn allow_truncated(mut self, allow: bool) -> DecodeOptions {
+        self.allow_truncated = allow;
+        self
+    }
+
+    /// Sets the interleaving of the bytes returned by `from_bytes_packed`.
+    pub fn output_layout(mut self, layout: pack::Layout) -> DecodeOptions {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets the largest images that are decoded.
+    pub fn limits(mut self, limits: Limits) -> DecodeOptions {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether the palette indices of indexed files are kept.
+    pub fn keep_indices(mut self, keep: bool) -> DecodeOptions {
+        self.keep_indices = keep;
+        self
+    }
+
+    /// Sets whether decoded images are flipped upside down.
+    pub fn flip_vertical(mut self, flip: bool) -> DecodeOptions {
+        self.flip_vertical = flip;
+        self
+    }
+}
+
+/// How the pixels of saved files are compressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Rows stored as they are.
+    #[default]
+    None,
+    /// Runs of 8 bpp palette indexes. Images of more than 256 colors are
+    /// quantized first, see `Image::quantize`.
+    Rle8,
+}
+
+/// How images are saved. The default saves like `save`.
+///
+/// ```no_run
+/// use two::{EncoderOptions, Image};
+///
+/// let img = Image::new(32, 32);
+/// let options = EncoderOptions::default()
+///     .bits_per_pixel(4)
+///     .dpi(96.0, 96.0)
+///     .hotspot(16, 16);
+/// img.save_with_options("cursor.bmp", &options).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EncoderOptions {
+    /// The bit depth written: 1, 2, 4 or 8 with a palette, 24 or 32. Left
+    /// unset, images are written as 24 bpp, as 32 bpp if they keep alpha in a
+    /// newer header, or as 8 bpp with `Rle8`.
+    pub bits_per_pixel: Option<u16>,
+    pub compression: Compression,
+    /// Stores the rows from the top, with a negative height, as some display
+    /// drivers and DirectDraw surfaces expect. Not possible with `Rle8`.
+    pub top_down: bool,
+    /// The header written: `Three`, `Four` or `Five`. The newer two tag the
+    /// pixels as sRGB and keep alpha, as 32 bpp with channel masks.
+    pub version: BmpVersion,
+    /// An ICC profile embedded after the pixels. Only version 5 headers have
+    /// room for it, so it is written with one whatever `version` says.
+    pub icc_profile: Option<Vec<u8>>,
+    /// The horizontal and vertical resolution in pixels per meter, instead of
+    /// that of the image.
+    pub resolution: Option<(i32, i32)>,
+    /// The palette of 1 to 256 colors of files of 8 bpp or less, every pixel
+    /// taking the nearest one. Left unset, the colors are quantized to fit.
+    pub palette: Option<Vec<Pixel>>,
+    /// The hotspot kept in the reserved fields, instead of that of the image.
+    pub hotspot: Option<(u16, u16)>,
+}
+
+impl EncoderOptions {
+    /// Sets the bit depth written.
+    pub fn bits_per_pixel(mut self, bpp: u16) -> EncoderOptions {
+        self.bits_per_pixel = Some(bpp);
+        self
+    }
+
+    /// Sets how the pixels are compressed.
+    pub fn compression(mut self, compression: Compression) -> EncoderOptions {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets whether the rows are stored from the top.
+    pub fn top_down(mut self, top_down: bool) -> EncoderOptions {
+        self.top_down = top_down;
+        self
+    }
+
+    /// Sets the version of the header written.
+    pub fn version(mut self, version: BmpVersion) -> EncoderOptions {
+        self.version = version;
+        self
+    }
+
+    /// Embeds the ICC profile in `bytes`, tagging the pixels with it
+    /// instead of sRGB.
+    pub fn icc_profile(mut self, bytes: Vec<u8>) -> EncoderOptions {
+        self.icc_profile = Some(bytes);
+        self
+    }
+
+    /// Sets the resolution to `x` by `y` dots per inch, rounded to whole
+    /// pixels per meter as `Image::set_dpi` does.
+    pub fn dpi(mut self, x: f32, y: f32) -> EncoderOptions {
+        self.resolution = Some((dpi_to_ppm(x), dpi_to_ppm(y)));
+        self
+    }
+
+    /// Sets the palette the pixels are mapped to.
+    pub fn palette(mut self, palette: Vec<Pixel>) -> EncoderOptions {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Sets the hotspot, as `Image::set_hotspot` does.
+    pub fn hotspot(mut self, x: u16, y: u16) -> EncoderOptions {
+        self.hotspot = Some((x, y));
+        self
+    }
+}
+
+fn dpi_to_ppm(dpi: f32) -> i32 {
+    (dpi / INCH_IN_METERS).round() as i32
+}
+
+/// Decodes an image from a reader, configured step by step.
+///
+/// Every switch of `DecodeOptions` has a method here, so one decoder covers
+/// what `open_with_alpha`, `open_lenient` and the other variants do.
+///
+/// ```
+/// use two::{Decoder, Limits};
+///
+/// let bmp_data: &[u8] = include_bytes!("../test/rgbw.bmp");
+/// let img = Decoder::new(bmp_data)
+///     .flip_vertical(true)
+///     .keep_indexed(true)
+///     .limits(Limits::default())
+///     .decode()
+///     .unwrap();
+/// assert_eq!(img.get_width(), 2);
+/// ```
+pub struct Decoder<'a, R> {
+    source: R,
+    options: DecodeOptions,
+    progress: Option<Box<dyn FnMut(u32, u32) + 'a>>,
+    cancel: Option<CancellationToken>,
+}
+
+impl<R: fmt::Debug> fmt::Debug for Decoder<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("source", &self.source)
+            .field("options", &self.options)
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl<'a, R: Read> Decoder<'a, R> {
+    /// Creates a decoder with the default options, which decodes like `from_reader`.
+    pub fn new(source: R) -> Decoder<'a, R> {
+        Decoder::with_options(source, DecodeOptions::default())
+    }
+
+    /// Creates a decoder starting from `options`.
+    pub fn with_options(source: R, options: DecodeOptions) -> Decoder<'a, R> {
+        Decoder {
+            source,
+            options,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Sets how the alpha channel is decoded.
+    pub fn alpha(mut self, mode: AlphaMode) -> Decoder<'a, R> {
+        self.options = self.options.alpha(mode);
+        self
+    }
+
+    /// Sets whether rows missing from a short pixel array are filled with
+    /// black, like `open_lenient` does.
+    pub fn allow_truncated(mut self, allow: bool) -> Decoder<'a, R> {
+        self.options = self.options.allow_truncated(allow);
+        self
+    }
+
+    /// Sets the largest images that are decoded.
+    pub fn limits(mut self, limits: Limits) -> Decoder<'a, R> {
+        self.options = self.options.limits(limits);
+        self
+    }
+
+    /// Sets whether the palette indices of indexed files are kept, see
+    /// `Image::indices`.
+    pub fn keep_indexed(mut self, keep: bool) -> Decoder<'a, R> {
+        self.options = self.options.keep_indices(keep);
+        self
+    }
+
+    /// Sets whether the image is flipped upside down, so that its first row
+    /// is the bottom one.
+    pub fn flip_vertical(mut self, flip: bool) -> Decoder<'a, R> {
+        self.options = self.options.flip_vertical(flip);
+        self
+    }
+
+    /// Calls `progress` with the number of rows decoded so far and the number
+    /// of rows of the image, every 64 rows and once the last one is done. The
+    /// closure may borrow from its caller for as long as the decoder lives.
+    ///
+    /// ```
+    /// let bmp_data: &[u8] = include_bytes!("../test/rgbw.bmp");
+    /// let mut calls = Vec::new();
+    /// let img = two::Decoder::new(bmp_data)
+    ///     .on_progress(|done, total| calls.push((done, total)))
+    ///     .decode()
+    ///     .unwrap();
+    /// assert_eq!(calls.last(), Some(&(img.get_height(), img.get_height())));
+    /// ```
+    pub fn on_progress<F: FnMut(u32, u32) + 'a>(mut self, progress: F) -> Decoder<'a, R> {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stops decoding before the next row once `token` is cancelled, failing
+    /// with `BmpErrorKind::Cancelled`.
+    pub fn cancel_with(mut self, token: CancellationToken) -> Decoder<'a, R> {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Returns the options the decoder was configured with.
+    pub fn options(&self) -> DecodeOptions {
+        self.options
+    }
+
+    /// Reads and decodes the image.
+    pub fn decode(self) -> BmpResult<Image> {
+        self.decode_with_warnings().map(|(img, _)| img)
+    }
+
+    /// Reads and decodes the image, and also returns any problems that were
+    /// worked around.
+    pub fn decode_with_warnings(mut self) -> BmpResult<(Image, Vec<BmpWarning>)> {
+        let mut bytes = Vec::new();
+        self.source.read_to_end(&mut bytes)?;
+        let hooks = decoder::Hooks {
+            progress: self
+                .progress
+                .as_deref_mut()
+                .map(|p| p as &mut dyn FnMut(u32, u32)),
+            cancel: self.cancel.as_ref(),
+        };
+        let mut warnings = Vec::new();
+        let mut bmp_data = Cursor::new(&bytes[..]);
+        let img = decoder::decode_image(&mut bmp_data, self.options, &mut warnings, None, hooks)?;
+        Ok((img, warnings))
+    }
+}
+
+/// The largest images the decoder accepts, checked against the headers before
+/// anything is allocated. Files whose headers claim a larger image fail with
+/// `LimitsExceeded`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// The most memory the decoded pixels may take, counted as 4 bytes per
+    /// pixel whatever the format.
+    pub max_pixel_bytes: u64,
+}
+
+impl Limits {
+    /// Accepts any image the format can describe.
+    pub fn none() -> Limits {
+        Limits {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixel_bytes: u64::MAX,
+        }
+    }
+}
+
+impl Default for Limits {
+    /// Accepts images up to a million pixels on each side and 1 GiB of pixels.
+    fn default() -> Limits {
+        Limits {
+            max_width: 1 << 20,
+            max_height: 1 << 20,
+            max_pixel_bytes: 1 << 30,
+        }
+    }
+}
+
+/// A color profile referenced by a version 5 header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum IccProfile {
+    /// The profile data, stored in the file.
+    Embedded(Vec<u8>),
+    /// The file name of the profile.
+    Linked(Vec<u8>),
+}
+
+/// The size of a decoded image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The format of an image stored whole inside a BMP file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbeddedFormat {
+    Jpeg,
+    Png,
+}
+
+/// A JPEG or PNG file stored as the pixel data of a BMP file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmbeddedStream<'a> {
+    pub format: EmbeddedFormat,
+    /// The embedded file, as stored.
+    pub data: &'a [u8],
+}
+
+/// Details of the header of the file an image was decoded from.
+///
+/// Images created in memory describe the 24 bpp file they would be saved as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// Size of the DIB header in bytes, which tells its version apart.
+    pub header_size: u32,
+    /// Width as stored, in pixels.
+    pub width: i32,
+    /// Height as stored, negative for images stored top-down.
+    pub height: i32,
+    pub bits_per_pixel: u16,
+    /// Compression type as stored, 0 if uncompressed.
+    pub compression: u32,
+    /// Size of the pixel data as stored, may be 0 for uncompressed images.
+    pub data_size: u32,
+    /// Horizontal resolution in pixels per meter.
+    pub hres: i32,
+    /// Vertical resolution in pixels per meter.
+    pub vres: i32,
+    /// Number of palette entries, 0 for the default of the bit depth.
+    pub num_colors: u32,
+    /// Number of palette entries needed to show the image, 0 for all.
+    pub num_important_colors: u32,
+}
+
+const INCH_IN_METERS: f32 = 0.0254;
+// Color space types of version 4 headers, 'sRGB' and 'Win '
+const LCS_SRGB: u32 = 0x7352_4742;
+const LCS_WINDOWS: u32 = 0x5769_6E20;
+// Color space types of version 5 headers with a profile, 'MBED' and 'LINK'
+const PROFILE_EMBEDDED: u32 = 0x4D42_4544;
+const PROFILE_LINKED: u32 = 0x4C49_4E4B;
+
+/// An RGB image, with its pixels stored row by row from the top in `B`.
+///
+/// The pixels are kept in a `Vec` unless the image was made over other
+/// storage with `Image::from_buffer`, see the `buffer` module.
 #[derive(Clone, PartialEq, Eq)]
-pub struct Image {
+pub struct Image<B = Vec<Pixel>> {
     header: BmpHeader,
     dib_header: BmpDibHeader,
     color_palette: Option<Vec<Pixel>>,
+    color_space: Option<ColorSpaceFields>,
+    icc_profile: Option<IccProfile>,
     width: u32,
     height: u32,
     padding: u32,
-    data: Vec<Pixel>,
+    data: B,
+    alpha: Option<Vec<u8>>,
+    indices: Option<Vec<u8>>,
+    history: Option<Vec<history::OpRecord>>,
+    // The bytes before the pixel data, kept by `Image::open_preserving`
+    source_headers: Option<Vec<u8>>,
 }
 
 impl Image {
     pub fn new(width: u32, height: u32) -> Image {
-        let mut data = Vec::with_capacity((width * height) as usize);
-        let (header_size, data_size) = file_size!(24, width, height);
+        Image::over(
+            width,
+            height,
+            vec![px!(0, 0, 0); width as usize * height as usize],
+        )
+    }
+
+    /// Returns details of the header the image was decoded from.
+    pub fn metadata(&self) -> Metadata {
+        let dh = &self.dib_header;
+        Metadata {
+            header_size: dh.header_size,
+            width: dh.width,
+            height: dh.height,
+            bits_per_pixel: dh.bits_per_pixel,
+            compression: dh.compress_type,
+            data_size: dh.data_size,
+            hres: dh.hres,
+            vres: dh.vres,
+            num_colors: dh.num_colors,
+            num_important_colors: dh.num_imp_colors,
+        }
+    }
+
+    /// Returns the version of the file the image was decoded from. Images that
+    /// were not decoded report `BmpVersion::Three`, which they are saved as.
+    ///
+    /// ```
+    /// use two::BmpVersion;
+    ///
+    /// let img = two::open("test/bmpsuite-2.5/g/pal8v5.bmp").unwrap();
+    /// assert_eq!(img.version(), BmpVersion::Five);
+    /// assert_eq!(img.version().as_ref(), "BMP Version 5");
+    /// ```
+    pub fn version(&self) -> BmpVersion {
+        // The decoder rejects headers of any other size
+        BmpVersion::from_dib_header(&self.dib_header).unwrap_or(BmpVersion::Three)
+    }
+
+    /// Returns the palette of images decoded from 1, 4 or 8 bpp files, or built
+    /// from an `IndexedImage`.
+    #[inline]
+    pub fn palette(&self) -> Option<&[Pixel]> {
+        self.color_palette.as_deref()
+    }
+
+    /// Returns the alpha of every pixel, row by row from the top, if the image
+    /// was decoded with an alpha channel. 0 is transparent and 255 opaque.
+    ///
+    /// The color channels are not premultiplied. Images derived from this one,
+    /// for example by `crop`, do not keep the alpha channel.
+    #[inline]
+    pub fn alpha(&self) -> Option<&[u8]> {
+        self.alpha.as_deref()
+    }
+
+    /// Returns the palette index of every pixel, row by row from the top, if
+    /// the image was decoded with `DecodeOptions::keep_indices` from a file
+    /// with a palette, or built from an `IndexedImage`.
+    ///
+    /// The indices are those of the file. They are not updated when pixels
+    /// are changed, and images derived from this one do not keep them.
+    ///
+    /// ```
+    /// use two::DecodeOptions;
+    ///
+    /// let options = DecodeOptions {
+    ///     keep_indices: true,
+    ///     ..DecodeOptions::default()
+    /// };
+    /// let (img, _) = two::open_with_options("test/bmpsuite-2.5/g/pal4.bmp", options).unwrap();
+    /// let i = img.indices().unwrap()[0] as usize;
+    /// assert_eq!(img.palette().unwrap()[i], img.get_pixel(0, 0));
+    /// ```
+    #[inline]
+    pub fn indices(&self) -> Option<&[u8]> {
+        self.indices.as_deref()
+    }
+
+    /// Multiplies the color channels by the alpha channel, as expected by most
+    /// compositing code and GPU blending. Does nothing without an alpha channel.
+    pub fn premultiply_alpha(&mut self) {
+        if let Some(ref alpha) = self.alpha {
+            for (px, &a) in self.data.iter_mut().zip(alpha.iter()) {
+                let mul = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+                *px = px!(mul(px.r), mul(px.g), mul(px.b));
+            }
+            self.record(history::OpRecord::new("premultiply_alpha"));
+        }
+    }
+
+    /// Divides premultiplied color channels by the alpha channel again. Fully
+    /// transparent pixels become black. Does nothing without an alpha channel.
+    ///
+    /// Colors of translucent pixels lose precision through premultiplication,
+    /// so the round trip is not exact.
+    pub fn unpremultiply_alpha(&mut self) {
+        if let Some(ref alpha) = self.alpha {
+            for (px, &a) in self.data.iter_mut().zip(alpha.iter()) {
+                let div = |c: u8| match a {
+                    0 => 0,
+                    _ => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8,
+                };
+                *px = px!(div(px.r), div(px.g), div(px.b));
+            }
+            self.record(history::OpRecord::new("unpremultiply_alpha"));
+        }
+    }
+
+    /// Returns the color space of the version 4 or 5 header the image was
+    /// decoded from.
+    pub fn color_space(&self) -> Option<ColorSpaceInfo> {
+        let fields = self.color_space?;
+        let cs_type = match fields.cs_type {
+            0 => ColorSpaceType::Calibrated,
+            LCS_SRGB => ColorSpaceType::Srgb,
+            LCS_WINDOWS => ColorSpaceType::WindowsDefault,
+            PROFILE_LINKED => ColorSpaceType::Linked,
+            PROFILE_EMBEDDED => ColorSpaceType::Embedded,
+            other => ColorSpaceType::Other(other),
+        };
+
+        // CIE XYZ coordinates are 2.30 fixed point numbers, the gammas 16.16 ones
+        let mut endpoints = [[0.0; 3]; 3];
+        for (xyz, &val) in endpoints.iter_mut().flatten().zip(fields.endpoints.iter()) {
+            *xyz = val as f32 / (1 << 30) as f32;
+        }
+        Some(ColorSpaceInfo {
+            cs_type,
+            endpoints,
+            gamma: fields.gamma.map(|val| val as f32 / 65536.0),
+        })
+    }
+
+    /// Returns the ICC profile embedded in the file the image was decoded from.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        match self.icc_profile {
+            Some(IccProfile::Embedded(ref data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns the file name of the ICC profile the image was decoded with, as
+    /// stored in the file.
+    ///
+    /// The name is usually a Windows path in the system code page. It is not
+    /// opened, as following names from untrusted files is not safe.
+    pub fn linked_icc_profile(&self) -> Option<&[u8]> {
+        match self.icc_profile {
+            Some(IccProfile::Linked(ref name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the horizontal and vertical resolution in dots per inch.
+    pub fn dpi(&self) -> (f32, f32) {
+        let dpi = |ppm: i32| ppm as f32 * INCH_IN_METERS;
+        (dpi(self.dib_header.hres), dpi(self.dib_header.vres))
+    }
+
+    /// Returns the width of a pixel divided by its height, as implied by the
+    /// resolution. Pixels are square, 1.0, unless both resolutions are set and
+    /// differ, as on some capture hardware. See `resample_to_square_pixels`.
+    pub fn pixel_aspect_ratio(&self) -> f32 {
+        let (hres, vres) = (self.dib_header.hres, self.dib_header.vres);
+        if hres <= 0 || vres <= 0 {
+            return 1.0;
+        }
+        // More pixels per meter across make them narrower
+        vres as f32 / hres as f32
+    }
+
+    /// Sets the resolution written by `save` to `x` by `y` dots per inch.
+    ///
+    /// The header stores whole pixels per meter, so the values read back by
+    /// `dpi` are rounded slightly.
+    pub fn set_dpi(&mut self, x: f32, y: f32) {
+        self.dib_header.hres = dpi_to_ppm(x);
+        self.dib_header.vres = dpi_to_ppm(y);
+    }
+
+    /// Returns the hotspot of images extracted from cursor resources, which is
+    /// kept in the reserved fields of the file header. Returns `None` if both
+    /// are zero, as in ordinary files.
+    pub fn hotspot(&self) -> Option<(u16, u16)> {
+        match (self.header.creator1, self.header.creator2) {
+            (0, 0) => None,
+            hotspot => Some(hotspot),
+        }
+    }
+
+    /// Sets the hotspot written by `save`, or clears it with `None`.
+    pub fn set_hotspot(&mut self, hotspot: Option<(u16, u16)>) {
+        let (x, y) = hotspot.unwrap_or((0, 0));
+        self.header.creator1 = x;
+        self.header.creator2 = y;
+    }
+
+    /// Returns the pixels of row `y`.
+    #[inline]
+    fn row_mut(&mut self, y: u32) -> &mut [Pixel] {
+        let start = self.index(0, y);
+        &mut self.data[start..start + self.width as usize]
+    }
+
+    /// Sets every pixel in the `(x, y, width, height)` region to `val`.
+    ///
+    /// Any part of the region lying outside of the image is clipped.
+    pub fn fill_rect<R: Into<Rect>>(&mut self, rect: R, val: Pixel) {
+        let rect = rect.into().clamp(self.size());
+        let (x0, x1) = (rect.x as usize, rect.right() as usize);
+        for y in rect.y..rect.bottom() {
+            self.row_mut(y)[x0..x1].fill(val);
+        }
+    }
+
+    /// Copies the `(x, y, width, height)` region of `src` into this image, with the
+    /// region's top-left corner placed at `dst`.
+    ///
+    /// Any part of the region lying outside of `src` or of this image is clipped.
+    /// All images currently share one pixel format, so pixels are copied as-is.
+    pub fn copy_from<R: Into<Rect>, P: Into<Point>>(&mut self, src: &Image, src_rect: R, dst: P) {
+        let src_rect = src_rect.into().clamp(src.size());
+        let dst = dst.into();
+        let (w, h) = (src_rect.width as i64, src_rect.height as i64);
+
+        // Clip the placed region against this image
+        let (dx, dy) = (dst.x as i64, dst.y as i64);
+        let x0 = (-dx).max(0);
+        let y0 = (-dy).max(0);
+        let x1 = w.min(self.width as i64 - dx);
+        let y1 = h.min(self.height as i64 - dy);
+
+        let (sx, sy) = (src_rect.x as i64, src_rect.y as i64);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let px = src.get_pixel((sx + x) as u32, (sy + y) as u32);
+                self.set_pixel((dx + x) as u32, (dy + y) as u32, px);
+            }
+        }
+    }
+
+    /// Returns a copy of the `(x, y, width, height)` region, clipped to the image.
+    pub fn crop<R: Into<Rect>>(&self, rect: R) -> Image {
+        let rect = rect.into().clamp(self.size());
+        let mut img = Image::new(rect.width, rect.height);
+        img.copy_from(self, rect, (0, 0));
+        self.derive_history(&mut img, || {
+            history::OpRecord::new("crop")
+                .param("x", rect.x)
+                .param("y", rect.y)
+                .param("width", rect.width)
+                .param("height", rect.height)
+        });
+        img
+    }
+
+    /// Trims the margins of `background`, or of the top-left pixel's color if
+    /// `None`, where no channel differs from it by more than `tolerance`.
+    ///
+    /// An image that is all background is cropped to nothing.
+    pub fn autocrop(&self, background: Option<Pixel>, tolerance: u8) -> Image {
+        if self.data.is_empty() {
+            return self.clone();
+        }
+        let bg = background.unwrap_or_else(|| self.get_pixel(0, 0));
+        let is_bg = |px: &Pixel| {
+            let close = |a: u8, b: u8| a.abs_diff(b) <= tolerance;
+            close(px.r, bg.r) && close(px.g, bg.g) && close(px.b, bg.b)
+        };
+
+        let width = self.width as usize;
+        let rows: Vec<&[Pixel]> = self.data.chunks_exact(width).collect();
+        let has_content = |row: &&[Pixel]| !row.iter().all(is_bg);
+        let top = match rows.iter().position(has_content) {
+            Some(top) => top,
+            None => return self.crop((0, 0, 0, 0)),
+        };
+        let bottom = rows.iter().rposition(has_content).unwrap_or(top);
+        let mut left = width;
+        let mut right = 0;
+        for row in &rows[top..=bottom] {
+            if let Some(x) = row.iter().position(|px| !is_bg(px)) {
+                left = left.min(x);
+                right = right.max(row.iter().rposition(|px| !is_bg(px)).unwrap_or(x));
+            }
+        }
+
+        self.crop((
+            left as u32,
+            top as u32,
+            (right - left + 1) as u32,
+            (bottom - top + 1) as u32,
+        ))
+    }
+
+    /// Returns the JPEG or PNG file embedded in `bmp_data`, `None` if its pixels
+    /// are stored as usual.
+    ///
+    /// Such files fail to decode with `EmbeddedJpeg` or `EmbeddedPng`, and their
+    /// embedded file can be handed to a decoder for its format instead.
+    ///
+    /// ```no_run
+    /// use two::{BmpErrorKind, EmbeddedFormat, Image};
+    ///
+    /// let bmp_data = std::fs::read("printout.bmp").unwrap();
+    /// if let Err(e) = two::from_bytes(&bmp_data) {
+    ///     if let BmpErrorKind::EmbeddedPng = e.kind {
+    ///         let stream = Image::embedded_stream(&bmp_data).unwrap().unwrap();
+    ///         assert_eq!(stream.format, EmbeddedFormat::Png);
+    ///         std::fs::write("printout.png", stream.data).unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn embedded_stream(bmp_data: &[u8]) -> BmpResult<Option<EmbeddedStream<'_>>> {
+        decoder::embedded_stream(bmp_data)
+    }
 
-        for _ in 0..width * height {
-            data.push(px!(0, 0, 0));
+    /// Returns the pixels packed in the `P` layout, with the rows in `order`
+    /// and without any row padding.
+    pub fn to_bytes<P: pack::PackPixel>(&self, order: RowOrder) -> Vec<u8> {
+        let mut bytes = vec![0; (self.width * self.height) as usize * P::BYTES];
+        for ((x, y), out) in self.coordinates().zip(bytes.chunks_exact_mut(P::BYTES)) {
+            P::pack(self.get_pixel(x, y), out);
         }
+        if order == RowOrder::BottomUp {
+            flip_rows(&mut bytes, self.width as usize * P::BYTES);
+        }
+        bytes
+    }
+
+    /// Creates a `width` by `height` image from pixels packed in the `P`
+    /// layout, with the rows in `order` and without any row padding, as
+    /// `to_bytes` returns them. Fails with `DimensionMismatch` if `bytes` holds
+    /// fewer pixels; any bytes after them are ignored.
+    pub fn from_bytes<P: pack::PackPixel>(
+        width: u32,
+        height: u32,
+        bytes: &[u8],
+        order: RowOrder,
+    ) -> BmpResult<Image> {
+        let row_len = width as usize * P::BYTES;
+        let needed = row_len.saturating_mul(height as usize);
+        if bytes.len() < needed {
+            return Err(BmpError::new(
+                BmpErrorKind::DimensionMismatch,
+                format!(
+                    "A {}x{} image needs {} bytes, but only {} are available",
+                    width,
+                    height,
+                    needed,
+                    bytes.len()
+                ),
+            ));
+        }
+
+        let mut img = Image::new(width, height);
+        if row_len == 0 {
+            return Ok(img);
+        }
+        for (y, row) in bytes[..needed].chunks_exact(row_len).enumerate() {
+            let y = match order {
+                RowOrder::TopDown => y as u32,
+                RowOrder::BottomUp => height - 1 - y as u32,
+            };
+            for (x, packed) in row.chunks_exact(P::BYTES).enumerate() {
+                img.set_pixel(x as u32, y, P::unpack(packed));
+            }
+        }
+        Ok(img)
+    }
+
+    /// Writes the pixels into `buf` in `layout`, with the rows in `order` and
+    /// each row starting `stride` bytes after the previous one, as in a framebuffer.
+    ///
+    /// Nothing is written unless `stride` fits a row and `buf` fits all of them.
+    /// The bytes between the end of a row and the next are left as they are.
+    ///
+    /// ```
+    /// use two::pack::{Layout, RowOrder};
+    /// use two::{consts, Image};
+    ///
+    /// let mut img = Image::new(2, 2);
+    /// img.fill(consts::RED);
+    /// let mut framebuffer = [0u8; 24];
+    /// img.copy_into_slice(&mut framebuffer, Layout::Rgb8, RowOrder::TopDown, 12)
+    ///     .unwrap();
+    /// assert_eq!(framebuffer[12..15], [255, 0, 0]);
+    /// assert!(img
+    ///     .copy_into_slice(&mut framebuffer, Layout::Rgba8, RowOrder::TopDown, 6)
+    ///     .is_err());
+    /// ```
+    pub fn copy_into_slice(
+        &self,
+        buf: &mut [u8],
+        layout: pack::Layout,
+        order: RowOrder,
+        stride: usize,
+    ) -> Result<(), pack::SizeMismatch> {
+        let n = layout.bytes_per_pixel();
+        let row_len = self.width as usize * n;
+        if stride < row_len {
+            return Err(pack::SizeMismatch {
+                needed: row_len,
+                available: stride,
+            });
+        }
+        // The last row needs no bytes after its pixels
+        let needed = match self.height as usize {
+            0 => 0,
+            h => (h - 1) * stride + row_len,
+        };
+        if buf.len() < needed {
+            return Err(pack::SizeMismatch {
+                needed,
+                available: buf.len(),
+            });
+        }
+
+        for y in 0..self.height {
+            let row = match order {
+                RowOrder::TopDown => y,
+                RowOrder::BottomUp => self.height - 1 - y,
+            };
+            let start = row as usize * stride;
+            let out = &mut buf[start..start + row_len];
+            for (x, out) in (0..self.width).zip(out.chunks_exact_mut(n)) {
+                let i = self.index(x, y);
+                let alpha = self.alpha.as_ref().map_or(255, |alpha| alpha[i]);
+                layout.pack(self.data[i], alpha, out);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B: PixelBuffer> Image<B> {
+    /// Creates a `width` by `height` image over `buffer`, whose first pixels
+    /// become the image, row by row from the top. Fails with
+    /// `DimensionMismatch` if the buffer holds fewer pixels.
+    pub fn from_buffer(width: u32, height: u32, buffer: B) -> BmpResult<Image<B>> {
+        let needed = width as usize * height as usize;
+        let available = buffer.as_ref().len();
+        if available < needed {
+            return Err(BmpError::new(
+                BmpErrorKind::DimensionMismatch,
+                format!(
+                    "A {}x{} image does not fit into {} pixels",
+                    width, height, available
+                ),
+            ));
+        }
+        Ok(Image::over(width, height, buffer))
+    }
 
+    fn over(width: u32, height: u32, data: B) -> Image<B> {
+        // Images too large for a file get no sizes, `save` fails on them
+        let (header_size, data_size) = decoder::file_size(24, width, height).unwrap_or_default();
         Image {
             header: BmpHeader::new(header_size, data_size),
             dib_header: BmpDibHeader::new(width as i32, height as i32),
             color_palette: None,
+            color_space: None,
+            icc_profile: None,
             width,
             height,
             padding: width % 4,
             data,
+            alpha: None,
+            indices: None,
+            history: None,
+            source_headers: None,
         }
     }
 
+    /// Returns the storage of the pixels, which may be larger than the image.
+    pub fn into_buffer(self) -> B {
+        self.data
+    }
+
+    /// Returns the pixels, row by row from the top.
+    #[inline]
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.data.as_ref()[..self.width as usize * self.height as usize]
+    }
+
+    /// Returns the pixels, row by row from the top.
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [Pixel] {
+        let len = self.width as usize * self.height as usize;
+        &mut self.data.as_mut()[..len]
+    }
+
     /// Returns the `width` of the Image.
     #[inline]
     pub fn get_width(&self) -> u32 {
@@ -263,12 +1306,36 @@ impl Image {
 
     #[inline]
     pub fn set_pixel(&mut self, x: u32, y: u32, val: Pixel) {
-        self.data[((self.height - y - 1) * self.width + x) as usize] = val;
+        let index = self.index(x, y);
+        self.data.as_mut()[index] = val;
     }
 
     #[inline]
     pub fn get_pixel(&self, x: u32, y: u32) -> Pixel {
-        self.data[((self.height - y - 1) * self.width + x) as usize]
+        self.data.as_ref()[self.index(x, y)]
+    }
+
+    /// Maps the `(x, y)` coordinate to its position in the pixel data, which is stored top-down.
+    #[inline]
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    /// Sets every pixel of the image to `val`.
+    pub fn fill(&mut self, val: Pixel) {
+        self.pixels_mut().fill(val);
+    }
+
+    /// Returns the `width` and `height` of the image.
+    #[inline]
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    /// Returns the region covering the whole image.
+    #[inline]
+    pub fn bounds(&self) -> Rect {
+        Rect::from_size(self.size())
     }
 
     #[inline]
@@ -282,22 +1349,52 @@ impl Image {
     }
 
     pub fn to_writer<W: Write>(&self, destination: &mut W) -> io::Result<()> {
-        let bmp_data = encoder::encode_image(self)?;
+        let bmp_data = encoder::encode_image(self, &EncoderOptions::default())?;
         destination.write_all(&bmp_data)?;
         Ok(())
     }
+
+    /// Saves the image as `options` ask for.
+    ///
+    /// ```no_run
+    /// use two::{Compression, EncoderOptions, Image};
+    ///
+    /// let img = Image::new(640, 480);
+    /// let options = EncoderOptions::default().compression(Compression::Rle8);
+    /// img.save_with_options("flat.bmp", &options).unwrap();
+    /// ```
+    pub fn save_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &EncoderOptions,
+    ) -> io::Result<()> {
+        fs::write(path, encoder::encode_image(self, options)?)
+    }
+
+    /// Like `save_with_options`, but writes to `destination`.
+    pub fn to_writer_with_options<W: Write>(
+        &self,
+        destination: &mut W,
+        options: &EncoderOptions,
+    ) -> io::Result<()> {
+        destination.write_all(&encoder::encode_image(self, options)?)
+    }
 }
 
-impl fmt::Debug for Image {
+impl<B: PixelBuffer> fmt::Debug for Image<B> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Image")
             .field("header", &self.header)
             .field("dib_header", &self.dib_header)
             .field("color_palette", &self.color_palette)
+            .field("color_space", &self.color_space)
+            .field("icc_profile", &self.icc_profile)
             .field("width", &self.width)
             .field("height", &self.height)
             .field("padding", &self.padding)
-            .field("data", &self.data)
+            .field("data", &self.pixels())
+            .field("alpha", &self.alpha)
+            .field("history", &self.history)
             .finish()
     }
 }
@@ -347,31 +1444,391 @@ pub fn open<P: AsRef<Path>>(path: P) -> BmpResult<Image> {
 pub fn from_reader<R: Read>(source: &mut R) -> BmpResult<Image> {
     let mut bytes = Vec::new();
     source.read_to_end(&mut bytes)?;
-
-    let mut bmp_data = Cursor::new(bytes);
-    decoder::decode_image(&mut bmp_data)
+    from_bytes(&bytes)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::{Read, Seek, SeekFrom};
-    use std::mem::size_of;
+/// Decodes an image from a file already in memory, without copying it.
+///
+/// ```
+/// let img = two::from_bytes(include_bytes!("../test/rgbw.bmp")).unwrap();
+/// assert_eq!(img.get_width(), 2);
+/// ```
+pub fn from_bytes(bytes: &[u8]) -> BmpResult<Image> {
+    from_bytes_with_options(bytes, DecodeOptions::default()).map(|(img, _)| img)
+}
 
-    #[test]
-    fn size_of_bmp_header_is_54_bytes() {
-        let bmp_header_size = size_of::<BmpHeader>();
-        let bmp_bip_header_size = size_of::<BmpDibHeader>();
+impl TryFrom<&[u8]> for Image {
+    type Error = BmpError;
 
-        assert_eq!(12, bmp_header_size);
-        assert_eq!(40, bmp_bip_header_size);
+    fn try_from(bytes: &[u8]) -> BmpResult<Image> {
+        from_bytes(bytes)
     }
+}
 
-    fn verify_test_bmp_image(img: Image) {
-        let header = img.header;
-        assert_eq!(70, header.file_size);
-        assert_eq!(0, header.creator1);
-        assert_eq!(0, header.creator2);
+/// Like `open`, but decodes the alpha channel according to `mode`.
+pub fn open_with_alpha<P: AsRef<Path>>(path: P, mode: AlphaMode) -> BmpResult<Image> {
+    let mut f = fs::File::open(path)?;
+    from_reader_with_alpha(&mut f, mode)
+}
+
+/// Like `from_reader`, but decodes the alpha channel according to `mode`.
+pub fn from_reader_with_alpha<R: Read>(source: &mut R, mode: AlphaMode) -> BmpResult<Image> {
+    Decoder::new(source).alpha(mode).decode()
+}
+
+/// Opens a file whose pixel data may be cut short, as is common for files that
+/// were not fully downloaded.
+///
+/// The missing rows are black, and the returned warnings tell how many there were.
+///
+/// ```no_run
+/// let (img, warnings) = two::open_lenient("partial.bmp").unwrap();
+/// for warning in &warnings {
+///     eprintln!("{}", warning);
+/// }
+/// ```
+pub fn open_lenient<P: AsRef<Path>>(path: P) -> BmpResult<(Image, Vec<BmpWarning>)> {
+    open_with_options(path, DecodeOptions::default().allow_truncated(true))
+}
+
+/// Like `open`, but also returns the oddities of the file that were tolerated,
+/// such as a wrong file size in the header.
+///
+/// ```no_run
+/// let (img, warnings) = two::open_with_report("scan.bmp").unwrap();
+/// for warning in &warnings {
+///     eprintln!("scan.bmp: {}", warning);
+/// }
+/// ```
+pub fn open_with_report<P: AsRef<Path>>(path: P) -> BmpResult<(Image, Vec<BmpWarning>)> {
+    open_with_options(path, DecodeOptions::default())
+}
+
+/// Like `open`, but decodes according to `options` and also returns any
+/// problems that were worked around.
+pub fn open_with_options<P: AsRef<Path>>(
+    path: P,
+    options: DecodeOptions,
+) -> BmpResult<(Image, Vec<BmpWarning>)> {
+    let mut f = fs::File::open(path)?;
+    from_reader_with_options(&mut f, options)
+}
+
+pub fn from_reader_with_options<R: Read>(
+    source: &mut R,
+    options: DecodeOptions,
+) -> BmpResult<(Image, Vec<BmpWarning>)> {
+    let mut bytes = Vec::new();
+    source.read_to_end(&mut bytes)?;
+    from_bytes_with_options(&bytes, options)
+}
+
+pub fn from_bytes_with_options(
+    bytes: &[u8],
+    options: DecodeOptions,
+) -> BmpResult<(Image, Vec<BmpWarning>)> {
+    let mut bmp_data = Cursor::new(bytes);
+    let mut warnings = Vec::new();
+    let hooks = decoder::Hooks::default();
+    let img = decoder::decode_image(&mut bmp_data, options, &mut warnings, None, hooks)?;
+    Ok((img, warnings))
+}
+
+/// Decodes a file from an untrusted source, such as an upload to a server or
+/// a fuzzer, within the default limits.
+///
+/// Where `from_bytes` guesses around oddities of the headers and only warns
+/// about them, this fails with `BmpErrorKind::InconsistentHeader` on any size,
+/// offset or count that does not add up, and on pixel data that is cut short.
+/// No input makes it panic, and every file of the `corrupt` directory of the
+/// bmptestsuite is rejected.
+///
+/// ```
+/// let bmp_data = include_bytes!("../test/rgbw.bmp");
+/// let img = two::decode_untrusted(bmp_data).unwrap();
+/// assert_eq!(img.get_width(), 2);
+/// assert!(two::decode_untrusted(&bmp_data[..60]).is_err());
+/// ```
+pub fn decode_untrusted(bytes: &[u8]) -> BmpResult<Image> {
+    let mut bmp_data = Cursor::new(bytes);
+    let (header, dib_header, color_palette) =
+        decoder::decode_headers(&mut bmp_data, Limits::default())?;
+    decoder::check_untrusted(bytes, &header, &dib_header, color_palette.as_deref())?;
+    bmp_data.set_position(0);
+    decoder::decode_image(
+        &mut bmp_data,
+        DecodeOptions::default(),
+        &mut Vec::new(),
+        None,
+        decoder::Hooks::default(),
+    )
+}
+
+/// Decodes a file straight into the bytes of `options.layout`, row by row from
+/// the top, ready for upload to a graphics API without another pass over them.
+///
+/// ```
+/// use two::pack::Layout;
+/// use two::DecodeOptions;
+///
+/// let options = DecodeOptions::default().output_layout(Layout::Bgra8);
+/// let bmp_data = include_bytes!("../test/rgbw.bmp");
+/// let (bytes, info) = two::from_bytes_packed(bmp_data, options).unwrap();
+/// assert_eq!((info.width, info.height), (2, 2));
+/// assert_eq!(bytes[..4], [0, 0, 255, 255]);
+/// ```
+pub fn from_bytes_packed(bytes: &[u8], options: DecodeOptions) -> BmpResult<(Vec<u8>, ImageInfo)> {
+    let mut bmp_data = Cursor::new(bytes);
+    decoder::decode_packed(&mut bmp_data, options, &mut Vec::new())
+}
+
+/// A file whose headers were read, ready for its pixels to be decoded by
+/// `decode_into` and its variants.
+struct Frame<'a> {
+    bmp_data: Cursor<&'a [u8]>,
+    header: BmpHeader,
+    dib_header: BmpDibHeader,
+    palette: Option<Vec<Pixel>>,
+    info: ImageInfo,
+}
+
+impl<'a> Frame<'a> {
+    fn read(bytes: &'a [u8]) -> BmpResult<Frame<'a>> {
+        let mut bmp_data = Cursor::new(bytes);
+        let (header, dib_header, palette) =
+            decoder::decode_headers(&mut bmp_data, Limits::default())?;
+        let info = ImageInfo {
+            width: dib_header.width.unsigned_abs(),
+            height: dib_header.height.unsigned_abs(),
+        };
+        Ok(Frame {
+            bmp_data,
+            header,
+            dib_header,
+            palette,
+            info,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.info.width as usize * self.info.height as usize
+    }
+
+    fn is_bottom_up(&self) -> bool {
+        self.dib_header.is_bottom_up()
+    }
+
+    /// Calls `push` with every pixel, in file order.
+    fn decode<F: FnMut(Pixel)>(mut self, push: F) -> BmpResult<()> {
+        decoder::decode_pixels(
+            &mut self.bmp_data,
+            &self.header,
+            &self.dib_header,
+            self.palette.as_deref(),
+            push,
+        )
+    }
+}
+
+/// Decodes the file in `bytes` into `data`, reusing its allocation.
+///
+/// `data` is cleared first and then holds the pixels row by row from the top.
+/// Decoding frames of the same size allocates nothing but their palettes.
+pub fn decode_into(bytes: &[u8], data: &mut Vec<Pixel>, info: &mut ImageInfo) -> BmpResult<()> {
+    let frame = Frame::read(bytes)?;
+    let (size, bottom_up) = (frame.info, frame.is_bottom_up());
+    data.clear();
+    data.reserve(frame.len());
+    frame.decode(|px| data.push(px))?;
+    if bottom_up {
+        flip_rows(data, size.width as usize);
+    }
+
+    *info = size;
+    Ok(())
+}
+
+/// Decodes the file in `bytes` into the start of `pixels`, row by row from
+/// the top, and returns its size.
+///
+/// Unlike `decode_into`, the buffer never grows, so one allocation serves any
+/// number of frames up to its size. Images with more pixels than `pixels`
+/// holds fail with `DimensionMismatch` before anything is decoded.
+///
+/// ```
+/// use two::consts;
+///
+/// let mut frame = vec![consts::BLACK; 640 * 480];
+/// let bmp_data = include_bytes!("../test/rgbw.bmp");
+/// let info = two::decode_into_slice(bmp_data, &mut frame).unwrap();
+/// assert_eq!((info.width, info.height), (2, 2));
+/// assert_eq!(frame[3], consts::WHITE);
+/// ```
+pub fn decode_into_slice(bytes: &[u8], pixels: &mut [Pixel]) -> BmpResult<ImageInfo> {
+    let frame = Frame::read(bytes)?;
+    let info = frame.info;
+    if frame.len() > pixels.len() {
+        return Err(BmpError::new(
+            BmpErrorKind::DimensionMismatch,
+            format!(
+                "A {}x{} image does not fit into {} pixels",
+                info.width,
+                info.height,
+                pixels.len()
+            ),
+        ));
+    }
+
+    // The pixels come in file order, so each is written straight to its row
+    let (width, height) = (info.width as usize, info.height as usize);
+    let bottom_up = frame.is_bottom_up();
+    let mut i = 0;
+    frame.decode(|px| {
+        let (row, x) = (i / width, i % width);
+        let y = match bottom_up {
+            true => height - 1 - row,
+            false => row,
+        };
+        pixels[y * width + x] = px;
+        i += 1;
+    })?;
+    Ok(info)
+}
+
+/// Decodes the file in `bytes` into `buffer` as `decode_into_slice` does, and
+/// returns it as an image over the buffer, without allocating any pixels.
+pub fn decode_into_buffer<B: PixelBuffer>(bytes: &[u8], mut buffer: B) -> BmpResult<Image<B>> {
+    let info = decode_into_slice(bytes, buffer.as_mut())?;
+    Image::from_buffer(info.width, info.height, buffer)
+}
+
+/// Decodes the file in `file` into `bytes` as packed RGB triples, reusing its
+/// allocation.
+///
+/// `bytes` is cleared first and then holds the rows in `order`, without padding.
+pub fn decode_bytes_into(
+    file: &[u8],
+    bytes: &mut Vec<u8>,
+    order: RowOrder,
+    info: &mut ImageInfo,
+) -> BmpResult<()> {
+    let frame = Frame::read(file)?;
+    let (size, bottom_up) = (frame.info, frame.is_bottom_up());
+    bytes.clear();
+    bytes.reserve(frame.len() * 3);
+    frame.decode(|px| bytes.extend_from_slice(&[px.r, px.g, px.b]))?;
+    // The pixels come in file order
+    if bottom_up != (order == RowOrder::BottomUp) {
+        flip_rows(bytes, size.width as usize * 3);
+    }
+
+    *info = size;
+    Ok(())
+}
+
+/// An image decoded to a single channel when its colors allow it.
+// Boxing the RGB image would change the public variants for a few bytes
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodedImage {
+    Rgb(Image),
+    /// The image used a palette of grays only.
+    Gray(GrayImage),
+}
+
+impl DecodedImage {
+    /// Returns the image as RGB, expanding gray samples if needed.
+    pub fn into_rgb(self) -> Image {
+        match self {
+            DecodedImage::Rgb(img) => img,
+            DecodedImage::Gray(gray) => gray.to_image(),
+        }
+    }
+
+    #[inline]
+    pub fn is_gray(&self) -> bool {
+        matches!(*self, DecodedImage::Gray(_))
+    }
+}
+
+/// Opens the image at `path` like `open`, but decodes it to a `GrayImage`
+/// if every entry of its palette is a shade of gray.
+pub fn open_detect_gray<P: AsRef<Path>>(path: P) -> BmpResult<DecodedImage> {
+    let mut f = fs::File::open(path)?;
+    from_reader_detect_gray(&mut f)
+}
+
+/// Decodes an image like `from_reader`, but to a `GrayImage` if every entry
+/// of its palette is a shade of gray.
+///
+/// Images without a palette are always decoded to RGB.
+pub fn from_reader_detect_gray<R: Read>(source: &mut R) -> BmpResult<DecodedImage> {
+    let mut bytes = Vec::new();
+    source.read_to_end(&mut bytes)?;
+
+    let frame = Frame::read(&bytes)?;
+    let gray_palette = frame
+        .palette
+        .as_deref()
+        .is_some_and(|p| p.iter().all(|px| px.r == px.g && px.g == px.b));
+    if !gray_palette {
+        return from_bytes(&bytes).map(DecodedImage::Rgb);
+    }
+
+    let (info, bottom_up) = (frame.info, frame.is_bottom_up());
+    let mut data = Vec::with_capacity(frame.len());
+    frame.decode(|px| data.push(px.r))?;
+    if bottom_up {
+        flip_rows(&mut data, info.width as usize);
+    }
+
+    let gray = GrayImage::from_vec(info.width, info.height, data)
+        .ok_or_else(|| BmpError::new(BmpErrorKind::DimensionMismatch, "Pixel data ended early"))?;
+    Ok(DecodedImage::Gray(gray))
+}
+
+/// Encodes `img` into `bmp_data`, reusing its allocation.
+///
+/// `bmp_data` is cleared first and then holds the complete BMP file.
+pub fn encode_into_buf(img: &Image, bmp_data: &mut Vec<u8>) -> io::Result<()> {
+    encoder::encode_into(img, bmp_data)
+}
+
+/// Reverses the order of the `row_len` long rows in `data`.
+///
+/// BMP files are usually stored bottom-up, so this runs on almost every decode.
+/// Swapping whole rows lets it compile down to block copies.
+fn flip_rows<T>(data: &mut [T], row_len: usize) {
+    if row_len == 0 {
+        return;
+    }
+    let rows = data.len() / row_len;
+    for y in 0..rows / 2 {
+        let (top, bottom) = data.split_at_mut((rows - y - 1) * row_len);
+        top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::mem::size_of;
+
+    #[test]
+    fn size_of_bmp_header_is_54_bytes() {
+        let bmp_header_size = size_of::<BmpHeader>();
+        let bmp_bip_header_size = size_of::<BmpDibHeader>();
+
+        assert_eq!(12, bmp_header_size);
+        assert_eq!(40, bmp_bip_header_size);
+    }
+
+    fn verify_test_bmp_image(img: Image) {
+        let header = img.header;
+        assert_eq!(70, header.file_size);
+        assert_eq!(0, header.creator1);
+        assert_eq!(0, header.creator2);
 
         let dib_header = img.dib_header;
         assert_eq!(54, header.pixel_offset);
@@ -434,6 +1891,89 @@ mod tests {
         assert_eq!(bmp_img.get_pixel(1, 1), consts::WHITE);
     }
 
+    #[test]
+    fn decode_into_slice_checks_the_size() {
+        let expected = open("test/bmpsuite-2.5/g/pal8topdown.bmp").unwrap();
+        let mut frame = vec![consts::RED; 127 * 64 + 1];
+        for path in &[
+            "test/bmpsuite-2.5/g/pal8.bmp",
+            "test/bmpsuite-2.5/g/pal8topdown.bmp",
+        ] {
+            let info = decode_into_slice(&fs::read(path).unwrap(), &mut frame).unwrap();
+            assert_eq!((info.width, info.height), (127, 64));
+            assert_eq!(frame[..127 * 64], expected.data[..]);
+            assert_eq!(frame[127 * 64], consts::RED);
+        }
+
+        let bmp_data = fs::read("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        match decode_into_slice(&bmp_data, &mut frame[..100]) {
+            Err(BmpError {
+                kind: BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("The image should not fit into 100 pixels"),
+        }
+    }
+
+    #[test]
+    fn decode_into_reuses_the_buffers() {
+        let mut data = Vec::new();
+        let mut bytes = Vec::new();
+        let mut info = ImageInfo::default();
+
+        let large = fs::read("test/bmptestsuite-0.9/valid/24bpp-320x240.bmp").unwrap();
+        let rgbw = fs::read("test/rgbw.bmp").unwrap();
+        decode_into(&large, &mut data, &mut info).unwrap();
+        assert_eq!((info.width, info.height), (320, 240));
+        assert_eq!(data.len(), 320 * 240);
+        let capacity = data.capacity();
+
+        decode_into(&rgbw, &mut data, &mut info).unwrap();
+        assert_eq!((info.width, info.height), (2, 2));
+        assert_eq!(
+            data,
+            vec![consts::RED, consts::LIME, consts::BLUE, consts::WHITE]
+        );
+        assert_eq!(data.capacity(), capacity);
+
+        decode_bytes_into(&rgbw, &mut bytes, RowOrder::TopDown, &mut info).unwrap();
+        assert_eq!(bytes, vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]);
+
+        decode_bytes_into(&rgbw, &mut bytes, RowOrder::BottomUp, &mut info).unwrap();
+        assert_eq!(bytes, vec![0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn gray_palettes_decode_to_gray_images() {
+        let path = "test/bmpsuite-2.5/g/pal8gs.bmp";
+        let decoded = open_detect_gray(path).unwrap();
+        assert!(decoded.is_gray());
+        assert_eq!(decoded.into_rgb().data, open(path).unwrap().data);
+
+        let decoded = open_detect_gray("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        assert!(!decoded.is_gray());
+        let decoded = open_detect_gray("test/rgbw.bmp").unwrap();
+        assert_eq!(decoded, DecodedImage::Rgb(open("test/rgbw.bmp").unwrap()));
+    }
+
+    #[test]
+    fn encode_into_buf_reuses_the_buffer() {
+        let mut buf = Vec::new();
+        let img = Image::new(64, 64);
+        encode_into_buf(&img, &mut buf).unwrap();
+        let capacity = buf.capacity();
+
+        let mut img = Image::new(2, 2);
+        img.set_pixel(0, 0, consts::RED);
+        img.set_pixel(1, 0, consts::LIME);
+        img.set_pixel(0, 1, consts::BLUE);
+        img.set_pixel(1, 1, consts::WHITE);
+        encode_into_buf(&img, &mut buf).unwrap();
+
+        assert_eq!(buf, fs::read("test/rgbw.bmp").unwrap());
+        assert_eq!(buf.capacity(), capacity);
+    }
+
     #[test]
     fn read_write_1pbb_bmp_image() {
         let img = open("test/bmptestsuite-0.9/valid/1bpp-1x1.bmp").unwrap();
@@ -470,6 +2010,931 @@ mod tests {
         assert_eq!(img.get_pixel(0, 0), consts::BLUE);
     }
 
+    #[test]
+    fn read_32bpp_bmp_image() {
+        let img = open("test/bmptestsuite-0.9/valid/32bpp-320x240.bmp").unwrap();
+        let expected = open("test/bmptestsuite-0.9/valid/24bpp-320x240.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+
+        let img = open("test/bmpsuite-2.5/g/rgb32.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+    }
+
+    /// Checks that `path` decodes to the pixels of `expected`, give or take `tolerance`.
+    fn assert_close_to(path: &str, expected: &Image, tolerance: i32) {
+        let img = open(path).unwrap();
+        assert_eq!(img.data.len(), expected.data.len(), "{}", path);
+        for (a, b) in img.data.iter().zip(expected.data.iter()) {
+            let close = |a: u8, b: u8| (a as i32 - b as i32).abs() <= tolerance;
+            let same = close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b);
+            assert!(same, "{}: {} vs {}", path, a, b);
+        }
+    }
+
+    #[test]
+    fn read_16bpp_bmp_image() {
+        let img = open("test/bmptestsuite-0.9/valid/555-1x1.bmp").unwrap();
+        assert_eq!(img.get_pixel(0, 0), consts::BLUE);
+        let img = open("test/bmptestsuite-0.9/valid/565-1x1.bmp").unwrap();
+        assert_eq!(img.get_pixel(0, 0), consts::BLUE);
+
+        // Both layouts only lose the low bits of each channel
+        let expected = open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        for name in &["rgb16.bmp", "rgb16-565.bmp", "rgb16bfdef.bmp"] {
+            assert_close_to(&format!("test/bmpsuite-2.5/g/{}", name), &expected, 8);
+        }
+    }
+
+    #[test]
+    fn read_bitfields_bmp_image() {
+        let expected = open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        for name in &["g/rgb32bf.bmp", "g/rgb32bfdef.bmp", "q/rgb32-xbgr.bmp"] {
+            let img = open(format!("test/bmpsuite-2.5/{}", name)).unwrap();
+            assert_eq!(img.data, expected.data, "{}", name);
+        }
+
+        // Channels wider or narrower than 8 bits are scaled
+        assert_close_to("test/bmpsuite-2.5/q/rgb32-111110.bmp", &expected, 2);
+        assert_close_to("test/bmpsuite-2.5/q/rgb32-7187.bmp", &expected, 2);
+    }
+
+    #[test]
+    fn read_rle8_bmp_image() {
+        let img = open("test/bmpsuite-2.5/g/pal8rle.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+
+        let expected = open("test/bmptestsuite-0.9/valid/8bpp-320x240.bmp").unwrap();
+        for name in &["rle8-absolute", "rle8-encoded"] {
+            let path = format!("test/bmptestsuite-0.9/valid/{}-320x240.bmp", name);
+            assert_eq!(open(path).unwrap().data, expected.data, "{}", name);
+        }
+        // Deltas leave pixels behind
+        let img = open("test/bmptestsuite-0.9/valid/rle8-delta-320x240.bmp").unwrap();
+        assert_eq!((img.get_width(), img.get_height()), (320, 240));
+    }
+
+    #[test]
+    fn read_rle4_bmp_image() {
+        let img = open("test/bmpsuite-2.5/g/pal4rle.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/pal4.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+
+        let expected = open("test/bmptestsuite-0.9/valid/4bpp-320x240.bmp").unwrap();
+        for name in &["rle4-absolute", "rle4-encoded"] {
+            let path = format!("test/bmptestsuite-0.9/valid/{}-320x240.bmp", name);
+            assert_eq!(open(path).unwrap().data, expected.data, "{}", name);
+        }
+    }
+
+    #[test]
+    fn read_top_down_bmp_image() {
+        let valid = "test/bmptestsuite-0.9/valid";
+        for (top_down, bottom_up) in &[
+            ("24bpp-topdown-320x240", "24bpp-320x240"),
+            ("8bpp-topdown-320x240", "8bpp-320x240"),
+            ("1bpp-topdown-320x240", "1bpp-320x240"),
+            ("565-320x240-topdown", "565-320x240"),
+        ] {
+            let img = open(format!("{}/{}.bmp", valid, top_down)).unwrap();
+            let expected = open(format!("{}/{}.bmp", valid, bottom_up)).unwrap();
+            assert_eq!(img.data, expected.data, "{}", top_down);
+        }
+
+        let mut data = Vec::new();
+        let mut info = ImageInfo::default();
+        let bmp_data = fs::read("test/bmpsuite-2.5/g/pal8topdown.bmp").unwrap();
+        decode_into(&bmp_data, &mut data, &mut info).unwrap();
+        assert_eq!(data, open("test/bmpsuite-2.5/g/pal8.bmp").unwrap().data);
+    }
+
+    #[test]
+    fn error_when_opening_compressed_top_down_image() {
+        match open("test/bmpsuite-2.5/b/rletopdown.bmp") {
+            Err(BmpError {
+                kind: BmpErrorKind::UnsupportedHeader,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Compressed images cannot be stored top-down"),
+        }
+    }
+
+    #[test]
+    fn read_os2_v2_bmp_image() {
+        let img = open("test/bmpsuite-2.5/q/pal8os2v2.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+    }
+
+    #[test]
+    fn read_os2_huffman_bmp_image() {
+        let img = open("test/bmpsuite-2.5/q/pal1huff.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/pal1wb.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+
+        let bmp_data = fs::read("test/bmpsuite-2.5/q/pal1huff.bmp").unwrap();
+        let streamed = stream::StreamDecoder::new(Cursor::new(&bmp_data))
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(streamed.data, expected.data);
+    }
+
+    #[test]
+    fn pixels_start_at_the_pixel_offset() {
+        // A gap between the palette and the pixels
+        let img = open("test/bmpsuite-2.5/q/pal8offs.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        assert_eq!(img.data, expected.data);
+        // A palette that true color pixels do not use
+        let img = open("test/bmpsuite-2.5/q/rgb24largepal.bmp").unwrap();
+        let rgb24 = open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        assert_eq!(img.data, rgb24.data);
+
+        // The same gap in front of true color pixels
+        let mut bmp_data = fs::read("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        let offset = u32::from_le_bytes(bmp_data[10..14].try_into().unwrap());
+        bmp_data.splice(offset as usize..offset as usize, [0xAB; 100]);
+        bmp_data[10..14].copy_from_slice(&(offset + 100).to_le_bytes());
+        let file_size = bmp_data.len() as u32;
+        bmp_data[2..6].copy_from_slice(&file_size.to_le_bytes());
+        let (img, warnings) = from_bytes_with_options(&bmp_data, DecodeOptions::default()).unwrap();
+        assert_eq!(img.data, rgb24.data);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn hotspot_is_kept_in_the_reserved_fields() {
+        let mut img = open("test/rgbw.bmp").unwrap();
+        assert_eq!(img.hotspot(), None);
+
+        img.set_hotspot(Some((1, 0)));
+        let mut bmp_data = Vec::new();
+        img.to_writer(&mut bmp_data).unwrap();
+        assert_eq!(&bmp_data[6..10], &[1, 0, 0, 0]);
+        let (decoded, warnings) =
+            from_bytes_with_options(&bmp_data, DecodeOptions::default()).unwrap();
+        assert_eq!(decoded.hotspot(), Some((1, 0)));
+        assert!(warnings.contains(&BmpWarning::NonzeroReserved {
+            creator1: 1,
+            creator2: 0
+        }));
+
+        img.set_hotspot(None);
+        let mut bmp_data = Vec::new();
+        img.to_writer(&mut bmp_data).unwrap();
+        assert_eq!(from_bytes(&bmp_data).unwrap().hotspot(), None);
+    }
+
+    #[test]
+    fn version_follows_the_header_size() {
+        for (name, version) in [
+            ("g/pal8.bmp", BmpVersion::Three),
+            ("g/rgb16-565.bmp", BmpVersion::ThreeNT),
+            ("g/pal8v4.bmp", BmpVersion::Four),
+            ("g/pal8v5.bmp", BmpVersion::Five),
+            ("q/pal8os2v2.bmp", BmpVersion::Os2Two),
+        ] {
+            let path = format!("test/bmpsuite-2.5/{}", name);
+            assert_eq!(open(&path).unwrap().version(), version, "{}", name);
+        }
+        assert_eq!(Image::new(1, 1).version(), BmpVersion::Three);
+    }
+
+    #[test]
+    fn metadata_describes_the_decoded_file() {
+        let img = open("test/bmpsuite-2.5/g/pal8topdown.bmp").unwrap();
+        let meta = img.metadata();
+        assert_eq!(meta.header_size, 40);
+        assert_eq!((meta.width, meta.height), (127, -64));
+        assert_eq!(meta.bits_per_pixel, 8);
+        assert_eq!(meta.num_colors, 252);
+
+        let meta = open("test/bmpsuite-2.5/g/pal8rle.bmp").unwrap().metadata();
+        assert_eq!(meta.compression, 1);
+
+        // Saved as plain 24 bpp, keeping the resolution
+        let img = open("test/bmpsuite-2.5/g/pal8v5.bmp").unwrap();
+        let meta = img.metadata();
+        let path = std::env::temp_dir().join("two-metadata-test.bmp");
+        img.save(&path).unwrap();
+        let saved = open(&path).unwrap().metadata();
+        assert_eq!((saved.header_size, saved.bits_per_pixel), (40, 24));
+        assert_eq!((saved.hres, saved.vres), (meta.hres, meta.vres));
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(Image::new(2, 3).metadata().bits_per_pixel, 24);
+    }
+
+    #[test]
+    fn palette_of_indexed_files() {
+        let img = open("test/bmpsuite-2.5/g/pal4.bmp").unwrap();
+        let palette = img.palette().unwrap();
+        assert_eq!(palette.len(), 12);
+        assert!(palette.contains(&img.get_pixel(0, 0)));
+
+        let rgb = open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        assert_eq!(rgb.palette(), None);
+        assert_eq!(Image::new(1, 1).palette(), None);
+    }
+
+    #[test]
+    fn small_palettes_and_2_bpp() {
+        let img = open("test/bmpsuite-2.5/q/pal2.bmp").unwrap();
+        assert_eq!(img.palette().unwrap().len(), 4);
+        assert_eq!(img.metadata().bits_per_pixel, 2);
+        let img = open("test/bmpsuite-2.5/q/pal2color.bmp").unwrap();
+        let palette = img.palette().unwrap();
+        assert!(img.data.iter().all(|px| palette.contains(px)));
+
+        match open("test/bmpsuite-2.5/b/pal8badindex.bmp") {
+            Err(BmpError {
+                kind: BmpErrorKind::PaletteIndexOutOfRange,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Indexes past the end of the palette should be an error"),
+        }
+
+        // Compressed indexes are looked up alike
+        let mut bmp_data = fs::read("test/bmpsuite-2.5/g/pal8rle.bmp").unwrap();
+        bmp_data[46..50].copy_from_slice(&2u32.to_le_bytes());
+        match from_bytes(&bmp_data) {
+            Err(BmpError {
+                kind: BmpErrorKind::PaletteIndexOutOfRange,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Run-length encoded indexes past the palette should be an error"),
+        }
+    }
+
+    #[test]
+    fn indices_match_the_palette() {
+        let options = DecodeOptions {
+            keep_indices: true,
+            ..DecodeOptions::default()
+        };
+        for name in [
+            "g/pal1.bmp",
+            "q/pal2.bmp",
+            "g/pal4.bmp",
+            "g/pal4rle.bmp",
+            "g/pal8.bmp",
+            "g/pal8topdown.bmp",
+            "g/pal8rle.bmp",
+            "q/pal1huff.bmp",
+        ] {
+            let path = format!("test/bmpsuite-2.5/{}", name);
+            let (img, _) = open_with_options(&path, options).unwrap();
+            let palette = img.palette().unwrap();
+            let indices = img.indices().unwrap();
+            assert_eq!(indices.len(), img.data.len(), "{}", name);
+            for (&i, px) in indices.iter().zip(img.data.iter()) {
+                assert_eq!(palette[i as usize], *px, "{}", name);
+            }
+        }
+
+        assert_eq!(
+            open("test/bmpsuite-2.5/g/pal8.bmp").unwrap().indices(),
+            None
+        );
+        let (img, _) = open_with_options("test/bmpsuite-2.5/g/rgb24.bmp", options).unwrap();
+        assert_eq!(img.indices(), None);
+    }
+
+    #[test]
+    fn alpha_of_32_and_16_bpp_images() {
+        let img = open("test/bmpsuite-2.5/q/rgba32.bmp").unwrap();
+        let alpha = img.alpha().unwrap();
+        assert_eq!(alpha.len(), 127 * 64);
+        // The first pixel of the file is at the bottom left
+        assert_eq!(alpha[127 * 63], 255);
+        assert!(alpha.contains(&0));
+        let img = open("test/bmpsuite-2.5/q/rgba16-4444.bmp").unwrap();
+        assert!(img.alpha().is_some());
+
+        // The unused byte is zero everywhere
+        assert_eq!(open("test/bmpsuite-2.5/g/rgb32.bmp").unwrap().alpha(), None);
+        let img = open_with_alpha("test/bmpsuite-2.5/g/rgb32.bmp", AlphaMode::Keep).unwrap();
+        assert!(img.alpha().unwrap().iter().all(|&a| a == 0));
+        let img = open_with_alpha("test/bmpsuite-2.5/q/rgba32.bmp", AlphaMode::Ignore).unwrap();
+        assert_eq!(img.alpha(), None);
+        assert_eq!(open("test/rgbw.bmp").unwrap().alpha(), None);
+    }
+
+    #[test]
+    fn truncated_pixel_data_is_filled_with_black() {
+        let path = "test/bmptestsuite-0.9/corrupt/24bpp-pixeldata-cropped.bmp";
+        match open(path) {
+            Err(BmpError {
+                kind: BmpErrorKind::BmpIoError(_),
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Truncated pixel data should not be accepted by default"),
+        }
+
+        let (img, warnings) = open_lenient(path).unwrap();
+        let missing_rows = 240 - 119;
+        assert!(warnings.contains(&BmpWarning::TruncatedPixelData { missing_rows }));
+        // The rows are stored bottom-up, so the top of the image is missing
+        let expected = open("test/bmptestsuite-0.9/valid/24bpp-320x240.bmp").unwrap();
+        let split = (missing_rows * 320) as usize;
+        assert!(img.data[..split].iter().all(|&px| px == consts::BLACK));
+        assert_eq!(img.data[split..], expected.data[split..]);
+
+        // Pixels starting past the end of the file are all missing
+        let path = "test/bmptestsuite-0.9/corrupt/offbits-negative.bmp";
+        let (img, warnings) = open_lenient(path).unwrap();
+        let missing_rows = img.get_height();
+        assert!(warnings.contains(&BmpWarning::TruncatedPixelData { missing_rows }));
+        assert!(img.data.iter().all(|&px| px == consts::BLACK));
+
+        for entry in fs::read_dir("test/bmptestsuite-0.9/corrupt").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "bmp") {
+                let lenient = std::panic::catch_unwind(|| open_lenient(&path).map(|_| ()));
+                assert!(lenient.is_ok(), "{} panics", path.display());
+            }
+        }
+
+        // Compressed pixels are smaller than the rows they expand to
+        for path in ["test/rgbw.bmp", "test/bmpsuite-2.5/q/pal1huff.bmp"] {
+            let (img, warnings) = open_lenient(path).unwrap();
+            let truncated = |w: &BmpWarning| matches!(w, BmpWarning::TruncatedPixelData { .. });
+            assert!(!warnings.iter().any(truncated), "{}", path);
+            assert_eq!(img, open(path).unwrap(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn untrusted_files_must_be_consistent() {
+        for path in [
+            "g/pal1.bmp",
+            "g/pal4rle.bmp",
+            "g/rgb16-565.bmp",
+            "q/pal8offs.bmp",
+        ] {
+            let path = format!("test/bmpsuite-2.5/{}", path);
+            let img = decode_untrusted(&fs::read(&path).unwrap()).unwrap();
+            assert_eq!(img, open(&path).unwrap());
+        }
+
+        let inconsistent = |bmp_data: &[u8]| match decode_untrusted(bmp_data) {
+            Err(BmpError {
+                kind: BmpErrorKind::InconsistentHeader,
+                ..
+            }) => (/* Expected */),
+            other => panic!("{:?} should be inconsistent", other.map(|_| ())),
+        };
+        let bmp_data = fs::read("test/bmpsuite-2.5/g/pal4.bmp").unwrap();
+        // A file size that is off, and the pixels inside the palette
+        let mut bad = bmp_data.clone();
+        bad[2] ^= 1;
+        inconsistent(&bad);
+        let mut bad = bmp_data.clone();
+        bad[10] -= 4;
+        inconsistent(&bad);
+        // Pixel data that ends early
+        let file_size = bmp_data.len() as u32 - 4;
+        let mut bad = bmp_data[..file_size as usize].to_vec();
+        bad[2..6].copy_from_slice(&file_size.to_le_bytes());
+        inconsistent(&bad);
+        inconsistent(&fs::read("test/bmpsuite-2.5/b/badfilesize.bmp").unwrap());
+        inconsistent(&fs::read("test/bmpsuite-2.5/b/badplanes.bmp").unwrap());
+        inconsistent(&fs::read("test/bmpsuite-2.5/b/shortfile.bmp").unwrap());
+    }
+
+    #[test]
+    fn decoder_applies_its_options() {
+        let path = "test/bmpsuite-2.5/g/pal8.bmp";
+        let expected = open(path).unwrap();
+        let img = Decoder::new(fs::File::open(path).unwrap())
+            .decode()
+            .unwrap();
+        assert_eq!(img, expected);
+        assert_eq!(img.indices(), None);
+
+        let img = Decoder::new(fs::File::open(path).unwrap())
+            .flip_vertical(true)
+            .keep_indexed(true)
+            .decode()
+            .unwrap();
+        let (width, height) = (img.get_width(), img.get_height());
+        for (x, y) in img.coordinates() {
+            assert_eq!(img.get_pixel(x, y), expected.get_pixel(x, height - y - 1));
+        }
+        let palette = img.palette().unwrap();
+        let bottom_left = img.indices().unwrap()[0] as usize;
+        assert_eq!(palette[bottom_left], expected.get_pixel(0, height - 1));
+
+        // Top-down files are flipped as well
+        let img = Decoder::new(fs::File::open("test/bmpsuite-2.5/g/pal8topdown.bmp").unwrap())
+            .flip_vertical(true)
+            .decode()
+            .unwrap();
+        assert_eq!(img.get_pixel(0, 0), expected.get_pixel(0, height - 1));
+
+        let limits = Limits {
+            max_width: width - 1,
+            ..Limits::default()
+        };
+        match Decoder::new(fs::File::open(path).unwrap())
+            .limits(limits)
+            .decode()
+        {
+            Err(BmpError {
+                kind: BmpErrorKind::LimitsExceeded,
+                ..
+            }) => (/* Expected */),
+            other => panic!("{:?} should exceed the limits", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unused_header_fields_are_only_reported() {
+        let valid = open("test/bmptestsuite-0.9/valid/1bpp-320x240.bmp").unwrap();
+        for (name, planes) in [("planes-zero", 0), ("planes-large", 5000)] {
+            let path = format!("test/bmptestsuite-0.9/questionable/{}.bmp", name);
+            let bmp_data = fs::read(&path).unwrap();
+            let (img, warnings) = open_with_report(&path).unwrap();
+            assert_eq!(warnings, vec![BmpWarning::WrongPlanes { planes }]);
+            assert_eq!(img.data, valid.data);
+            // Untrusted files must still get every field right
+            match decode_untrusted(&bmp_data) {
+                Err(BmpError {
+                    kind: BmpErrorKind::InconsistentHeader,
+                    ..
+                }) => (/* Expected */),
+                _ => panic!("{} planes should not be trusted", planes),
+            }
+        }
+
+        // An absurd size of the pixel data, which is not used
+        let expected = open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        let mut bmp_data = fs::read("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        bmp_data[34..38].copy_from_slice(&u32::MAX.to_le_bytes());
+        let (img, warnings) = from_bytes_with_options(&bmp_data, DecodeOptions::default()).unwrap();
+        assert_eq!(img.data, expected.data);
+        assert_eq!(
+            warnings,
+            vec![BmpWarning::WrongDataSize {
+                declared: u32::MAX,
+                expected: 128 * 64
+            }]
+        );
+        assert_eq!(decode_untrusted(&bmp_data).unwrap().data, expected.data);
+    }
+
+    #[test]
+    fn progress_is_reported_every_64_rows() {
+        for name in ["24bpp", "8bpp", "rle8-encoded", "565"] {
+            let path = format!("test/bmptestsuite-0.9/valid/{}-320x240.bmp", name);
+            let mut calls = Vec::new();
+            let img = Decoder::new(fs::File::open(&path).unwrap())
+                .on_progress(|done, total| calls.push((done, total)))
+                .decode()
+                .unwrap();
+            assert_eq!(img, open(&path).unwrap());
+            let expected = vec![(64, 240), (128, 240), (192, 240), (240, 240)];
+            assert_eq!(calls, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn cancelled_decodes_stop_between_rows() {
+        let path = "test/bmptestsuite-0.9/valid/24bpp-320x240.bmp";
+        let cancelled = |result: BmpResult<Image>| match result {
+            Err(BmpError {
+                kind: BmpErrorKind::Cancelled,
+                details,
+            }) => details,
+            other => panic!("{:?} should be cancelled", other.map(|_| ())),
+        };
+
+        let token = CancellationToken::new();
+        let decoder = Decoder::new(fs::File::open(path).unwrap()).cancel_with(token.clone());
+        token.cancel();
+        let details = cancelled(decoder.decode());
+        assert!(details.contains("after 0 of 240 rows"), "{}", details);
+
+        // Cancelled from the progress hook, as a GUI would
+        let token = CancellationToken::new();
+        let hook_token = token.clone();
+        let details = cancelled(
+            Decoder::new(fs::File::open(path).unwrap())
+                .cancel_with(token)
+                .on_progress(move |done, _| {
+                    if done == 64 {
+                        hook_token.cancel();
+                    }
+                })
+                .decode(),
+        );
+        assert!(details.contains("after 64 of 240 rows"), "{}", details);
+
+        let img = Decoder::new(fs::File::open(path).unwrap())
+            .cancel_with(CancellationToken::new())
+            .decode()
+            .unwrap();
+        assert_eq!(img, open(path).unwrap());
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected_without_panicking() {
+        let mut paths: Vec<_> = fs::read_dir("test/bmptestsuite-0.9/corrupt")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bmp"))
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 50);
+        for path in paths {
+            let bmp_data = fs::read(&path).unwrap();
+            // The lenient decoder may guess, but must not panic either
+            let lenient = std::panic::catch_unwind(|| from_bytes(&bmp_data).map(|_| ()));
+            assert!(lenient.is_ok(), "{} panics", path.display());
+            let strict = std::panic::catch_unwind(|| decode_untrusted(&bmp_data).map(|_| ()));
+            match strict {
+                Ok(Err(_)) => (/* Expected */),
+                Ok(Ok(())) => panic!("{} should be rejected", path.display()),
+                Err(_) => panic!("{} panics", path.display()),
+            }
+        }
+
+        let bmp_data = fs::read("test/bmptestsuite-0.9/valid/8bpp-320x240.bmp").unwrap();
+        let craft = |at: usize, value: &[u8]| {
+            let mut bad = bmp_data.clone();
+            bad[at..at + value.len()].copy_from_slice(value);
+            bad
+        };
+        for bad in [
+            // Zero bits per pixel, a huge palette, pixels past the end of the
+            // file and a negative width
+            craft(28, &0u16.to_le_bytes()),
+            craft(46, &u32::MAX.to_le_bytes()),
+            craft(10, &(bmp_data.len() as u32 + 1).to_le_bytes()),
+            craft(18, &(-320i32).to_le_bytes()),
+        ] {
+            assert!(std::panic::catch_unwind(|| from_bytes(&bad).map(|_| ())).is_ok());
+            assert!(decode_untrusted(&bad).is_err());
+        }
+    }
+
+    #[test]
+    fn oddities_of_the_headers_are_reported() {
+        let (_, warnings) = open_with_report("test/bmpsuite-2.5/g/pal1.bmp").unwrap();
+        assert!(warnings.is_empty());
+
+        let mut bmp_data = fs::read("test/bmpsuite-2.5/g/pal4.bmp").unwrap();
+        let actual = bmp_data.len() as u64;
+        bmp_data[2..6].copy_from_slice(&10u32.to_le_bytes());
+        bmp_data[6] = 1;
+        // Thirteen colors, though the pixels start after twelve
+        bmp_data[46] = 13;
+        let (img, warnings) = from_bytes_with_options(&bmp_data, DecodeOptions::default()).unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                BmpWarning::WrongFileSize {
+                    declared: 10,
+                    actual
+                },
+                BmpWarning::ShortPalette {
+                    declared: 13,
+                    available: 12
+                },
+                BmpWarning::NonzeroReserved {
+                    creator1: 1,
+                    creator2: 0
+                },
+            ]
+        );
+        assert_eq!(img.data, open("test/bmpsuite-2.5/g/pal4.bmp").unwrap().data);
+
+        let mut bmp_data = fs::read("test/bmpsuite-2.5/g/pal1.bmp").unwrap();
+        bmp_data[38..42].copy_from_slice(&5669i32.to_le_bytes());
+        bmp_data[42..46].copy_from_slice(&2835i32.to_le_bytes());
+        let (img, warnings) = from_bytes_with_options(&bmp_data, DecodeOptions::default()).unwrap();
+        assert_eq!(
+            warnings,
+            vec![BmpWarning::NonSquarePixels {
+                hres: 5669,
+                vres: 2835
+            }]
+        );
+        assert!((img.pixel_aspect_ratio() - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn embedded_jpeg_and_png_streams() {
+        match open("test/bmpsuite-2.5/q/rgb24png.bmp") {
+            Err(BmpError {
+                kind: BmpErrorKind::EmbeddedPng,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("An embedded PNG file should be reported as such"),
+        }
+
+        let bmp_data = fs::read("test/bmpsuite-2.5/q/rgb24jpeg.bmp").unwrap();
+        let stream = Image::embedded_stream(&bmp_data).unwrap().unwrap();
+        assert_eq!(stream.format, EmbeddedFormat::Jpeg);
+        assert_eq!(stream.data.len(), 2319);
+        assert_eq!(stream.data[..2], [0xFF, 0xD8]);
+
+        let bmp_data = fs::read("test/rgbw.bmp").unwrap();
+        assert_eq!(Image::embedded_stream(&bmp_data).unwrap(), None);
+    }
+
+    #[test]
+    fn huge_dimensions_exceed_the_limits() {
+        let mut bmp_data = fs::read("test/rgbw.bmp").unwrap();
+        bmp_data[18..22].copy_from_slice(&i32::MAX.to_le_bytes());
+        match from_bytes(&bmp_data) {
+            Err(BmpError {
+                kind: BmpErrorKind::LimitsExceeded,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("A huge width should exceed the default limits"),
+        }
+
+        let rgbw = fs::read("test/rgbw.bmp").unwrap();
+        let options = DecodeOptions {
+            limits: Limits {
+                max_pixel_bytes: 15,
+                ..Limits::default()
+            },
+            ..DecodeOptions::default()
+        };
+        match from_bytes_with_options(&rgbw, options) {
+            Err(BmpError {
+                kind: BmpErrorKind::LimitsExceeded,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Four pixels should not fit in 15 bytes"),
+        }
+        let options = DecodeOptions {
+            limits: Limits::none(),
+            ..DecodeOptions::default()
+        };
+        assert!(from_bytes_with_options(&rgbw, options).is_ok());
+
+        // Without limits, huge headers still fail on their missing pixels
+        let mut bmp_data = fs::read("test/bmpsuite-2.5/g/pal1.bmp").unwrap();
+        bmp_data[18..22].copy_from_slice(&100_000i32.to_le_bytes());
+        bmp_data[22..26].copy_from_slice(&100_000i32.to_le_bytes());
+        assert!(from_bytes_with_options(&bmp_data, options).is_err());
+    }
+
+    #[test]
+    fn premultiplied_alpha_round_trip() {
+        let mut img = Image::new(3, 1);
+        img.data = vec![consts::WHITE, px!(200, 100, 10), consts::RED];
+        img.alpha = Some(vec![255, 128, 0]);
+
+        img.premultiply_alpha();
+        assert_eq!(img.data[1..], [px!(100, 50, 5), consts::BLACK]);
+        img.unpremultiply_alpha();
+        assert_eq!(img.data[1..], [px!(199, 100, 10), consts::BLACK]);
+        assert_eq!(img.data[0], consts::WHITE);
+
+        let mut opaque = Image::new(1, 1);
+        opaque.fill(consts::RED);
+        opaque.premultiply_alpha();
+        assert_eq!(opaque.get_pixel(0, 0), consts::RED);
+    }
+
+    #[test]
+    fn color_space_of_v4_and_v5_images() {
+        let img = open("test/bmpsuite-2.5/g/pal8v4.bmp").unwrap();
+        let cs = img.color_space().unwrap();
+        assert_eq!(cs.cs_type, ColorSpaceType::Calibrated);
+        assert!((cs.endpoints[0][0] - 0.64).abs() < 1e-6);
+        assert!((cs.endpoints[2][2] - 0.79).abs() < 1e-6);
+        assert!((cs.gamma[1] - 2.2).abs() < 1e-4);
+
+        let img = open("test/bmpsuite-2.5/g/pal8v5.bmp").unwrap();
+        assert_eq!(img.color_space().unwrap().cs_type, ColorSpaceType::Srgb);
+        let img = open("test/bmpsuite-2.5/q/rgb24prof.bmp").unwrap();
+        assert_eq!(img.color_space().unwrap().cs_type, ColorSpaceType::Embedded);
+        let img = open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        assert_eq!(img.color_space(), None);
+    }
+
+    #[test]
+    fn icc_profiles_of_v5_images() {
+        let img = open("test/bmpsuite-2.5/q/rgb24prof.bmp").unwrap();
+        let profile = img.icc_profile().unwrap();
+        assert_eq!(profile.len(), 3048);
+        assert_eq!(&profile[36..40], b"acsp");
+        assert_eq!(img.linked_icc_profile(), None);
+
+        let img = open("test/bmpsuite-2.5/q/rgb24lprof.bmp").unwrap();
+        assert_eq!(img.icc_profile(), None);
+        assert!(img.linked_icc_profile().unwrap().ends_with(b".icc"));
+
+        let img = open("test/bmpsuite-2.5/g/pal8v5.bmp").unwrap();
+        assert_eq!(img.icc_profile(), None);
+    }
+
+    #[test]
+    fn decode_from_bytes() {
+        let bytes = fs::read("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        let expected = open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap(), expected);
+        assert_eq!(Image::try_from(&bytes[..]).unwrap(), expected);
+
+        match from_bytes(&bytes[..20]) {
+            Err(BmpError {
+                kind: BmpErrorKind::BmpIoError(_),
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Truncated headers should not be decoded"),
+        }
+    }
+
+    #[test]
+    fn dpi_is_saved_as_pixels_per_meter() {
+        let mut img = Image::new(2, 2);
+        img.set_dpi(300.0, 72.0);
+        assert_eq!((img.metadata().hres, img.metadata().vres), (11811, 2835));
+
+        let path = std::env::temp_dir().join("two-dpi-test.bmp");
+        img.save(&path).unwrap();
+        let (x, y) = open(&path).unwrap().dpi();
+        assert!((x - 300.0).abs() < 0.01 && (y - 72.0).abs() < 0.01);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn top_down_files_have_a_negative_height() {
+        let img = open("test/rgbw.bmp").unwrap();
+        let options = EncoderOptions::default().top_down(true);
+        let mut bytes = Vec::new();
+        img.to_writer_with_options(&mut bytes, &options).unwrap();
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata().height, -2);
+        assert_eq!(decoded.data, img.data);
+        // The top row comes first
+        assert_eq!(&bytes[54..57], &[0, 0, 255]);
+
+        let mut bytes = Vec::new();
+        img.quantize()
+            .to_writer_with_options(&mut bytes, &options)
+            .unwrap();
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata().height, -2);
+        assert_eq!(decoded.data, img.data);
+
+        let options = options.compression(Compression::Rle8);
+        let err = img
+            .to_writer_with_options(&mut bytes, &options)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn newer_headers_keep_alpha_and_tag_srgb() {
+        let img = open("test/bmpsuite-2.5/q/rgba32.bmp").unwrap();
+        for (version, size) in [(BmpVersion::Four, 108), (BmpVersion::Five, 124)] {
+            let options = EncoderOptions::default().version(version);
+            let mut bytes = Vec::new();
+            img.to_writer_with_options(&mut bytes, &options).unwrap();
+
+            let decoded = from_bytes(&bytes).unwrap();
+            let meta = decoded.metadata();
+            assert_eq!(meta.header_size, size);
+            assert_eq!((meta.bits_per_pixel, meta.compression), (32, 3));
+            assert_eq!(decoded.version(), version);
+            assert_eq!(decoded.color_space().unwrap().cs_type, ColorSpaceType::Srgb);
+            assert_eq!(
+                (decoded.data, decoded.alpha),
+                (img.data.clone(), img.alpha.clone())
+            );
+        }
+
+        // Without alpha, only the header grows
+        let img = open("test/rgbw.bmp").unwrap();
+        let options = EncoderOptions::default().version(BmpVersion::Five);
+        let mut bytes = Vec::new();
+        img.to_writer_with_options(&mut bytes, &options).unwrap();
+        assert_eq!(bytes.len(), fs::read("test/rgbw.bmp").unwrap().len() + 84);
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata().bits_per_pixel, 24);
+        assert_eq!(decoded.data, img.data);
+        // Only alpha needs the masks of the newer headers
+        let options = EncoderOptions::default();
+        let mut bytes = Vec::new();
+        open("test/bmpsuite-2.5/q/rgba32.bmp")
+            .unwrap()
+            .to_writer_with_options(&mut bytes, &options)
+            .unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap().metadata().bits_per_pixel, 24);
+
+        let options = EncoderOptions::default().version(BmpVersion::Os2Two);
+        let err = img
+            .to_writer_with_options(&mut bytes, &options)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn icc_profiles_are_embedded_after_the_pixels() {
+        let source = open("test/bmpsuite-2.5/q/rgb24prof.bmp").unwrap();
+        let profile = source.icc_profile().unwrap().to_vec();
+        let options = EncoderOptions::default().icc_profile(profile.clone());
+        let mut bytes = Vec::new();
+        source.to_writer_with_options(&mut bytes, &options).unwrap();
+        assert!(bytes.ends_with(&profile));
+
+        let decoded = decode_untrusted(&bytes).unwrap();
+        assert_eq!(decoded.version(), BmpVersion::Five);
+        let info = decoded.color_space().unwrap();
+        assert_eq!(info.cs_type, ColorSpaceType::Embedded);
+        assert_eq!(decoded.icc_profile(), Some(&profile[..]));
+        assert_eq!(decoded.data, source.data);
+
+        let indexed = source.quantize();
+        let mut bytes = Vec::new();
+        let options = options.compression(Compression::Rle8);
+        indexed
+            .to_writer_with_options(&mut bytes, &options)
+            .unwrap();
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.icc_profile(), Some(&profile[..]));
+        assert_eq!(decoded.data, indexed.to_image().data);
+    }
+
+    #[test]
+    fn options_set_the_bit_depth() {
+        let img = open("test/rgbw.bmp").unwrap();
+        for bpp in [1, 2, 4, 8, 24, 32] {
+            let options = EncoderOptions::default().bits_per_pixel(bpp);
+            let mut bytes = Vec::new();
+            img.to_writer_with_options(&mut bytes, &options).unwrap();
+            let decoded = from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.metadata().bits_per_pixel, bpp);
+            // Two colors cannot keep all four
+            if bpp > 1 {
+                assert_eq!(decoded.data, img.data, "{}", bpp);
+            }
+        }
+
+        let indexed = img.quantize();
+        let options = EncoderOptions::default().bits_per_pixel(2);
+        let mut bytes = Vec::new();
+        indexed
+            .to_writer_with_options(&mut bytes, &options)
+            .unwrap();
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.metadata().bits_per_pixel, 2);
+        assert_eq!(decoded.data, img.data);
+
+        for options in [
+            EncoderOptions::default().bits_per_pixel(16),
+            EncoderOptions::default()
+                .bits_per_pixel(4)
+                .compression(Compression::Rle8),
+            EncoderOptions::default()
+                .bits_per_pixel(1)
+                .palette(img.data.clone()),
+        ] {
+            let err = img
+                .to_writer_with_options(&mut bytes, &options)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn options_set_the_palette_resolution_and_hotspot() {
+        let img = open("test/rgbw.bmp").unwrap();
+        let palette = vec![consts::BLACK, consts::WHITE];
+        let options = EncoderOptions::default()
+            .bits_per_pixel(1)
+            .palette(palette.clone())
+            .dpi(300.0, 72.0)
+            .hotspot(1, 0);
+        let mut bytes = Vec::new();
+        img.to_writer_with_options(&mut bytes, &options).unwrap();
+
+        let decoded = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.palette(), Some(&palette[..]));
+        assert_eq!(
+            (decoded.metadata().hres, decoded.metadata().vres),
+            (11811, 2835)
+        );
+        assert_eq!(decoded.hotspot(), Some((1, 0)));
+        // Every pixel takes the nearest color
+        for (px, saved) in img.pixels().iter().zip(decoded.pixels()) {
+            let nearest = if px.r as u32 + px.g as u32 + px.b as u32 > 382 {
+                consts::WHITE
+            } else {
+                consts::BLACK
+            };
+            assert_eq!(*saved, nearest);
+        }
+    }
+
     #[test]
     fn read_write_bmp_v3_image() {
         let bmp_img = open("test/bmptestsuite-0.9/valid/24bpp-320x240.bmp").unwrap();
@@ -479,13 +2944,17 @@ mod tests {
     #[test]
     fn read_write_bmp_v4_image() {
         let bmp_img = open("test/bmpsuite-2.5/g/pal8v4.bmp").unwrap();
-        bmp_img.save("test/pal8v4-test.bmp").unwrap();
+        let path = std::env::temp_dir().join("two-pal8v4-test.bmp");
+        bmp_img.save(&path).unwrap();
+        let _ = fs::remove_file(&path);
     }
 
     #[test]
     fn read_write_bmp_v5_image() {
         let bmp_img = open("test/bmpsuite-2.5/g/pal8v5.bmp").unwrap();
-        bmp_img.save("test/pal8v5-test.bmp").unwrap();
+        let path = std::env::temp_dir().join("two-pal8v5-test.bmp");
+        bmp_img.save(&path).unwrap();
+        let _ = fs::remove_file(&path);
     }
 
     #[test]
@@ -502,13 +2971,13 @@ mod tests {
 
     #[test]
     fn error_when_opening_image_with_wrong_bits_per_pixel() {
-        let result = open("test/bmptestsuite-0.9/valid/32bpp-1x1.bmp");
+        let result = open("test/bmpsuite-2.5/b/badbitcount.bmp");
         match result {
             Err(BmpError {
                 kind: BmpErrorKind::UnsupportedBitsPerPixel,
                 ..
             }) => (/* Expected */),
-            _ => panic!("32bpp are not yet supported"),
+            _ => panic!("Only the bit depths of the BMP formats are supported"),
         }
     }
 
@@ -552,6 +3021,106 @@ mod tests {
         assert_eq!(img.get_pixel(1, 0), consts::WHITE);
     }
 
+    #[test]
+    fn copy_from_clips_region_against_both_images() {
+        let mut src = Image::new(3, 3);
+        src.set_pixel(1, 1, consts::RED);
+        src.set_pixel(2, 2, consts::BLUE);
+
+        let mut dst = Image::new(2, 2);
+        dst.copy_from(&src, (1, 1, 5, 5), (-1, 0));
+        assert_eq!(dst.get_pixel(0, 1), consts::BLUE);
+        assert_eq!(dst.get_pixel(0, 0), consts::BLACK);
+
+        dst.copy_from(&src, (0, 0, 3, 3), (0, 0));
+        assert_eq!(dst.get_pixel(1, 1), consts::RED);
+    }
+
+    #[test]
+    fn autocrop_trims_uniform_margins() {
+        let mut img = Image::new(10, 8);
+        img.fill(consts::WHITE);
+        img.fill_rect((2, 3, 4, 2), consts::RED);
+        img.set_pixel(7, 6, px!(250, 250, 250));
+
+        let cropped = img.autocrop(None, 10);
+        assert_eq!((cropped.get_width(), cropped.get_height()), (4, 2));
+        assert_eq!(cropped, img.crop((2, 3, 4, 2)));
+
+        // Without tolerance the off-white pixel is content
+        let cropped = img.autocrop(Some(consts::WHITE), 0);
+        assert_eq!((cropped.get_width(), cropped.get_height()), (6, 4));
+
+        let cropped = Image::new(3, 3).autocrop(None, 0);
+        assert_eq!(cropped.get_width() * cropped.get_height(), 0);
+    }
+
+    #[test]
+    fn fill_rect_is_clipped_to_the_image() {
+        let mut img = Image::new(4, 3);
+        img.fill(consts::WHITE);
+        img.fill_rect((2, 1, 10, 10), consts::RED);
+
+        for (x, y) in img.coordinates() {
+            let expected = if x >= 2 && y >= 1 {
+                consts::RED
+            } else {
+                consts::WHITE
+            };
+            assert_eq!(img.get_pixel(x, y), expected);
+        }
+
+        img.fill_rect((4, 0, 1, 1), consts::BLUE);
+        img.fill_rect((0, 0, 0, 3), consts::BLUE);
+        assert!(img.data.iter().all(|&px| px != consts::BLUE));
+    }
+
+    #[test]
+    fn file_sizes_are_exact_and_checked() {
+        assert_eq!(decoder::file_size(24, 2, 2).unwrap(), (54, 16));
+        assert_eq!(decoder::file_size(1, 33, 1).unwrap(), (54, 8));
+        // Floats lose the last bits of such rows
+        let (_, data_size) = decoder::file_size(24, 16_777_217, 1).unwrap();
+        assert_eq!(data_size, 50_331_652);
+        assert_eq!(decoder::row_size(u32::MAX, 64), 34_359_738_360);
+
+        for (bpp, width, height) in [(24, 1 << 16, 1 << 16), (64, u32::MAX, u32::MAX)] {
+            match decoder::file_size(bpp, width, height) {
+                Err(BmpError {
+                    kind: BmpErrorKind::LimitsExceeded,
+                    ..
+                }) => (/* Expected */),
+                other => panic!("{:?} should not fit into a file", other),
+            }
+        }
+    }
+
+    #[test]
+    fn flip_rows_reverses_row_order() {
+        let mut data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+        flip_rows(&mut data, 3);
+        assert_eq!(data, vec![7, 8, 9, 4, 5, 6, 1, 2, 3]);
+
+        let mut data = vec![1, 2, 3, 4];
+        flip_rows(&mut data, 1);
+        assert_eq!(data, vec![4, 3, 2, 1]);
+        flip_rows(&mut data, 0);
+    }
+
+    #[test]
+    fn pixel_conversions() {
+        assert_eq!(Pixel::from_f32(1.5, 0.5, f32::NAN), px!(255, 128, 0));
+        assert_eq!(Pixel::from_f32(-1.0, 0.0, 1.0), consts::BLUE);
+        assert_eq!(px!(255, 0, 51).to_f32(), [1.0, 0.0, 0.2]);
+
+        assert_eq!(Pixel::from([1, 2, 3]), px!(1, 2, 3));
+        assert_eq!(Pixel::from((1, 2, 3)), px!(1, 2, 3));
+        let rgb: [u8; 3] = px!(4, 5, 6).into();
+        assert_eq!(rgb, [4, 5, 6]);
+        let rgb: (u8, u8, u8) = px!(4, 5, 6).into();
+        assert_eq!(rgb, (4, 5, 6));
+    }
+
     #[test]
     fn coordinates_iterator_gives_x_and_y_in_row_major_order() {
         let img = Image::new(2, 3);
@@ -0,0 +1,147 @@
+//! Merging of several captures of the same scene.
+
+use crate::decoder::{BmpError, BmpErrorKind, BmpResult};
+use crate::policy::ColorPolicy;
+use crate::{Image, Pixel};
+
+// Spread of the well-exposedness weight around mid-gray, as in Mertens et al.
+const SIGMA: f32 = 0.2;
+// Keeps the weights of flat, badly exposed areas from all dropping to zero.
+const EPSILON: f32 = 1e-6;
+
+/// Fuses bracketed exposures into a single tone-balanced image.
+///
+/// Every pixel is a weighted average of the inputs, where the weight favours
+/// local contrast, saturated colors and values close to mid-gray (Mertens et al.).
+/// The weights are applied per pixel instead of through a Laplacian pyramid,
+/// which is much simpler but may show seams where the best exposure changes abruptly.
+///
+/// All images must be of the same size.
+pub fn exposure_fuse(images: &[Image]) -> BmpResult<Image> {
+    exposure_fuse_with(images, ColorPolicy::default())
+}
+
+/// Like `exposure_fuse`, converting the averages with `policy`.
+pub fn exposure_fuse_with(images: &[Image], policy: ColorPolicy) -> BmpResult<Image> {
+    let first = match images.first() {
+        Some(first) => first,
+        None => {
+            return Err(BmpError::new(
+                BmpErrorKind::DimensionMismatch,
+                "Expected at least one image to fuse",
+            ))
+        }
+    };
+    let (width, height) = (first.width, first.height);
+    if let Some(img) = images
+        .iter()
+        .find(|img| img.width != width || img.height != height)
+    {
+        return Err(BmpError::new(
+            BmpErrorKind::DimensionMismatch,
+            format!(
+                "Expected {}x{} images, but found one of {}x{}",
+                width, height, img.width, img.height
+            ),
+        ));
+    }
+
+    let len = (width * height) as usize;
+    let mut sums = vec![[0f32; 3]; len];
+    let mut weights = vec![0f32; len];
+    for img in images {
+        for (i, (x, y)) in img.coordinates().enumerate() {
+            let px = img.get_pixel(x, y);
+            let w = contrast(img, x, y) * saturation(px) * well_exposedness(px) + EPSILON;
+            sums[i][0] += w * px.r as f32;
+            sums[i][1] += w * px.g as f32;
+            sums[i][2] += w * px.b as f32;
+            weights[i] += w;
+        }
+    }
+
+    let mut fused = Image::new(width, height);
+    for (i, (x, y)) in fused.coordinates().enumerate() {
+        let (sum, total) = (sums[i], weights[i]);
+        let c = |s: f32| policy.to_u8(s / total);
+        fused.set_pixel(x, y, Pixel::new(c(sum[0]), c(sum[1]), c(sum[2])));
+    }
+    Ok(fused)
+}
+
+#[inline]
+fn intensity(px: Pixel) -> f32 {
+    (px.r as f32 + px.g as f32 + px.b as f32) / (3.0 * 255.0)
+}
+
+/// Absolute response of a Laplacian filter on the intensity, edges are replicated.
+fn contrast(img: &Image, x: u32, y: u32) -> f32 {
+    let at = |dx: i64, dy: i64| {
+        let x = (x as i64 + dx).clamp(0, img.width as i64 - 1) as u32;
+        let y = (y as i64 + dy).clamp(0, img.height as i64 - 1) as u32;
+        intensity(img.get_pixel(x, y))
+    };
+    (at(-1, 0) + at(1, 0) + at(0, -1) + at(0, 1) - 4.0 * at(0, 0)).abs()
+}
+
+/// Standard deviation of the channels.
+fn saturation(px: Pixel) -> f32 {
+    let (r, g, b) = (
+        px.r as f32 / 255.0,
+        px.g as f32 / 255.0,
+        px.b as f32 / 255.0,
+    );
+    let mu = (r + g + b) / 3.0;
+    (((r - mu).powi(2) + (g - mu).powi(2) + (b - mu).powi(2)) / 3.0).sqrt()
+}
+
+/// Product of per-channel Gaussians centered on mid-gray.
+fn well_exposedness(px: Pixel) -> f32 {
+    let g = |c: u8| (-(c as f32 / 255.0 - 0.5).powi(2) / (2.0 * SIGMA * SIGMA)).exp();
+    g(px.r) * g(px.g) * g(px.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    fn flat(px: Pixel) -> Image {
+        let mut img = Image::new(4, 4);
+        img.fill(px);
+        img
+    }
+
+    #[test]
+    fn fusing_one_image_returns_it() {
+        let mut img = flat(consts::CORAL);
+        img.fill_rect((1, 1, 2, 2), consts::STEEL_BLUE);
+        assert_eq!(exposure_fuse(&[img.clone()]).unwrap(), img);
+    }
+
+    #[test]
+    fn well_exposed_detail_wins() {
+        let dark = flat(px!(5, 5, 5));
+        let bright = flat(px!(250, 250, 250));
+        let mut good = flat(px!(120, 130, 140));
+        good.fill_rect((0, 0, 2, 4), px!(140, 110, 100));
+
+        let fused = exposure_fuse(&[dark, good.clone(), bright]).unwrap();
+        let (a, b) = (fused.get_pixel(1, 1), good.get_pixel(1, 1));
+        assert!((a.r as i32 - b.r as i32).abs() <= 2, "{} vs {}", a, b);
+        assert!((a.b as i32 - b.b as i32).abs() <= 2, "{} vs {}", a, b);
+    }
+
+    #[test]
+    fn error_when_sizes_differ_or_nothing_to_fuse() {
+        for images in &[vec![], vec![Image::new(2, 2), Image::new(2, 3)]] {
+            match exposure_fuse(images) {
+                Err(BmpError {
+                    kind: BmpErrorKind::DimensionMismatch,
+                    ..
+                }) => (/* Expected */),
+                _ => panic!("Only equally sized images can be fused"),
+            }
+        }
+    }
+}
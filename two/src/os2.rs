@@ -0,0 +1,191 @@
+//! OS/2 bitmap arrays, which bundle several bitmaps in one file.
+//!
+//! Every entry starts with a `BA` header pointing to the next entry, followed
+//! by an ordinary `BM` bitmap. They typically hold the same icon or pointer
+//! for different display resolutions. Offsets within the bitmaps count from
+//! the start of the array file.
+//!
+//! Like the rest of the decoder, arrays only hold bitmaps with a version 3 or
+//! later header. Entries with the 12 byte `BITMAPCOREHEADER` of OS/2 1.x, whose
+//! palettes take 3 bytes per color, fail with `UnsupportedBmpVersion`.
+//!
+//! ```no_run
+//! let images = two::open_array("pointers.bmp").unwrap();
+//! for img in &images {
+//!     println!("{}x{}", img.get_width(), img.get_height());
+//! }
+//! ```
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::decoder::{self, BmpError, BmpErrorKind, BmpResult};
+use crate::{wire, Image, Limits};
+
+const ARRAY_MAGIC: [u8; 2] = *b"BA";
+// The magic, the size of the header, the offset of the next entry and the
+// screen size the bitmap is meant for
+const ARRAY_HEADER_SIZE: usize = 14;
+
+/// Decodes every bitmap of the array at `path`, in file order. A plain bitmap
+/// is taken as an array of one.
+pub fn open_array<P: AsRef<Path>>(path: P) -> BmpResult<Vec<Image>> {
+    from_bytes_array(&fs::read(path)?)
+}
+
+/// Like `open_array`, decoding the array in `bytes`.
+pub fn from_bytes_array(bytes: &[u8]) -> BmpResult<Vec<Image>> {
+    if bytes.starts_with(&wire::MAGIC) {
+        return crate::from_bytes(bytes).map(|img| vec![img]);
+    }
+
+    let mut images = Vec::new();
+    let mut pos = 0;
+    loop {
+        let header = bytes.get(pos..pos + ARRAY_HEADER_SIZE).ok_or_else(|| {
+            BmpError::new(
+                BmpErrorKind::UnsupportedHeader,
+                format!("The array entry at {} lies outside of the file", pos),
+            )
+        })?;
+        if header[..2] != ARRAY_MAGIC {
+            return Err(BmpError::new(
+                BmpErrorKind::WrongMagicNumbers,
+                format!("Expected [66, 65] at {}, but was {:?}", pos, &header[..2]),
+            ));
+        }
+        images.push(decode_entry(bytes, pos + ARRAY_HEADER_SIZE)?);
+
+        let next = u32::from_le_bytes([header[6], header[7], header[8], header[9]]) as usize;
+        match next {
+            0 => return Ok(images),
+            // Only moving forward guarantees that the walk ends
+            next if next <= pos => {
+                return Err(BmpError::new(
+                    BmpErrorKind::UnsupportedHeader,
+                    format!("The array entry at {} points back to {}", pos, next),
+                ))
+            }
+            next => pos = next,
+        }
+    }
+}
+
+/// Decodes the bitmap at `start`, whose pixel offset counts from the start of
+/// `bytes`, by moving its pixels right behind its headers. Core headers are
+/// rejected by `decode_headers`, so every palette entry takes 4 bytes.
+fn decode_entry(bytes: &[u8], start: usize) -> BmpResult<Image> {
+    let block = &bytes[start..];
+    let mut cursor = Cursor::new(block);
+    let (header, dh, palette) = decoder::decode_headers(&mut cursor, Limits::default())?;
+
+    let masks = match (dh.compress_type, dh.header_size) {
+        (3, 40) => 12,
+        _ => 0,
+    };
+    let colors = palette.map_or(0, |p| p.len());
+    let headers_len =
+        wire::FILE_HEADER_SIZE as usize + dh.header_size as usize + masks + colors * 4;
+    let offset = header.pixel_offset as usize;
+    if headers_len > block.len() || offset > bytes.len() {
+        return Err(BmpError::new(
+            BmpErrorKind::UnsupportedHeader,
+            format!("The bitmap at {} lies outside of the file", start),
+        ));
+    }
+    let size = match (dh.compress_type, dh.data_size) {
+        // Compressed data without a size runs to the end
+        (1 | 2, 0) => bytes.len() - offset,
+        (1 | 2, size) => size as usize,
+        _ => {
+            decoder::row_size(dh.width.unsigned_abs(), dh.bits_per_pixel)
+                * dh.height.unsigned_abs() as usize
+        }
+    };
+    let end = offset.saturating_add(size).min(bytes.len());
+
+    let mut bmp_data = block[..headers_len].to_vec();
+    bmp_data.extend_from_slice(&bytes[offset..end]);
+    let file_size = bmp_data.len() as u32;
+    bmp_data[2..6].copy_from_slice(&file_size.to_le_bytes());
+    bmp_data[10..14].copy_from_slice(&(headers_len as u32).to_le_bytes());
+    crate::from_bytes(&bmp_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wraps the bitmaps in an array, moving their pixels to the end of the
+    /// file as OS/2 tools do.
+    fn array_of(paths: &[&str]) -> Vec<u8> {
+        let files: Vec<Vec<u8>> = paths.iter().map(|p| fs::read(p).unwrap()).collect();
+        let headers_len = |f: &[u8]| u32::from_le_bytes([f[10], f[11], f[12], f[13]]) as usize;
+        let entries_len: usize = files
+            .iter()
+            .map(|f| ARRAY_HEADER_SIZE + headers_len(f))
+            .sum();
+
+        let mut array = Vec::new();
+        let mut pixels = entries_len;
+        for (i, f) in files.iter().enumerate() {
+            let next = match i + 1 == files.len() {
+                true => 0,
+                false => array.len() + ARRAY_HEADER_SIZE + headers_len(f),
+            };
+            array.extend_from_slice(b"BA");
+            // The size of both file headers and a version 3 header
+            array.extend_from_slice(&(ARRAY_HEADER_SIZE as u32 + 14 + 40).to_le_bytes());
+            array.extend_from_slice(&(next as u32).to_le_bytes());
+            array.extend_from_slice(&[0; 4]);
+            let mut headers = f[..headers_len(f)].to_vec();
+            headers[10..14].copy_from_slice(&(pixels as u32).to_le_bytes());
+            array.extend_from_slice(&headers);
+            pixels += f.len() - headers_len(f);
+        }
+        for f in &files {
+            array.extend_from_slice(&f[headers_len(f)..]);
+        }
+        array
+    }
+
+    #[test]
+    fn every_entry_is_decoded() {
+        let paths = [
+            "test/bmpsuite-2.5/g/pal8.bmp",
+            "test/bmpsuite-2.5/g/rgb24.bmp",
+            "test/bmpsuite-2.5/g/pal4rle.bmp",
+        ];
+        let images = from_bytes_array(&array_of(&paths)).unwrap();
+        assert_eq!(images.len(), 3);
+        for (img, path) in images.iter().zip(paths.iter()) {
+            assert_eq!(img.data, crate::open(path).unwrap().data);
+        }
+        assert_eq!(open_array(paths[1]).unwrap().len(), 1);
+
+        // The second entry points to itself
+        let mut looping = array_of(&paths[..2]);
+        let second = u32::from_le_bytes([looping[6], looping[7], looping[8], looping[9]]) as usize;
+        looping[second + 6..second + 10].copy_from_slice(&(second as u32).to_le_bytes());
+        match from_bytes_array(&looping) {
+            Err(BmpError {
+                kind: BmpErrorKind::UnsupportedHeader,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Arrays going in circles should not be decoded"),
+        }
+    }
+
+    #[test]
+    fn error_on_core_header_entry() {
+        let array = array_of(&["test/bmpsuite-2.5/g/pal8os2.bmp"]);
+        match from_bytes_array(&array) {
+            Err(BmpError {
+                kind: BmpErrorKind::UnsupportedBmpVersion,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Entries with a core header are not supported"),
+        }
+    }
+}
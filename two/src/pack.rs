@@ -0,0 +1,329 @@
+//! Pixel packing for raw output layouts.
+//!
+//! Each layout is a marker type implementing `PackPixel`, which lets
+//! `Image::to_bytes` produce any of them and `Image::from_bytes` read them back:
+//!
+//! ```
+//! use two::pack::{Rgb565, RowOrder};
+//! use two::{consts, Image};
+//!
+//! let mut img = Image::new(1, 1);
+//! img.set_pixel(0, 0, consts::RED);
+//! assert_eq!(img.to_bytes::<Rgb565>(RowOrder::TopDown), vec![0x00, 0xF8]);
+//!
+//! let back = Image::from_bytes::<Rgb565>(1, 1, &[0x00, 0xF8], RowOrder::TopDown).unwrap();
+//! assert_eq!(back.get_pixel(0, 0), consts::RED);
+//! ```
+
+use std::fmt;
+
+use crate::Pixel;
+
+/// The order in which the rows of raw pixel data are stored.
+///
+/// Most sources are top-down, while BMP files and Windows DIB sections are
+/// usually bottom-up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowOrder {
+    /// The first row is the top of the image.
+    TopDown,
+    /// The first row is the bottom of the image.
+    BottomUp,
+}
+
+/// The interleaving of pixels decoded straight into bytes, as graphics APIs
+/// expect them for upload. Unlike the `PackPixel` layouts, alpha is kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Red, green, blue and alpha bytes.
+    #[default]
+    Rgba8,
+    /// Blue, green, red and alpha bytes.
+    Bgra8,
+    /// Red, green and blue bytes.
+    Rgb8,
+}
+
+impl Layout {
+    /// Returns the number of bytes a single pixel occupies.
+    #[inline]
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Layout::Rgba8 | Layout::Bgra8 => 4,
+            Layout::Rgb8 => 3,
+        }
+    }
+
+    /// Writes `px` with `alpha` into `out`, which is exactly `bytes_per_pixel` long.
+    #[inline]
+    pub(crate) fn pack(self, px: Pixel, alpha: u8, out: &mut [u8]) {
+        match self {
+            Layout::Rgba8 => out.copy_from_slice(&[px.r, px.g, px.b, alpha]),
+            Layout::Bgra8 => out.copy_from_slice(&[px.b, px.g, px.r, alpha]),
+            Layout::Rgb8 => out.copy_from_slice(&[px.r, px.g, px.b]),
+        }
+    }
+}
+
+/// A buffer, or a row of one, too small for the pixels meant to go into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeMismatch {
+    /// The number of bytes the pixels need.
+    pub needed: usize,
+    /// The number of bytes there are.
+    pub available: usize,
+}
+
+impl fmt::Display for SizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Size mismatch:{} bytes are needed, but only {} are available",
+            self.needed, self.available
+        )
+    }
+}
+
+/// A raw pixel layout.
+pub trait PackPixel {
+    /// The number of bytes a single packed pixel occupies.
+    const BYTES: usize;
+
+    /// Writes `px` into `out`, which is exactly `BYTES` long.
+    fn pack(px: Pixel, out: &mut [u8]);
+
+    /// Reads the pixel packed in `bytes`, which is exactly `BYTES` long. Any
+    /// alpha is dropped.
+    fn unpack(bytes: &[u8]) -> Pixel;
+}
+
+/// 24 bits per pixel, red first.
+#[derive(Clone, Copy, Debug)]
+pub struct Rgb888;
+
+/// 24 bits per pixel, blue first, as stored in BMP files.
+#[derive(Clone, Copy, Debug)]
+pub struct Bgr888;
+
+/// 32 bits per pixel, red first, with an opaque alpha last.
+#[derive(Clone, Copy, Debug)]
+pub struct Rgba8888;
+
+/// 32 bits per pixel, with an opaque alpha first.
+#[derive(Clone, Copy, Debug)]
+pub struct Argb8888;
+
+/// 16 bits per pixel, 5 bits red, 6 bits green and 5 bits blue, little-endian.
+#[derive(Clone, Copy, Debug)]
+pub struct Rgb565;
+
+impl PackPixel for Rgb888 {
+    const BYTES: usize = 3;
+
+    #[inline]
+    fn pack(px: Pixel, out: &mut [u8]) {
+        out.copy_from_slice(&[px.r, px.g, px.b]);
+    }
+
+    #[inline]
+    fn unpack(bytes: &[u8]) -> Pixel {
+        Pixel::new(bytes[0], bytes[1], bytes[2])
+    }
+}
+
+impl PackPixel for Bgr888 {
+    const BYTES: usize = 3;
+
+    #[inline]
+    fn pack(px: Pixel, out: &mut [u8]) {
+        out.copy_from_slice(&[px.b, px.g, px.r]);
+    }
+
+    #[inline]
+    fn unpack(bytes: &[u8]) -> Pixel {
+        Pixel::new(bytes[2], bytes[1], bytes[0])
+    }
+}
+
+impl PackPixel for Rgba8888 {
+    const BYTES: usize = 4;
+
+    #[inline]
+    fn pack(px: Pixel, out: &mut [u8]) {
+        out.copy_from_slice(&[px.r, px.g, px.b, 0xFF]);
+    }
+
+    #[inline]
+    fn unpack(bytes: &[u8]) -> Pixel {
+        Pixel::new(bytes[0], bytes[1], bytes[2])
+    }
+}
+
+impl PackPixel for Argb8888 {
+    const BYTES: usize = 4;
+
+    #[inline]
+    fn pack(px: Pixel, out: &mut [u8]) {
+        out.copy_from_slice(&[0xFF, px.r, px.g, px.b]);
+    }
+
+    #[inline]
+    fn unpack(bytes: &[u8]) -> Pixel {
+        Pixel::new(bytes[1], bytes[2], bytes[3])
+    }
+}
+
+impl PackPixel for Rgb565 {
+    const BYTES: usize = 2;
+
+    #[inline]
+    fn pack(px: Pixel, out: &mut [u8]) {
+        let val = (px.r as u16 >> 3) << 11 | (px.g as u16 >> 2) << 5 | px.b as u16 >> 3;
+        out.copy_from_slice(&val.to_le_bytes());
+    }
+
+    #[inline]
+    fn unpack(bytes: &[u8]) -> Pixel {
+        let val = u16::from_le_bytes([bytes[0], bytes[1]]);
+        // The high bits are repeated in the low ones, so white stays white
+        let (r, g, b) = (
+            (val >> 11) as u8,
+            (val >> 5 & 0x3F) as u8,
+            (val & 0x1F) as u8,
+        );
+        Pixel::new(r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts, Image};
+
+    fn packed<P: PackPixel>(px: Pixel) -> Vec<u8> {
+        let mut out = vec![0; P::BYTES];
+        P::pack(px, &mut out);
+        out
+    }
+
+    #[test]
+    fn pack_single_pixels() {
+        let px = px!(0x12, 0x34, 0x56);
+        assert_eq!(packed::<Rgb888>(px), vec![0x12, 0x34, 0x56]);
+        assert_eq!(packed::<Bgr888>(px), vec![0x56, 0x34, 0x12]);
+        assert_eq!(packed::<Rgba8888>(px), vec![0x12, 0x34, 0x56, 0xFF]);
+        assert_eq!(packed::<Argb8888>(px), vec![0xFF, 0x12, 0x34, 0x56]);
+        assert_eq!(packed::<Rgb565>(consts::WHITE), vec![0xFF, 0xFF]);
+        assert_eq!(packed::<Rgb565>(consts::LIME), vec![0xE0, 0x07]);
+        assert_eq!(packed::<Rgb565>(consts::BLUE), vec![0x1F, 0x00]);
+    }
+
+    #[test]
+    fn to_bytes_is_unpadded_in_row_order() {
+        let img = crate::open("test/rgbw.bmp").unwrap();
+        assert_eq!(
+            img.to_bytes::<Rgb888>(RowOrder::TopDown),
+            vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]
+        );
+        assert_eq!(
+            img.to_bytes::<Rgb888>(RowOrder::BottomUp),
+            vec![0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 255, 0]
+        );
+        let len = Image::new(3, 5)
+            .to_bytes::<Argb8888>(RowOrder::TopDown)
+            .len();
+        assert_eq!(len, 60);
+    }
+
+    #[test]
+    fn from_bytes_reads_what_to_bytes_wrote() {
+        let img = crate::open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        let (w, h) = (img.get_width(), img.get_height());
+        for &order in &[RowOrder::TopDown, RowOrder::BottomUp] {
+            let bytes = img.to_bytes::<Bgr888>(order);
+            assert_eq!(
+                Image::from_bytes::<Bgr888>(w, h, &bytes, order)
+                    .unwrap()
+                    .data,
+                img.data
+            );
+            let bytes = img.to_bytes::<Argb8888>(order);
+            assert_eq!(
+                Image::from_bytes::<Argb8888>(w, h, &bytes, order)
+                    .unwrap()
+                    .data,
+                img.data
+            );
+        }
+
+        let bytes = [0xFF, 0xFF, 0xE0, 0x07, 0x1F, 0x00, 0x00, 0x00];
+        let img = Image::from_bytes::<Rgb565>(2, 2, &bytes, RowOrder::BottomUp).unwrap();
+        assert_eq!(img.get_pixel(0, 1), consts::WHITE);
+        assert_eq!(img.get_pixel(1, 1), consts::LIME);
+        assert_eq!(img.get_pixel(0, 0), consts::BLUE);
+
+        match Image::from_bytes::<Rgb565>(2, 2, &bytes[..7], RowOrder::TopDown) {
+            Err(crate::BmpError {
+                kind: crate::BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Seven bytes do not hold four pixels"),
+        }
+    }
+
+    #[test]
+    fn decoded_layouts_match_the_image() {
+        for path in &[
+            "test/bmpsuite-2.5/g/pal8.bmp",
+            "test/bmpsuite-2.5/g/pal8topdown.bmp",
+            "test/bmpsuite-2.5/q/rgba32.bmp",
+        ] {
+            let bmp_data = std::fs::read(path).unwrap();
+            let img = crate::open(path).unwrap();
+            let options = crate::DecodeOptions::default();
+            let (rgba, _) = crate::from_bytes_packed(&bmp_data, options).unwrap();
+            let options = options.output_layout(Layout::Rgb8);
+            let (rgb, info) = crate::from_bytes_packed(&bmp_data, options).unwrap();
+            assert_eq!(
+                (info.width, info.height),
+                (img.get_width(), img.get_height())
+            );
+            assert_eq!(rgb, img.to_bytes::<Rgb888>(RowOrder::TopDown), "{}", path);
+
+            let alpha = img.alpha().map(|a| a.to_vec());
+            let alpha = alpha.unwrap_or_else(|| vec![255; img.data.len()]);
+            let expected: Vec<u8> = img
+                .data
+                .iter()
+                .zip(alpha)
+                .flat_map(|(px, a)| [px.r, px.g, px.b, a])
+                .collect();
+            assert_eq!(rgba, expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn copy_into_a_strided_framebuffer() {
+        let img = crate::open("test/rgbw.bmp").unwrap();
+        let mut buf = [7u8; 20];
+        img.copy_into_slice(&mut buf, Layout::Bgra8, RowOrder::BottomUp, 12)
+            .unwrap();
+        // Blue and white at the bottom come first, red and lime follow after the stride
+        assert_eq!(buf[..8], [255, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(buf[8..12], [7; 4]);
+        assert_eq!(buf[12..20], [0, 0, 255, 255, 0, 255, 0, 255]);
+
+        let short_row = img.copy_into_slice(&mut buf, Layout::Bgra8, RowOrder::TopDown, 7);
+        let mismatch = SizeMismatch {
+            needed: 8,
+            available: 7,
+        };
+        assert_eq!(short_row, Err(mismatch));
+        let short_buf = img.copy_into_slice(&mut buf[..19], Layout::Rgba8, RowOrder::TopDown, 12);
+        let mismatch = SizeMismatch {
+            needed: 20,
+            available: 19,
+        };
+        assert_eq!(short_buf, Err(mismatch));
+    }
+}
@@ -0,0 +1,187 @@
+//! Opt-in measurements of decoding and encoding.
+//!
+//! The functions here work like their counterparts at the crate root, but also
+//! return how much data went through and how long every stage took, without
+//! attaching a profiler. The plain functions measure nothing.
+//!
+//! ```no_run
+//! use two::perf::{self, Stage};
+//!
+//! let (img, stats) = perf::open("scan.bmp").unwrap();
+//! println!("{} bytes, {} rows", stats.bytes_read, stats.rows);
+//! println!("pixels took {:?}", stats.elapsed(Stage::Pixels));
+//! let stats = perf::save(&img, "copy.bmp").unwrap();
+//! println!("{} bytes in {:?}", stats.bytes_written, stats.total());
+//! ```
+
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::decoder::{self, BmpResult};
+use crate::{encoder, DecodeOptions, EncoderOptions, Image};
+
+/// A step of decoding or encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Reading the file into memory.
+    Read,
+    /// Parsing the headers and the palette.
+    Headers,
+    /// Decoding the pixel data.
+    Pixels,
+    /// Decoding the alpha channel.
+    Alpha,
+    /// Reading the color space and any color profile.
+    ColorProfile,
+    /// Encoding the image into memory.
+    Encode,
+    /// Writing the encoded file.
+    Write,
+}
+
+/// What a single decode or encode did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// The number of rows decoded or encoded.
+    pub rows: u32,
+    /// The number of palette colors, 0 for images without one.
+    pub palette_size: usize,
+    /// The time taken by every stage, in the order they ran.
+    pub stages: Vec<(Stage, Duration)>,
+}
+
+impl Stats {
+    /// Returns the time spent in `stage`, zero if it did not run.
+    pub fn elapsed(&self, stage: Stage) -> Duration {
+        self.stages
+            .iter()
+            .filter(|&&(s, _)| s == stage)
+            .map(|&(_, d)| d)
+            .sum()
+    }
+
+    /// Returns the time spent in all stages.
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|&(_, d)| d).sum()
+    }
+
+    fn describe(&mut self, img: &Image) {
+        self.rows = img.height;
+        self.palette_size = img.palette().map_or(0, |p| p.len());
+    }
+}
+
+/// Records stages into `Stats`, if there are any to record into.
+pub(crate) struct Timer<'a> {
+    stats: Option<&'a mut Stats>,
+    start: Instant,
+}
+
+impl<'a> Timer<'a> {
+    pub(crate) fn new(stats: Option<&'a mut Stats>) -> Timer<'a> {
+        Timer {
+            stats,
+            start: Instant::now(),
+        }
+    }
+
+    /// Ends `stage`, which started when the previous one ended.
+    pub(crate) fn lap(&mut self, stage: Stage) {
+        if let Some(ref mut stats) = self.stats {
+            let now = Instant::now();
+            stats.stages.push((stage, now - self.start));
+            self.start = now;
+        }
+    }
+}
+
+/// Like `two::open`, measuring the read and every decoding stage.
+pub fn open<P: AsRef<Path>>(path: P) -> BmpResult<(Image, Stats)> {
+    let start = Instant::now();
+    let mut bytes = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut bytes)?;
+    let read = start.elapsed();
+
+    let (img, mut stats) = from_bytes(&bytes)?;
+    stats.stages.insert(0, (Stage::Read, read));
+    Ok((img, stats))
+}
+
+/// Like `two::from_bytes`, measuring every decoding stage.
+pub fn from_bytes(bytes: &[u8]) -> BmpResult<(Image, Stats)> {
+    let mut stats = Stats {
+        bytes_read: bytes.len() as u64,
+        ..Stats::default()
+    };
+    let mut bmp_data = Cursor::new(bytes);
+    let img = decoder::decode_image(
+        &mut bmp_data,
+        DecodeOptions::default(),
+        &mut Vec::new(),
+        Some(&mut stats),
+        decoder::Hooks::default(),
+    )?;
+    stats.describe(&img);
+    Ok((img, stats))
+}
+
+/// Like `Image::save`, measuring the encoding and the write.
+pub fn save<P: AsRef<Path>>(img: &Image, path: P) -> io::Result<Stats> {
+    let (bmp_data, mut stats) = encode(img)?;
+    let mut timer = Timer::new(Some(&mut stats));
+    fs::File::create(path)?.write_all(&bmp_data)?;
+    timer.lap(Stage::Write);
+    Ok(stats)
+}
+
+/// Encodes `img` as `Image::to_writer` does, measuring the encoding.
+pub fn encode(img: &Image) -> io::Result<(Vec<u8>, Stats)> {
+    let mut stats = Stats::default();
+    let mut timer = Timer::new(Some(&mut stats));
+    let bmp_data = encoder::encode_image(img, &EncoderOptions::default())?;
+    timer.lap(Stage::Encode);
+
+    stats.bytes_written = bmp_data.len() as u64;
+    stats.describe(img);
+    Ok((bmp_data, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoding_stages_are_recorded_in_order() {
+        let bytes = fs::read("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        let (img, stats) = from_bytes(&bytes).unwrap();
+        assert_eq!(img, crate::open("test/bmpsuite-2.5/g/pal8.bmp").unwrap());
+        assert_eq!(stats.bytes_read, bytes.len() as u64);
+        assert_eq!((stats.rows, stats.palette_size), (64, 252));
+        let stages: Vec<Stage> = stats.stages.iter().map(|&(s, _)| s).collect();
+        assert_eq!(
+            stages,
+            [
+                Stage::Headers,
+                Stage::Pixels,
+                Stage::Alpha,
+                Stage::ColorProfile
+            ]
+        );
+        assert_eq!(stats.elapsed(Stage::Encode), Duration::ZERO);
+        assert!(stats.total() >= stats.elapsed(Stage::Pixels));
+    }
+
+    #[test]
+    fn encoding_counts_the_bytes_written() {
+        let img = crate::open("test/rgbw.bmp").unwrap();
+        let (bmp_data, stats) = encode(&img).unwrap();
+        assert_eq!(stats.bytes_written, bmp_data.len() as u64);
+        assert_eq!((stats.rows, stats.palette_size), (2, 0));
+        assert_eq!(stats.stages.len(), 1);
+        assert_eq!(stats.stages[0].0, Stage::Encode);
+    }
+}
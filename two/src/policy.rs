@@ -0,0 +1,99 @@
+//! How float colors are turned back into 8-bit channels.
+//!
+//! Resampling, blending and color conversions compute in floats. They all end
+//! up in `ColorPolicy::to_u8`, so picking a policy makes the results match
+//! another implementation bit for bit, e.g. one that truncates instead of
+//! rounding. The default rounds to the nearest value and clamps.
+//!
+//! ```
+//! use two::policy::{ColorPolicy, OutOfRange, Rounding};
+//! use two::Pixel;
+//!
+//! let truncate = ColorPolicy {
+//!     rounding: Rounding::Down,
+//!     ..ColorPolicy::default()
+//! };
+//! assert_eq!(Pixel::from_f32(0.5, 1.5, 0.0), Pixel::new(128, 255, 0));
+//! assert_eq!(Pixel::from_f32_with(0.5, 1.5, 0.0, truncate), Pixel::new(127, 255, 0));
+//!
+//! let wrap = ColorPolicy {
+//!     out_of_range: OutOfRange::Wrap,
+//!     ..ColorPolicy::default()
+//! };
+//! assert_eq!(wrap.to_u8(257.0), 1);
+//! ```
+
+/// How values between two integers are resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rounding {
+    /// To the nearest integer, halves away from zero.
+    #[default]
+    Nearest,
+    /// Towards negative infinity, as a plain cast of positive values does.
+    Down,
+}
+
+/// What happens to values below 0 or above 255.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutOfRange {
+    /// Saturates them at 0 or 255.
+    #[default]
+    Clamp,
+    /// Keeps their lowest 8 bits, as integer arithmetic modulo 256 does.
+    Wrap,
+}
+
+/// The conversion of float channels to `u8`. NaN always becomes 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ColorPolicy {
+    pub rounding: Rounding,
+    pub out_of_range: OutOfRange,
+}
+
+impl ColorPolicy {
+    /// Converts `val`, a channel from 0.0 to 255.0.
+    #[inline]
+    pub fn to_u8(self, val: f32) -> u8 {
+        if val.is_nan() {
+            return 0;
+        }
+        let val = match self.rounding {
+            Rounding::Nearest => val.round(),
+            Rounding::Down => val.floor(),
+        };
+        match self.out_of_range {
+            OutOfRange::Clamp => val.clamp(0.0, 255.0) as u8,
+            OutOfRange::Wrap => val.rem_euclid(256.0) as u8,
+        }
+    }
+
+    /// Converts `val`, a channel from 0.0 to 1.0.
+    #[inline]
+    pub fn unit_to_u8(self, val: f32) -> u8 {
+        self.to_u8(val * 255.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding_and_range() {
+        let policy = ColorPolicy::default();
+        assert_eq!(policy.to_u8(127.5), 128);
+        assert_eq!(policy.to_u8(-3.0), 0);
+        assert_eq!(policy.to_u8(300.0), 255);
+        assert_eq!(policy.to_u8(f32::NAN), 0);
+
+        let policy = ColorPolicy {
+            rounding: Rounding::Down,
+            out_of_range: OutOfRange::Wrap,
+        };
+        assert_eq!(policy.to_u8(127.9), 127);
+        assert_eq!(policy.to_u8(-1.0), 255);
+        assert_eq!(policy.to_u8(511.5), 255);
+        assert_eq!(policy.to_u8(f32::INFINITY), 0);
+        assert_eq!(policy.unit_to_u8(0.5), 127);
+    }
+}
@@ -0,0 +1,184 @@
+//! Editing files without changing how they are stored.
+//!
+//! `save` writes every image as an uncompressed 24 bpp file. An image opened
+//! with `Image::open_preserving` keeps the headers of its file instead, and
+//! `save_preserving` writes it back with the same header version, bit depth,
+//! palette, compression and row order, updating only the sizes.
+//!
+//! ```no_run
+//! use two::{consts, Image};
+//!
+//! let mut img = Image::open_preserving("logo-rle8.bmp").unwrap();
+//! img.set_pixel(0, 0, consts::BLACK);
+//! // Still an 8 bpp RLE file, as long as black is in the palette
+//! img.save_preserving("logo-rle8.bmp").unwrap();
+//! ```
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+
+use crate::decoder::{self, BmpError, BmpErrorKind, BmpResult};
+use crate::{encoder, wire, DecodeOptions, EncoderOptions, Image};
+
+impl Image {
+    /// Opens the file at `path`, keeping everything before its pixel data for
+    /// `save_preserving`.
+    pub fn open_preserving<P: AsRef<Path>>(path: P) -> BmpResult<Image> {
+        Image::from_bytes_preserving(&fs::read(path)?)
+    }
+
+    /// Like `open_preserving`, decoding the file in `bytes`.
+    pub fn from_bytes_preserving(bytes: &[u8]) -> BmpResult<Image> {
+        let options = DecodeOptions::default().keep_indices(true);
+        let hooks = decoder::Hooks::default();
+        let mut img = decoder::decode_image(
+            &mut Cursor::new(bytes),
+            options,
+            &mut Vec::new(),
+            None,
+            hooks,
+        )?;
+        // Images without pixels decode whatever the offset says
+        let start = img.header.pixel_offset as usize;
+        let headers_end = wire::FILE_HEADER_SIZE as usize + img.dib_header.header_size as usize;
+        if start > bytes.len() || start < headers_end {
+            return Err(BmpError::new(
+                BmpErrorKind::InconsistentHeader,
+                format!(
+                    "The pixel data cannot start at {} in a file of {} bytes",
+                    start,
+                    bytes.len()
+                ),
+            ));
+        }
+        img.source_headers = Some(bytes[..start].to_vec());
+        Ok(img)
+    }
+
+    /// Saves the image in the layout of the file it was opened from with
+    /// `open_preserving`. Other images are saved as `save` would.
+    ///
+    /// Indexed pixels must keep colors of the palette. Fails with
+    /// `InvalidInput` if one does not, if the image changed its size, or if
+    /// the file was stored as 16 bpp, with bitfields or another layout that
+    /// cannot be written.
+    pub fn save_preserving<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.encode_preserving()?)
+    }
+
+    /// Like `save_preserving`, but writes to `destination`.
+    pub fn to_writer_preserving<W: Write>(&self, destination: &mut W) -> io::Result<()> {
+        destination.write_all(&self.encode_preserving()?)
+    }
+
+    fn encode_preserving(&self) -> io::Result<Vec<u8>> {
+        match self.source_headers {
+            Some(ref headers) => encoder::encode_preserving(self, headers),
+            None => encoder::encode_image(self, &EncoderOptions::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts, Pixel};
+
+    fn resave(img: &Image) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        img.to_writer_preserving(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn unchanged_files_are_written_back_as_read() {
+        for name in [
+            "g/pal1.bmp",
+            "g/pal4.bmp",
+            "g/pal8topdown.bmp",
+            "g/pal8v5.bmp",
+            "g/rgb24.bmp",
+            "g/rgb32.bmp",
+            "q/pal2.bmp",
+            "q/rgb24prof.bmp",
+        ] {
+            let path = format!("test/bmpsuite-2.5/{}", name);
+            let img = Image::open_preserving(&path).unwrap();
+            assert_eq!(resave(&img), fs::read(&path).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn edited_files_keep_their_layout() {
+        for name in ["pal4rle", "pal8rle", "pal8", "rgb24"] {
+            let path = format!("test/bmpsuite-2.5/g/{}.bmp", name);
+            let mut img = Image::open_preserving(&path).unwrap();
+            let color = img.palette().map_or(consts::RED, |p| p[1]);
+            img.set_pixel(3, 2, color);
+
+            let bytes = resave(&img);
+            let saved = crate::from_bytes(&bytes).unwrap();
+            let (meta, saved_meta) = (img.metadata(), saved.metadata());
+            assert_eq!(saved_meta.bits_per_pixel, meta.bits_per_pixel, "{}", name);
+            assert_eq!(saved_meta.compression, meta.compression, "{}", name);
+            assert_eq!(saved.palette(), img.palette(), "{}", name);
+            assert_eq!(saved.data, img.data, "{}", name);
+        }
+
+        // Flat areas compress well
+        let rle8 = "test/bmpsuite-2.5/g/pal8rle.bmp";
+        let mut img = Image::open_preserving(rle8).unwrap();
+        let color = img.palette().unwrap()[0];
+        img.fill_rect((0, 0, 127, 64), color);
+        assert!(resave(&img).len() < fs::metadata(rle8).unwrap().len() as usize / 2);
+    }
+
+    #[test]
+    fn layouts_that_cannot_be_kept_are_errors() {
+        let mut img = Image::open_preserving("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        img.set_pixel(0, 0, Pixel::new(1, 2, 3));
+        let err = img.to_writer_preserving(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let img = Image::open_preserving("test/bmpsuite-2.5/g/rgb16.bmp").unwrap();
+        let err = img.to_writer_preserving(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // Only the fields the stored headers have are updated
+        let img = Image::open_preserving("test/bmpsuite-2.5/q/rgb24prof.bmp").unwrap();
+        let headers = img.source_headers.as_ref().unwrap();
+        let mut v3 = headers[..54].to_vec();
+        v3[14..18].copy_from_slice(&40u32.to_le_bytes());
+        for headers in [&v3[..], &headers[..30], &headers[..10]] {
+            let err = encoder::encode_preserving(&img, headers).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        let img = crate::open("test/rgbw.bmp").unwrap();
+        let mut core = fs::read("test/rgbw.bmp").unwrap()[..26].to_vec();
+        core[14..18].copy_from_slice(&12u32.to_le_bytes());
+        assert!(encoder::encode_preserving(&img, &core).is_ok());
+
+        // Offsets outside of the file cannot keep the headers
+        let mut file = fs::read("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        file.truncate(54);
+        file[10..14].copy_from_slice(&100000u32.to_le_bytes());
+        file[18..22].copy_from_slice(&0i32.to_le_bytes());
+        match Image::from_bytes_preserving(&file) {
+            Err(BmpError {
+                kind: BmpErrorKind::InconsistentHeader,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("The pixel offset is past the end of the file"),
+        }
+        for name in ["32bpp-0x0", "32bpp-0x240", "32bpp-320x0"] {
+            let path = format!("test/bmptestsuite-0.9/questionable/{}.bmp", name);
+            let _ = Image::open_preserving(&path);
+        }
+
+        // Without kept headers, images are saved as 24 bpp
+        let img = crate::open("test/bmpsuite-2.5/g/pal8.bmp").unwrap();
+        let saved = crate::from_bytes(&resave(&img)).unwrap();
+        assert_eq!(saved.metadata().bits_per_pixel, 24);
+    }
+}
@@ -0,0 +1,184 @@
+//! The pixel array of a file exactly as it is stored.
+//!
+//! Forensic and analysis tools sometimes need the bytes on disk rather than
+//! decoded colors: palette indexes, padding and all. `read_pixel_bytes` skips
+//! the palette and everything else between the headers and the pixels, and
+//! reads no more than the pixel array.
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! let (info, bytes) = two::raw::read_pixel_bytes(File::open("scan.bmp").unwrap()).unwrap();
+//! if let Some(stride) = info.stride {
+//!     // The padding at the end of the first row in the file
+//!     println!("{:?}", &bytes[info.width as usize * 3..stride]);
+//! }
+//! ```
+
+use std::io::{self, Read};
+
+use crate::decoder::{self, BmpError, BmpErrorKind, BmpResult};
+use crate::{wire, Limits, RowOrder};
+
+/// How the bytes of a pixel array are laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u16,
+    /// The compression type as stored, 0 for none.
+    pub compression: u32,
+    /// The number of bytes from the start of a row to the next, including the
+    /// padding. `None` for run-length encoded data, whose rows vary in length.
+    pub stride: Option<usize>,
+    pub row_order: RowOrder,
+    /// Where the pixel array starts in the file.
+    pub pixel_offset: u32,
+}
+
+/// Reads the headers, skips to the pixel array and returns it untouched.
+///
+/// Uncompressed arrays are `stride * height` bytes long. For compressed ones,
+/// the size in the header is read, or the rest of the file if it is zero.
+pub fn read_pixel_bytes<R: Read>(mut reader: R) -> BmpResult<(RawInfo, Vec<u8>)> {
+    let (magic, header) = wire::read_file_header(&mut reader)?;
+    if magic != wire::MAGIC {
+        return Err(BmpError::new(
+            BmpErrorKind::WrongMagicNumbers,
+            format!("Expected {:?}, but was {:?}", wire::MAGIC, magic),
+        ));
+    }
+    let dib_header = wire::read_dib_header(&mut reader)?;
+    // Older headers are smaller and lay out their fields differently
+    if dib_header.header_size < wire::DIB_HEADER_SIZE {
+        return Err(BmpError::new(
+            BmpErrorKind::UnsupportedHeader,
+            format!(
+                "Headers of {} bytes are not supported",
+                dib_header.header_size
+            ),
+        ));
+    }
+    decoder::check_dib_header(&dib_header)?;
+    decoder::check_limits(&dib_header, Limits::default())?;
+
+    let headers_size = wire::FILE_HEADER_SIZE + wire::DIB_HEADER_SIZE as u64;
+    let gap = (header.pixel_offset as u64)
+        .checked_sub(headers_size)
+        .ok_or_else(|| {
+            BmpError::new(
+                BmpErrorKind::UnsupportedHeader,
+                format!("The pixel data cannot start at {}", header.pixel_offset),
+            )
+        })?;
+    io::copy(&mut (&mut reader).take(gap), &mut io::sink())?;
+
+    let width = dib_header.width.unsigned_abs();
+    let height = dib_header.height.unsigned_abs();
+    let stride = match dib_header.compress_type {
+        0 | 3 if !decoder::is_huffman(&dib_header) => {
+            Some(decoder::row_size(width, dib_header.bits_per_pixel))
+        }
+        _ => None,
+    };
+    // The claimed size is only read up to, never allocated up front
+    let len = match stride {
+        Some(stride) => Some(stride as u64 * height as u64),
+        None if dib_header.data_size > 0 => Some(dib_header.data_size as u64),
+        None => None,
+    };
+    let mut bytes = Vec::new();
+    match len {
+        Some(len) => {
+            (&mut reader).take(len).read_to_end(&mut bytes)?;
+            if (bytes.len() as u64) < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "The pixel array ends after {} of {} bytes",
+                        bytes.len(),
+                        len
+                    ),
+                )
+                .into());
+            }
+        }
+        None => {
+            reader.read_to_end(&mut bytes)?;
+        }
+    }
+
+    let info = RawInfo {
+        width,
+        height,
+        bits_per_pixel: dib_header.bits_per_pixel,
+        compression: dib_header.compress_type,
+        stride,
+        row_order: match dib_header.is_bottom_up() {
+            true => RowOrder::BottomUp,
+            false => RowOrder::TopDown,
+        },
+        pixel_offset: header.pixel_offset,
+    };
+    Ok((info, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn pixel_bytes_are_returned_as_stored() {
+        let file = fs::read("test/bmpsuite-2.5/g/pal4.bmp").unwrap();
+        let (info, bytes) = read_pixel_bytes(&file[..]).unwrap();
+        assert_eq!((info.width, info.height, info.bits_per_pixel), (127, 64, 4));
+        assert_eq!(info.stride, Some(64));
+        assert_eq!(info.row_order, RowOrder::BottomUp);
+        let start = info.pixel_offset as usize;
+        assert_eq!(bytes, file[start..start + 64 * 64]);
+
+        let file = fs::read("test/bmpsuite-2.5/g/pal8rle.bmp").unwrap();
+        let (info, bytes) = read_pixel_bytes(&file[..]).unwrap();
+        assert_eq!((info.compression, info.stride), (1, None));
+        assert_eq!(bytes, file[info.pixel_offset as usize..]);
+    }
+
+    #[test]
+    fn error_when_the_pixels_are_cut_short() {
+        let file = fs::read("test/bmptestsuite-0.9/corrupt/8bpp-pixeldata-cropped.bmp").unwrap();
+        match read_pixel_bytes(&file[..]) {
+            Err(BmpError {
+                kind: BmpErrorKind::BmpIoError(_),
+                ..
+            }) => (/* Expected */),
+            _ => panic!("A short pixel array should not be accepted"),
+        }
+    }
+
+    #[test]
+    fn corrupt_headers_are_errors() {
+        let file = fs::read("test/bmptestsuite-0.9/corrupt/bitdepth-large.bmp").unwrap();
+        match read_pixel_bytes(&file[..]) {
+            Err(BmpError {
+                kind: BmpErrorKind::UnsupportedBitsPerPixel,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("An unknown bit depth should not be accepted"),
+        }
+
+        // A header claiming a huge pixel array does not allocate it
+        let mut file = fs::read("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        file[18..22].copy_from_slice(&30000i32.to_le_bytes());
+        file[22..26].copy_from_slice(&30000i32.to_le_bytes());
+        assert!(read_pixel_bytes(&file[..]).is_err());
+
+        for entry in fs::read_dir("test/bmptestsuite-0.9/corrupt").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "bmp") {
+                // Only errors, never panics
+                let _ = read_pixel_bytes(&fs::read(&path).unwrap()[..]);
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Decoding a part of an image without decoding the rest.
+//!
+//! Uncompressed files store every row in the same number of bytes, so the rows
+//! and columns of a region can be read directly. Only the headers and the bytes
+//! of the region are read, which keeps tiles of very large scans cheap.
+//! Run-length encoded files are the exception, their rows cannot be found
+//! without decoding all rows before them.
+//!
+//! ```
+//! let tile = two::open_region("test/bmpsuite-2.5/g/rgb24.bmp", (8, 4, 16, 16)).unwrap();
+//! let img = two::open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+//! assert_eq!(tile.get_width(), 16);
+//! assert_eq!(tile.get_pixel(0, 0), img.get_pixel(8, 4));
+//! ```
+
+use std::fs;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::decoder::{self, BmpError, BmpErrorKind, BmpResult};
+use crate::geom::{Rect, Size};
+use crate::{wire, BmpDibHeader, Image, Limits};
+
+/// Decodes the part of the file at `path` within `rect`, clipped to the image.
+///
+/// The size of the whole image is not limited, but the region must lie within
+/// the default `Limits`. So must the whole image of compressed files, which is
+/// decoded to find the region.
+pub fn open_region<P: AsRef<Path>, R: Into<Rect>>(path: P, rect: R) -> BmpResult<Image> {
+    let mut f = BufReader::new(fs::File::open(path)?);
+    from_reader_region(&mut f, rect)
+}
+
+/// Like `open_region`, reading from `source`, which is expected to be at the
+/// start of the file.
+pub fn from_reader_region<S: Read + Seek, R: Into<Rect>>(
+    source: &mut S,
+    rect: R,
+) -> BmpResult<Image> {
+    let start = source.stream_position()?;
+    let mut prefix = vec![0; wire::FILE_HEADER_SIZE as usize];
+    source.read_exact(&mut prefix)?;
+    let pixel_offset = u32::from_le_bytes([prefix[10], prefix[11], prefix[12], prefix[13]]);
+    if (pixel_offset as u64) < wire::FILE_HEADER_SIZE + wire::DIB_HEADER_SIZE as u64 {
+        return Err(BmpError::new(
+            BmpErrorKind::UnsupportedHeader,
+            format!("The pixel data cannot start at {}", pixel_offset),
+        ));
+    }
+    let rest = pixel_offset as u64 - wire::FILE_HEADER_SIZE;
+    source.take(rest).read_to_end(&mut prefix)?;
+
+    let mut prefix = Cursor::new(&prefix[..]);
+    let (_, dh, palette) = decoder::decode_headers(&mut prefix, Limits::none())?;
+    let masks = decoder::read_bitmasks(&mut prefix, &dh)?;
+    let alpha_mask = decoder::alpha_mask(&mut prefix, &dh)?;
+
+    let (width, height) = (dh.width.unsigned_abs(), dh.height.unsigned_abs());
+    let rect = rect.into().clamp(Size::new(width, height));
+    let limits = Limits::default();
+    if rect.width > limits.max_width
+        || rect.height > limits.max_height
+        || rect.size().area() * 4 > limits.max_pixel_bytes
+    {
+        return Err(BmpError::new(
+            BmpErrorKind::LimitsExceeded,
+            format!(
+                "The {}x{} region exceeds the limits",
+                rect.width, rect.height
+            ),
+        ));
+    }
+
+    // Rows of compressed data can only be found by decoding them all
+    let compressed = matches!(dh.compress_type, 1 | 2) || decoder::is_huffman(&dh);
+    if let (Some(ref palette), true) = (&palette, compressed) {
+        decoder::check_limits(&dh, limits)?;
+        let mut data = Vec::new();
+        source.read_to_end(&mut data)?;
+        let mut full = Image::new(width, height);
+        full.data.clear();
+        let (w, h) = (width as usize, height as usize);
+        let push = |px| full.data.push(px);
+        if decoder::is_huffman(&dh) {
+            decoder::read_huffman(&data, palette, w, h, 0, push)?;
+        } else {
+            decoder::read_rle(&data, palette, w, h, dh.bits_per_pixel, 0, push)?;
+        }
+        if dh.is_bottom_up() {
+            crate::flip_rows(&mut full.data, width as usize);
+        }
+        let mut img = full.crop(rect);
+        copy_resolution(&dh, &mut img);
+        return Ok(img);
+    }
+
+    // The bytes covering the columns, of which the first `lead` pixels are
+    // left of the region when pixels are smaller than a byte
+    let bpp = dh.bits_per_pixel as u64;
+    let first_bit = rect.x as u64 * bpp;
+    let first_byte = first_bit / 8;
+    let lead = ((first_bit % 8) / bpp) as usize;
+    let span = (rect.right() as u64 * bpp).div_ceil(8) - first_byte;
+    let stride = decoder::row_size(width, dh.bits_per_pixel) as u64;
+    let row_dh = BmpDibHeader {
+        width: (lead as u32 + rect.width) as i32,
+        ..dh.clone()
+    };
+
+    let mut img = Image::new(rect.width, rect.height);
+    img.data.clear();
+    let mut alpha = Vec::new();
+    let mut row = vec![0; span as usize];
+    for y in rect.y..rect.bottom() {
+        let file_row = match dh.is_bottom_up() {
+            true => height - 1 - y,
+            false => y,
+        };
+        let pos = start + pixel_offset as u64 + file_row as u64 * stride + first_byte;
+        source.seek(SeekFrom::Start(pos))?;
+        source.read_exact(&mut row)?;
+
+        let mut x = 0;
+        decoder::decode_row(&row, &row_dh, palette.as_deref(), masks, |px| {
+            if x >= lead {
+                img.data.push(px);
+            }
+            x += 1;
+        })?;
+        if alpha_mask != 0 {
+            let mut x = 0;
+            decoder::decode_row_alpha(&row, &row_dh, alpha_mask, |a| {
+                if x >= lead {
+                    alpha.push(a);
+                }
+                x += 1;
+            });
+        }
+    }
+
+    // An alpha channel of zeros is meant to be opaque, as `open` decides for
+    // the whole image
+    if alpha.iter().any(|&a| a != 0) {
+        img.alpha = Some(alpha);
+    }
+    copy_resolution(&dh, &mut img);
+    Ok(img)
+}
+
+fn copy_resolution(dh: &BmpDibHeader, img: &mut Image) {
+    img.dib_header.hres = dh.hres;
+    img.dib_header.vres = dh.vres;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_match_crops_of_the_whole_image() {
+        let rects = [
+            (0, 0, 1, 1),
+            (3, 5, 17, 9),
+            (120, 60, 20, 20),
+            (1, 63, 126, 1),
+        ];
+        for name in [
+            "g/pal1.bmp",
+            "g/pal4.bmp",
+            "g/pal8topdown.bmp",
+            "g/pal8rle.bmp",
+            "g/rgb16-565.bmp",
+            "g/rgb24.bmp",
+            "q/pal1huff.bmp",
+            "q/pal2.bmp",
+        ] {
+            let path = format!("test/bmpsuite-2.5/{}", name);
+            let img = crate::open(&path).unwrap();
+            for &rect in &rects {
+                let region = open_region(&path, rect).unwrap();
+                assert_eq!(region.data, img.crop(rect).data, "{} {:?}", name, rect);
+            }
+        }
+
+        let path = "test/bmpsuite-2.5/q/rgba32.bmp";
+        let img = crate::open(path).unwrap();
+        let region = open_region(path, (100, 10, 27, 40)).unwrap();
+        assert_eq!(region.data, img.crop((100, 10, 27, 40)).data);
+        let alpha = region.alpha().unwrap();
+        assert_eq!(alpha[0], img.alpha().unwrap()[10 * 127 + 100]);
+        assert_eq!(open_region(path, (200, 0, 5, 5)).unwrap().size().area(), 0);
+    }
+
+    #[test]
+    fn compressed_files_are_decoded_whole() {
+        // Huffman encoded rows may be stored from the top
+        let mut file = fs::read("test/bmpsuite-2.5/q/pal1huff.bmp").unwrap();
+        let height = i32::from_le_bytes([file[22], file[23], file[24], file[25]]);
+        file[22..26].copy_from_slice(&(-height).to_le_bytes());
+        let img = crate::from_bytes(&file).unwrap();
+        let region = from_reader_region(&mut Cursor::new(&file), (3, 5, 17, 9)).unwrap();
+        assert_eq!(region.data, img.crop((3, 5, 17, 9)).data);
+
+        // Whole images beyond the limits are not decoded for a small region
+        let mut file = fs::read("test/bmpsuite-2.5/b/badrle.bmp").unwrap();
+        file[18..22].copy_from_slice(&0x7FFF_FFFFi32.to_le_bytes());
+        file[22..26].copy_from_slice(&0x7FFF_FFFFi32.to_le_bytes());
+        match from_reader_region(&mut Cursor::new(&file), (0, 0, 1, 1)) {
+            Err(BmpError {
+                kind: BmpErrorKind::LimitsExceeded,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("The whole image exceeds the limits"),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Reading pixels at fractional coordinates, as needed by warps and texture lookups.
+//!
+//! Pixel centers lie on integer coordinates, so `(0.0, 0.0)` is the center of the
+//! top-left pixel. Coordinates outside of the image are clamped to its edges.
+
+use crate::policy::ColorPolicy;
+use crate::{Image, Pixel};
+
+/// How colors between pixel centers are reconstructed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Takes the closest pixel, keeping hard edges and exact colors.
+    Nearest,
+    /// Interpolates linearly between the four closest pixels.
+    Bilinear,
+}
+
+impl Image {
+    /// Returns the color at `(x, y)` reconstructed with `filter`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    #[inline]
+    pub fn sample(&self, x: f32, y: f32, filter: Filter) -> Pixel {
+        self.sample_with(x, y, filter, ColorPolicy::default())
+    }
+
+    /// Like `sample`, converting interpolated colors with `policy`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    #[inline]
+    pub fn sample_with(&self, x: f32, y: f32, filter: Filter, policy: ColorPolicy) -> Pixel {
+        match filter {
+            Filter::Nearest => self.sample_nearest(x, y),
+            Filter::Bilinear => self.bilinear(x, y, policy),
+        }
+    }
+
+    /// Returns the pixel whose center is closest to `(x, y)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    pub fn sample_nearest(&self, x: f32, y: f32) -> Pixel {
+        let x = clamp(x.round(), self.width);
+        let y = clamp(y.round(), self.height);
+        self.get_pixel(x as u32, y as u32)
+    }
+
+    /// Returns the color at `(x, y)`, interpolated linearly between the four
+    /// surrounding pixel centers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    pub fn sample_bilinear(&self, x: f32, y: f32) -> Pixel {
+        self.bilinear(x, y, ColorPolicy::default())
+    }
+
+    fn bilinear(&self, x: f32, y: f32, policy: ColorPolicy) -> Pixel {
+        let x = clamp(x, self.width);
+        let y = clamp(y, self.height);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as u32, y0 as u32);
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let (a, b) = (self.get_pixel(x0, y0), self.get_pixel(x1, y0));
+        let (c, d) = (self.get_pixel(x0, y1), self.get_pixel(x1, y1));
+        let mix = |a: u8, b: u8, c: u8, d: u8| {
+            let top = a as f32 + (b as f32 - a as f32) * fx;
+            let bottom = c as f32 + (d as f32 - c as f32) * fx;
+            policy.to_u8(top + (bottom - top) * fy)
+        };
+        Pixel::new(
+            mix(a.r, b.r, c.r, d.r),
+            mix(a.g, b.g, c.g, d.g),
+            mix(a.b, b.b, c.b, d.b),
+        )
+    }
+}
+
+/// Clamps `val` to the pixel centers of a dimension of `size` pixels, NaN becomes 0.
+#[inline]
+fn clamp(val: f32, size: u32) -> f32 {
+    assert!(size > 0, "cannot sample an empty image");
+    val.max(0.0).min((size - 1) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{consts, Image, Pixel};
+
+    fn gradient() -> Image {
+        let mut img = Image::new(2, 2);
+        img.set_pixel(0, 0, consts::BLACK);
+        img.set_pixel(1, 0, px!(100, 0, 0));
+        img.set_pixel(0, 1, px!(0, 100, 0));
+        img.set_pixel(1, 1, px!(100, 100, 200));
+        img
+    }
+
+    #[test]
+    fn bilinear_interpolates_between_centers() {
+        let img = gradient();
+        assert_eq!(img.sample_bilinear(0.0, 0.0), consts::BLACK);
+        assert_eq!(img.sample_bilinear(0.5, 0.0), px!(50, 0, 0));
+        assert_eq!(img.sample_bilinear(0.5, 0.5), px!(50, 50, 50));
+        assert_eq!(img.sample_bilinear(1.0, 1.0), px!(100, 100, 200));
+        // Clamped to the edges
+        assert_eq!(img.sample_bilinear(-3.0, 7.0), px!(0, 100, 0));
+        assert_eq!(img.sample_bilinear(f32::NAN, 0.0), consts::BLACK);
+    }
+
+    #[test]
+    fn bilinear_follows_the_color_policy() {
+        use super::Filter;
+        use crate::policy::{ColorPolicy, Rounding};
+
+        let img = gradient();
+        let down = ColorPolicy {
+            rounding: Rounding::Down,
+            ..ColorPolicy::default()
+        };
+        assert_eq!(img.sample(0.25, 0.0, Filter::Bilinear), px!(25, 0, 0));
+        assert_eq!(img.sample(0.255, 0.0, Filter::Bilinear), px!(26, 0, 0));
+        assert_eq!(
+            img.sample_with(0.255, 0.0, Filter::Bilinear, down),
+            px!(25, 0, 0)
+        );
+    }
+
+    #[test]
+    fn nearest_picks_the_closest_pixel() {
+        let img = gradient();
+        assert_eq!(img.sample_nearest(0.4, 0.6), px!(0, 100, 0));
+        assert_eq!(img.sample_nearest(0.6, 0.4), px!(100, 0, 0));
+        assert_eq!(img.sample_nearest(5.0, -1.0), px!(100, 0, 0));
+    }
+}
@@ -0,0 +1,142 @@
+//! Reconstruction of animations stored as partial frames.
+//!
+//! Screen recorders and GIF converters often dump only the region that changed
+//! in every frame. `compose` layers these regions the way a GIF decoder does and
+//! returns the complete frames.
+//!
+//! ```
+//! use two::sequence::{self, Disposal};
+//! use two::{consts, Image};
+//!
+//! let mut background = Image::new(8, 8);
+//! background.fill(consts::WHITE);
+//! let mut cursor = Image::new(2, 2);
+//! cursor.fill(consts::RED);
+//!
+//! let frames = sequence::compose(
+//!     &[background, cursor.clone(), cursor],
+//!     &[(0, 0), (1, 1), (5, 5)],
+//!     &[Disposal::Keep, Disposal::Previous, Disposal::Previous],
+//! )
+//! .unwrap();
+//! assert_eq!(frames[2].get_pixel(1, 1), consts::WHITE);
+//! assert_eq!(frames[2].get_pixel(5, 5), consts::RED);
+//! ```
+
+use crate::decoder::{BmpError, BmpErrorKind, BmpResult};
+use crate::{consts, Image};
+
+/// What happens to the region of a frame before the next frame is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disposal {
+    /// Leaves the frame in place.
+    Keep,
+    /// Clears the region of the frame to black.
+    Background,
+    /// Restores the region to what it was before the frame was drawn.
+    Previous,
+}
+
+/// Draws every frame at its offset on top of the ones before it and returns the
+/// full frames.
+///
+/// The canvas is large enough for all frames at their offsets and starts out
+/// black. `offsets` and `disposal` hold one entry per frame.
+pub fn compose(
+    frames: &[Image],
+    offsets: &[(u32, u32)],
+    disposal: &[Disposal],
+) -> BmpResult<Vec<Image>> {
+    if offsets.len() != frames.len() || disposal.len() != frames.len() {
+        return Err(BmpError::new(
+            BmpErrorKind::DimensionMismatch,
+            format!(
+                "Expected an offset and a disposal for each of the {} frames, but found {} and {}",
+                frames.len(),
+                offsets.len(),
+                disposal.len()
+            ),
+        ));
+    }
+
+    let (width, height) = frames
+        .iter()
+        .zip(offsets.iter())
+        .fold((0, 0), |(w, h), (frame, &(x, y))| {
+            (w.max(x + frame.width), h.max(y + frame.height))
+        });
+
+    let mut canvas = Image::new(width, height);
+    let mut composed = Vec::with_capacity(frames.len());
+    for ((frame, &(x, y)), disposal) in frames.iter().zip(offsets.iter()).zip(disposal.iter()) {
+        let rect = (x, y, frame.width, frame.height);
+        let previous = match disposal {
+            Disposal::Previous => Some(canvas.crop(rect)),
+            _ => None,
+        };
+
+        let whole = (0, 0, frame.width, frame.height);
+        canvas.copy_from(frame, whole, (x as i32, y as i32));
+        composed.push(canvas.clone());
+
+        match disposal {
+            Disposal::Keep => {}
+            Disposal::Background => canvas.fill_rect(rect, consts::BLACK),
+            Disposal::Previous => {
+                if let Some(previous) = previous {
+                    canvas.copy_from(&previous, whole, (x as i32, y as i32));
+                }
+            }
+        }
+    }
+    Ok(composed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_layered_and_disposed() {
+        let colors = [consts::WHITE, consts::RED, consts::BLUE, consts::RED];
+        let frames: Vec<Image> = [4, 2, 1, 1]
+            .iter()
+            .zip(colors)
+            .map(|(&size, color)| {
+                let mut img = Image::new(size, size);
+                img.fill(color);
+                img
+            })
+            .collect();
+        let offsets = [(0, 0), (0, 0), (3, 3), (4, 4)];
+        let disposal = [
+            Disposal::Keep,
+            Disposal::Background,
+            Disposal::Keep,
+            Disposal::Keep,
+        ];
+
+        let out = compose(&frames, &offsets, &disposal).unwrap();
+        assert_eq!(out.len(), 4);
+        // The canvas grows to fit the last frame
+        assert_eq!((out[0].get_width(), out[0].get_height()), (5, 5));
+        assert_eq!(out[0].get_pixel(4, 4), consts::BLACK);
+        assert_eq!(out[1].get_pixel(1, 1), consts::RED);
+        assert_eq!(out[2].get_pixel(1, 1), consts::BLACK);
+        assert_eq!(out[2].get_pixel(2, 2), consts::WHITE);
+        assert_eq!(out[3].get_pixel(3, 3), consts::BLUE);
+        assert_eq!(out[3].get_pixel(4, 4), consts::RED);
+    }
+
+    #[test]
+    fn error_when_offsets_are_missing() {
+        let frames = [Image::new(1, 1)];
+        match compose(&frames, &[], &[Disposal::Keep]) {
+            Err(BmpError {
+                kind: BmpErrorKind::DimensionMismatch,
+                ..
+            }) => (/* Expected */),
+            _ => panic!("Missing offsets should not be accepted"),
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Decoding from any `Read` without holding the whole file in memory.
+//!
+//! `from_reader` reads the whole file before decoding it. `StreamDecoder` only
+//! reads up to the pixel data at first, then one row at a time, and never seeks.
+//! Compressed rows are expanded as they are read, up to the code ending them.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use two::stream::StreamDecoder;
+//!
+//! let file = BufReader::new(File::open("scan.bmp").unwrap());
+//! let mut decoder = StreamDecoder::new(file).unwrap();
+//! let mut row = vec![two::consts::BLACK; decoder.info().width as usize];
+//! while let Some(y) = decoder.next_row(&mut row).unwrap() {
+//!     println!("row {} starts with {}", y, row[0]);
+//! }
+//! ```
+
+use std::io::{Cursor, Read};
+
+use crate::decoder::{self, BmpError, BmpErrorKind, BmpResult, RleRows};
+use crate::{
+    flip_rows, huffman, wire, BmpDibHeader, BmpHeader, ColorSpaceFields, Image, ImageInfo, Limits,
+    Pixel,
+};
+
+/// Decodes an image row by row as it is read.
+pub struct StreamDecoder<R> {
+    reader: R,
+    header: BmpHeader,
+    dib_header: BmpDibHeader,
+    color_palette: Option<Vec<Pixel>>,
+    color_space: Option<ColorSpaceFields>,
+    masks: [u32; 4],
+    alpha_mask: u32,
+    // The bytes of the current row, with padding, or its indexes if compressed
+    row: Vec<u8>,
+    compressed: Option<Compressed>,
+    rows_read: u32,
+}
+
+/// The state of the expansion of compressed rows, which is carried over from
+/// one row to the next.
+enum Compressed {
+    Rle(RleRows),
+    Huffman(huffman::Rows),
+}
+
+impl<R: Read> StreamDecoder<R> {
+    /// Reads the headers and the palette, stopping at the pixel data.
+    pub fn new(mut reader: R) -> BmpResult<StreamDecoder<R>> {
+        let mut prefix = vec![0; wire::FILE_HEADER_SIZE as usize];
+        reader.read_exact(&mut prefix)?;
+        let pixel_offset = u32::from_le_bytes([prefix[10], prefix[11], prefix[12], prefix[13]]);
+        if (pixel_offset as u64) < wire::FILE_HEADER_SIZE + wire::DIB_HEADER_SIZE as u64 {
+            return Err(BmpError::new(
+                BmpErrorKind::UnsupportedHeader,
+                format!("The pixel data cannot start at {}", pixel_offset),
+            ));
+        }
+        // Everything before the pixel data, which is parsed as usual
+        let rest = pixel_offset as u64 - wire::FILE_HEADER_SIZE;
+        (&mut reader).take(rest).read_to_end(&mut prefix)?;
+
+        let mut prefix = Cursor::new(&prefix[..]);
+        let (header, dib_header, color_palette) =
+            decoder::decode_headers(&mut prefix, Limits::default())?;
+        let masks = decoder::read_bitmasks(&mut prefix, &dib_header)?;
+        let alpha_mask = decoder::alpha_mask(&mut prefix, &dib_header)?;
+        let color_space = decoder::read_color_space(&mut prefix, &dib_header)?;
+
+        let width = dib_header.width.unsigned_abs();
+        let height = dib_header.height.unsigned_abs();
+        let compressed = match color_palette {
+            Some(_) if decoder::is_huffman(&dib_header) => {
+                Some(Compressed::Huffman(huffman::Rows::new()))
+            }
+            Some(_) if matches!(dib_header.compress_type, 1 | 2) => {
+                let bpp = dib_header.bits_per_pixel;
+                let rows = RleRows::new(width as usize, height as usize, bpp, false);
+                Some(Compressed::Rle(rows))
+            }
+            _ => None,
+        };
+        let row_len = match compressed {
+            Some(_) => width as usize,
+            None => decoder::row_size(width, dib_header.bits_per_pixel),
+        };
+
+        Ok(StreamDecoder {
+            reader,
+            row: vec![0; row_len],
+            header,
+            dib_header,
+            color_palette,
+            color_space,
+            masks,
+            alpha_mask,
+            compressed,
+            rows_read: 0,
+        })
+    }
+
+    /// Returns the size of the image.
+    pub fn info(&self) -> ImageInfo {
+        ImageInfo {
+            width: self.dib_header.width.unsigned_abs(),
+            height: self.dib_header.height.unsigned_abs(),
+        }
+    }
+
+    /// Returns whether the rows come from the bottom up, as they usually do.
+    #[inline]
+    pub fn is_bottom_up(&self) -> bool {
+        self.dib_header.is_bottom_up()
+    }
+
+    /// Decodes the next row in file order into the start of `row`, returning its
+    /// `y` coordinate counted from the top, or `None` after the last row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is shorter than the width of the image.
+    pub fn next_row(&mut self, row: &mut [Pixel]) -> BmpResult<Option<u32>> {
+        let ImageInfo { width, height } = self.info();
+        let row = &mut row[..width as usize];
+        if self.rows_read == height {
+            return Ok(None);
+        }
+
+        match self.compressed {
+            Some(ref mut compressed) => {
+                match compressed {
+                    Compressed::Rle(rows) => rows.next_row(&mut self.reader, &mut self.row)?,
+                    Compressed::Huffman(rows) => rows.next_row(&mut self.reader, &mut self.row)?,
+                }
+                let palette = self.color_palette.as_deref().unwrap_or_default();
+                for (px, &i) in row.iter_mut().zip(&self.row) {
+                    *px = decoder::palette_color(palette, i as usize)?;
+                }
+            }
+            None => {
+                self.reader.read_exact(&mut self.row)?;
+                let mut x = 0;
+                let palette = self.color_palette.as_deref();
+                decoder::decode_row(&self.row, &self.dib_header, palette, self.masks, |px| {
+                    row[x] = px;
+                    x += 1;
+                })?;
+            }
+        }
+
+        let y = match self.is_bottom_up() {
+            true => height - 1 - self.rows_read,
+            false => self.rows_read,
+        };
+        self.rows_read += 1;
+        Ok(Some(y))
+    }
+
+    /// Decodes the remaining rows into an image, keeping any alpha as `open` does.
+    ///
+    /// Color profiles are not read, as they are usually stored after the pixels.
+    pub fn decode(mut self) -> BmpResult<Image> {
+        let ImageInfo { width, height } = self.info();
+        let mut data = vec![px!(0, 0, 0); (width * height) as usize];
+        let has_alpha = self.alpha_mask != 0 && self.compressed.is_none();
+        let mut alpha = Vec::new();
+        for file_row in data.chunks_exact_mut(width.max(1) as usize) {
+            if self.next_row(file_row)?.is_none() {
+                break;
+            }
+            if has_alpha {
+                decoder::decode_row_alpha(&self.row, &self.dib_header, self.alpha_mask, |a| {
+                    alpha.push(a)
+                });
+            }
+        }
+
+        // The rows were stored in file order
+        if self.is_bottom_up() {
+            flip_rows(&mut data, width as usize);
+            flip_rows(&mut alpha, width as usize);
+        }
+        // An alpha channel of zeros is meant to be opaque
+        let alpha = match alpha.iter().any(|&a| a != 0) {
+            true => Some(alpha),
+            false => None,
+        };
+
+        Ok(Image {
+            header: self.header,
+            dib_header: self.dib_header,
+            color_palette: self.color_palette,
+            color_space: self.color_space,
+            icc_profile: None,
+            width,
+            height,
+            padding: width % 4,
+            data,
+            alpha,
+            indices: None,
+            history: None,
+            source_headers: None,
+        })
+    }
+}
+
+impl<R: Read> StreamDecoder<R> {
+    /// Turns the decoder into an iterator over the remaining rows.
+    pub fn rows(self) -> RowDecoder<R> {
+        RowDecoder {
+            decoder: self,
+            failed: false,
+        }
+    }
+}
+
+/// An iterator decoding one row at a time, in file order, as `(y, pixels)`
+/// with `y` counted from the top.
+///
+/// Each row is a new `Vec`. To reuse a single buffer instead, call
+/// `next_row_into`. The iterator ends after the first error.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use two::stream::RowDecoder;
+///
+/// let rows = RowDecoder::new(File::open("scan.bmp").unwrap()).unwrap();
+/// for row in rows {
+///     let (y, pixels) = row.unwrap();
+///     println!("row {} starts with {}", y, pixels[0]);
+/// }
+/// ```
+pub struct RowDecoder<R> {
+    decoder: StreamDecoder<R>,
+    failed: bool,
+}
+
+impl<R: Read> RowDecoder<R> {
+    /// Reads the headers and the palette, stopping at the pixel data.
+    pub fn new(reader: R) -> BmpResult<RowDecoder<R>> {
+        StreamDecoder::new(reader).map(StreamDecoder::rows)
+    }
+
+    /// Returns the size of the image.
+    #[inline]
+    pub fn info(&self) -> ImageInfo {
+        self.decoder.info()
+    }
+
+    /// Decodes the next row into the start of `row`, as `StreamDecoder::next_row` does.
+    pub fn next_row_into(&mut self, row: &mut [Pixel]) -> BmpResult<Option<u32>> {
+        if self.failed {
+            return Ok(None);
+        }
+        self.decoder
+            .next_row(row)
+            .inspect_err(|_| self.failed = true)
+    }
+}
+
+impl<R: Read> Iterator for RowDecoder<R> {
+    type Item = BmpResult<(u32, Vec<Pixel>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut row = vec![px!(0, 0, 0); self.info().width as usize];
+        self.next_row_into(&mut row)
+            .map(|y| y.map(|y| (y, row)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = match self.failed {
+            true => 0,
+            false => (self.info().height - self.decoder.rows_read) as usize,
+        };
+        (0, Some(left))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Hands out a few bytes at a time and cannot seek.
+    struct Trickle(Vec<u8>, usize);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(7).min(self.0.len() - self.1);
+            buf[..n].copy_from_slice(&self.0[self.1..self.1 + n]);
+            self.1 += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn streamed_images_match_buffered_ones() {
+        for path in &[
+            "test/bmpsuite-2.5/g/pal1.bmp",
+            "test/bmpsuite-2.5/g/pal4rle.bmp",
+            "test/bmpsuite-2.5/g/pal8rle.bmp",
+            "test/bmpsuite-2.5/q/pal8rletrns.bmp",
+            "test/bmpsuite-2.5/q/pal1huff.bmp",
+            "test/bmpsuite-2.5/g/pal8v5.bmp",
+            "test/bmpsuite-2.5/g/pal8topdown.bmp",
+            "test/bmpsuite-2.5/g/rgb16-565.bmp",
+            "test/bmpsuite-2.5/g/rgb24.bmp",
+            "test/bmpsuite-2.5/q/rgba32.bmp",
+        ] {
+            let bytes = fs::read(path).unwrap();
+            let expected = crate::open(path).unwrap();
+            let img = StreamDecoder::new(Trickle(bytes, 0))
+                .unwrap()
+                .decode()
+                .unwrap();
+            assert_eq!(img.data, expected.data, "{}", path);
+            assert_eq!(img.alpha, expected.alpha, "{}", path);
+        }
+    }
+
+    #[test]
+    fn compressed_rows_are_read_as_needed() {
+        for path in &[
+            "test/bmpsuite-2.5/g/pal8rle.bmp",
+            "test/bmpsuite-2.5/q/pal1huff.bmp",
+        ] {
+            let bytes = fs::read(path).unwrap();
+            let len = bytes.len();
+            let mut decoder = StreamDecoder::new(Trickle(bytes, 0)).unwrap();
+            let mut row = vec![px!(0, 0, 0); decoder.info().width as usize];
+            decoder.next_row(&mut row).unwrap();
+            // The codes of the first row end far from the end of the file
+            assert!(decoder.reader.1 < len / 4, "{}", path);
+        }
+    }
+
+    #[test]
+    fn rows_come_in_file_order() {
+        let file = fs::File::open("test/rgbw.bmp").unwrap();
+        let mut decoder = StreamDecoder::new(file).unwrap();
+        assert!(decoder.is_bottom_up());
+
+        let mut row = vec![px!(0, 0, 0); 2];
+        assert_eq!(decoder.next_row(&mut row).unwrap(), Some(1));
+        assert_eq!(row, vec![crate::consts::BLUE, crate::consts::WHITE]);
+        assert_eq!(decoder.next_row(&mut row).unwrap(), Some(0));
+        assert_eq!(decoder.next_row(&mut row).unwrap(), None);
+    }
+
+    #[test]
+    fn row_iterator_stops_after_an_error() {
+        let img = crate::open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        let rows: Vec<_> =
+            RowDecoder::new(fs::File::open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap())
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+        assert_eq!(rows.len(), 64);
+        assert_eq!(rows[0].0, 63);
+        assert_eq!(rows[0].1, img.data[63 * 127..]);
+
+        // Cut in the middle of the second row
+        let mut bytes = fs::read("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        let pixel_offset = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]);
+        bytes.truncate(pixel_offset as usize + 127 * 3 * 3 / 2);
+        let mut rows = RowDecoder::new(&bytes[..]).unwrap();
+        assert!(matches!(rows.next(), Some(Ok((63, _)))));
+        assert!(matches!(rows.next(), Some(Err(_))));
+        assert!(rows.next().is_none());
+    }
+}
@@ -0,0 +1,105 @@
+//! Float tensors for machine learning pipelines.
+//!
+//! Models usually take the channels as separate planes (CHW), while most other
+//! code keeps them interleaved (HWC). Both are flat row-major `Vec<f32>`s that
+//! `ndarray` or `tch` can take over with the shape `[3, height, width]` or
+//! `[height, width, 3]`.
+//!
+//! ```
+//! use two::tensor::Norm;
+//! use two::{consts, Image};
+//!
+//! let mut img = Image::new(2, 1);
+//! img.set_pixel(1, 0, consts::RED);
+//! let chw = img.to_f32_chw(Norm::Unit);
+//! assert_eq!(chw, vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+//! ```
+
+use crate::Image;
+
+/// How the 0 to 255 channels are turned into floats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Norm {
+    /// Keeps the values from 0.0 to 255.0.
+    None,
+    /// Scales the values to `0.0..=1.0`.
+    Unit,
+    /// Scales the values to `0.0..=1.0`, then subtracts `mean` and divides by
+    /// `std`, per channel in RGB order.
+    MeanStd { mean: [f32; 3], std: [f32; 3] },
+}
+
+impl Norm {
+    /// The statistics of ImageNet, which most pretrained vision models expect.
+    pub const IMAGENET: Norm = Norm::MeanStd {
+        mean: [0.485, 0.456, 0.406],
+        std: [0.229, 0.224, 0.225],
+    };
+
+    #[inline]
+    fn apply(self, channel: usize, val: u8) -> f32 {
+        match self {
+            Norm::None => val as f32,
+            Norm::Unit => val as f32 / 255.0,
+            Norm::MeanStd { mean, std } => (val as f32 / 255.0 - mean[channel]) / std[channel],
+        }
+    }
+}
+
+impl Image {
+    /// Returns the channels as planes, first all red values row by row, then
+    /// green, then blue.
+    pub fn to_f32_chw(&self, norm: Norm) -> Vec<f32> {
+        let plane = self.data.len();
+        let mut out = vec![0.0; plane * 3];
+        for (i, px) in self.data.iter().enumerate() {
+            for (c, &val) in [px.r, px.g, px.b].iter().enumerate() {
+                out[c * plane + i] = norm.apply(c, val);
+            }
+        }
+        out
+    }
+
+    /// Returns the channels interleaved, red, green and blue for every pixel
+    /// row by row.
+    pub fn to_f32_hwc(&self, norm: Norm) -> Vec<f32> {
+        self.data
+            .iter()
+            .flat_map(|px| [px.r, px.g, px.b].into_iter().enumerate())
+            .map(|(c, val)| norm.apply(c, val))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts;
+
+    #[test]
+    fn layouts_hold_the_same_values() {
+        let img = crate::open("test/rgbw.bmp").unwrap();
+        let chw = img.to_f32_chw(Norm::None);
+        let hwc = img.to_f32_hwc(Norm::None);
+        assert_eq!(chw[..4], [255.0, 0.0, 0.0, 255.0]);
+        assert_eq!(hwc[..6], [255.0, 0.0, 0.0, 0.0, 255.0, 0.0]);
+        for i in 0..4 {
+            for c in 0..3 {
+                assert_eq!(chw[c * 4 + i], hwc[i * 3 + c]);
+            }
+        }
+    }
+
+    #[test]
+    fn mean_std_normalization() {
+        let mut img = Image::new(1, 1);
+        img.set_pixel(0, 0, consts::WHITE);
+        let norm = Norm::MeanStd {
+            mean: [0.5, 0.0, 1.0],
+            std: [0.5, 2.0, 1.0],
+        };
+        assert_eq!(img.to_f32_hwc(norm), vec![1.0, 0.5, 0.0]);
+        let imagenet = img.to_f32_chw(Norm::IMAGENET);
+        assert!((imagenet[0] - 2.2489).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,192 @@
+//! Splitting an image into disjoint tiles that can be edited independently.
+//!
+//! Each `TileMut` borrows its own part of every row it covers, so tiles can be
+//! handed to different threads without any unsafe code. With the `rayon`
+//! feature, `Image::par_for_each_tile` does just that.
+//!
+//! ```
+//! use two::{consts, Image};
+//!
+//! let mut img = Image::new(5, 3);
+//! for mut tile in img.tiles_mut((2, 2)) {
+//!     if tile.rect().x == 4 {
+//!         tile.fill(consts::RED);
+//!     }
+//! }
+//! assert_eq!(img.get_pixel(4, 2), consts::RED);
+//! assert_eq!(img.get_pixel(3, 2), consts::BLACK);
+//! ```
+
+use crate::geom::{Rect, Size};
+use crate::{Image, Pixel};
+
+/// A mutable view of a rectangular part of an image, with coordinates
+/// relative to its top-left corner.
+#[derive(Debug)]
+pub struct TileMut<'a> {
+    rect: Rect,
+    rows: Vec<&'a mut [Pixel]>,
+}
+
+impl<'a> TileMut<'a> {
+    /// Returns the part of the image the tile covers.
+    #[inline]
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.rect.width
+    }
+
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.rect.height
+    }
+
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> Pixel {
+        self.rows[y as usize][x as usize]
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: u32, y: u32, val: Pixel) {
+        self.rows[y as usize][x as usize] = val;
+    }
+
+    /// Returns row `y` of the tile.
+    #[inline]
+    pub fn row_mut(&mut self, y: u32) -> &mut [Pixel] {
+        self.rows[y as usize]
+    }
+
+    /// Sets every pixel of the tile to `val`.
+    pub fn fill(&mut self, val: Pixel) {
+        for row in &mut self.rows {
+            row.fill(val);
+        }
+    }
+}
+
+impl Image {
+    /// Splits the image into tiles of `tile_size`, row by row from the top-left.
+    /// The tiles along the right and bottom edges are smaller if the size does
+    /// not divide the image evenly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width or height of `tile_size` is zero.
+    pub fn tiles_mut<S: Into<Size>>(&mut self, tile_size: S) -> Vec<TileMut<'_>> {
+        let tile_size = tile_size.into();
+        assert!(
+            tile_size.width > 0 && tile_size.height > 0,
+            "tiles cannot be empty"
+        );
+        let Size { width, height } = self.size();
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+
+        let columns = width.div_ceil(tile_size.width) as usize;
+        let mut tiles: Vec<TileMut> = Vec::new();
+        for (y, row) in self.data.chunks_exact_mut(width as usize).enumerate() {
+            let y = y as u32;
+            // The first row of a band of tiles starts them
+            if y.is_multiple_of(tile_size.height) {
+                let h = tile_size.height.min(height - y);
+                for x in (0..width).step_by(tile_size.width as usize) {
+                    let w = tile_size.width.min(width - x);
+                    tiles.push(TileMut {
+                        rect: Rect::new(x, y, w, h),
+                        rows: Vec::with_capacity(h as usize),
+                    });
+                }
+            }
+            let band_start = tiles.len() - columns;
+            let band = &mut tiles[band_start..];
+            for (tile, part) in band
+                .iter_mut()
+                .zip(row.chunks_mut(tile_size.width as usize))
+            {
+                tile.rows.push(part);
+            }
+        }
+        tiles
+    }
+
+    /// Calls `f` on every tile of `tile_size`, in parallel.
+    ///
+    /// ```
+    /// use two::{consts, Image};
+    ///
+    /// let mut img = Image::new(64, 64);
+    /// img.par_for_each_tile((16, 16), |mut tile| {
+    ///     if (tile.rect().x + tile.rect().y) % 32 == 0 {
+    ///         tile.fill(consts::WHITE);
+    ///     }
+    /// });
+    /// assert_eq!(img.get_pixel(20, 20), consts::WHITE);
+    /// assert_eq!(img.get_pixel(20, 0), consts::BLACK);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width or height of `tile_size` is zero.
+    #[cfg(feature = "rayon")]
+    pub fn par_for_each_tile<S, F>(&mut self, tile_size: S, f: F)
+    where
+        S: Into<Size>,
+        F: Fn(TileMut<'_>) + Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        self.tiles_mut(tile_size).into_par_iter().for_each(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let mut img = Image::new(7, 5);
+        let rects: Vec<Rect> = img.tiles_mut((3, 2)).iter().map(TileMut::rect).collect();
+        assert_eq!(rects.len(), 9);
+        assert_eq!(rects[2], Rect::new(6, 0, 1, 2));
+        assert_eq!(rects[8], Rect::new(6, 4, 1, 1));
+
+        for mut tile in img.tiles_mut((3, 2)) {
+            let rect = tile.rect();
+            for y in 0..tile.get_height() {
+                for x in 0..tile.get_width() {
+                    let px = tile.get_pixel(x, y);
+                    tile.set_pixel(x, y, px!(px.r + 1, rect.x as u8, rect.y as u8));
+                }
+            }
+        }
+        for (x, y) in img.coordinates() {
+            let expected = px!(1, (x / 3 * 3) as u8, (y / 2 * 2) as u8);
+            assert_eq!(img.get_pixel(x, y), expected);
+        }
+        assert!(Image::new(0, 3).tiles_mut((1, 1)).is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_tiles_match_sequential_ones() {
+        let mut img = crate::open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        let mut expected = img.clone();
+        let invert = |mut tile: TileMut| {
+            for y in 0..tile.get_height() {
+                for px in tile.row_mut(y) {
+                    *px = px!(255 - px.r, 255 - px.g, 255 - px.b);
+                }
+            }
+        };
+        img.par_for_each_tile((10, 7), invert);
+        expected.tiles_mut((10, 7)).into_iter().for_each(invert);
+        assert_eq!(img, expected);
+    }
+}
@@ -0,0 +1,290 @@
+//! Geometric transforms that resample an image through a coordinate mapping.
+//!
+//! Every pixel of the output is looked up at its mapped position in the source,
+//! so the output has no holes. Positions outside of the source get a background
+//! color, black unless given.
+
+use crate::decoder::{BmpError, BmpErrorKind, BmpResult};
+use crate::history::OpRecord;
+use crate::sample::Filter;
+use crate::{consts, Image, Limits, Pixel};
+
+impl Image {
+    /// Corrects radial lens distortion around the optical center `(cx, cy)`.
+    ///
+    /// Uses the Brown–Conrady model without tangential terms: an undistorted point
+    /// at radius `r` from the center was captured at radius `r * (1 + k1 * r^2 + k2 * r^4)`.
+    /// Radii are measured in units of half the longer image side. Negative
+    /// coefficients fix pincushion distortion, positive ones fix barrel distortion.
+    pub fn undistort(&self, k1: f32, k2: f32, cx: f32, cy: f32, filter: Filter) -> Image {
+        let norm = self.width.max(self.height) as f32 / 2.0;
+        let mut out = self.remap(self.width, self.height, filter, consts::BLACK, |x, y| {
+            let (dx, dy) = ((x - cx) / norm, (y - cy) / norm);
+            let r2 = dx * dx + dy * dy;
+            let scale = 1.0 + k1 * r2 + k2 * r2 * r2;
+            (cx + dx * scale * norm, cy + dy * scale * norm)
+        });
+        self.derive_history(&mut out, || {
+            OpRecord::new("undistort")
+                .param("k1", k1)
+                .param("k2", k2)
+                .param("cx", cx)
+                .param("cy", cy)
+                .param("filter", format!("{:?}", filter))
+        });
+        out
+    }
+
+    /// Rotates the image clockwise by `degrees` around its center, keeping its size.
+    ///
+    /// Corners rotated out of the image are lost, areas rotated in are `background`.
+    pub fn rotate(&self, degrees: f32, filter: Filter, background: Pixel) -> Image {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let cx = (self.width as f32 - 1.0) / 2.0;
+        let cy = (self.height as f32 - 1.0) / 2.0;
+        let mut out = self.remap(self.width, self.height, filter, background, |x, y| {
+            // Rotating back counterclockwise finds the source
+            let (dx, dy) = (x - cx, y - cy);
+            (cx + dx * cos + dy * sin, cy - dx * sin + dy * cos)
+        });
+        self.derive_history(&mut out, || {
+            OpRecord::new("rotate")
+                .param("degrees", degrees)
+                .param("filter", format!("{:?}", filter))
+                .param("background", background)
+        });
+        out
+    }
+
+    /// Applies the homography `h`, a row-major 3x3 matrix mapping points of this
+    /// image to points of the `out_width` by `out_height` output.
+    ///
+    /// For rectifying a scanned page, `h` maps the page's corners in the scan to
+    /// the corners of the output. The output is filled with `background` if `h`
+    /// cannot be inverted.
+    pub fn warp_perspective(
+        &self,
+        h: [f32; 9],
+        out_width: u32,
+        out_height: u32,
+        filter: Filter,
+        background: Pixel,
+    ) -> Image {
+        let mut out = match invert(h) {
+            Some(inv) => self.remap(out_width, out_height, filter, background, |x, y| {
+                let w = inv[6] * x + inv[7] * y + inv[8];
+                if w.abs() < f32::EPSILON {
+                    // Maps to infinity, so outside of the image
+                    return (f32::NAN, f32::NAN);
+                }
+                (
+                    (inv[0] * x + inv[1] * y + inv[2]) / w,
+                    (inv[3] * x + inv[4] * y + inv[5]) / w,
+                )
+            }),
+            None => {
+                let mut out = Image::new(out_width, out_height);
+                out.fill(background);
+                out
+            }
+        };
+        self.derive_history(&mut out, || {
+            OpRecord::new("warp_perspective")
+                .param("h", format!("{:?}", h))
+                .param("width", out_width)
+                .param("height", out_height)
+                .param("filter", format!("{:?}", filter))
+                .param("background", background)
+        });
+        out
+    }
+
+    /// Stretches the image along its less resolved axis until its pixels are
+    /// square, so that it displays with the proportions it was captured with.
+    ///
+    /// The result has the higher of the two resolutions both ways. Images
+    /// whose pixels are already square are copied. The resolutions come from
+    /// the file, so a result beyond the default `Limits` is `LimitsExceeded`.
+    pub fn resample_to_square_pixels(&self, filter: Filter) -> BmpResult<Image> {
+        let ratio = self.pixel_aspect_ratio() as f64;
+        let (sx, sy) = if ratio >= 1.0 {
+            (ratio, 1.0)
+        } else {
+            (1.0, 1.0 / ratio)
+        };
+        let width = (self.width as f64 * sx).round();
+        let height = (self.height as f64 * sy).round();
+        let limits = Limits::default();
+        if width > limits.max_width as f64
+            || height > limits.max_height as f64
+            || width * height * 4.0 > limits.max_pixel_bytes as f64
+        {
+            return Err(BmpError::new(
+                BmpErrorKind::LimitsExceeded,
+                format!("Square pixels would make the image {}x{}", width, height),
+            ));
+        }
+        let (width, height) = (width as u32, height as u32);
+        let (sx, sy) = (sx as f32, sy as f32);
+        let mut out = if (width, height) == (self.width, self.height) {
+            self.clone()
+        } else {
+            let mut out = self.remap(width, height, filter, consts::BLACK, |x, y| {
+                ((x + 0.5) / sx - 0.5, (y + 0.5) / sy - 0.5)
+            });
+            let res = self.dib_header.hres.max(self.dib_header.vres);
+            out.dib_header.hres = res;
+            out.dib_header.vres = res;
+            out
+        };
+        self.derive_history(&mut out, || {
+            OpRecord::new("resample_to_square_pixels").param("filter", format!("{:?}", filter))
+        });
+        Ok(out)
+    }
+
+    /// Creates a `width` by `height` image whose pixel `(x, y)` is sampled at
+    /// `map(x, y)` in this image, or set to `background` outside of it.
+    fn remap<F>(&self, width: u32, height: u32, filter: Filter, background: Pixel, map: F) -> Image
+    where
+        F: Fn(f32, f32) -> (f32, f32),
+    {
+        let mut out = Image::new(width, height);
+        let inside = |v: f32, size: u32| v >= -0.5 && v < size as f32 - 0.5;
+        for (x, y) in out.coordinates() {
+            let (sx, sy) = map(x as f32, y as f32);
+            let px = if inside(sx, self.width) && inside(sy, self.height) {
+                self.sample(sx, sy, filter)
+            } else {
+                background
+            };
+            out.set_pixel(x, y, px);
+        }
+        out
+    }
+}
+
+/// Returns the inverse of the row-major 3x3 matrix `m`, if it is not singular.
+fn invert(m: [f32; 9]) -> Option<[f32; 9]> {
+    // Transposed cofactors divided by the determinant, in f64 for stability
+    let m = m.map(|v| v as f64);
+    let cof = [
+        m[4] * m[8] - m[5] * m[7],
+        m[2] * m[7] - m[1] * m[8],
+        m[1] * m[5] - m[2] * m[4],
+        m[5] * m[6] - m[3] * m[8],
+        m[0] * m[8] - m[2] * m[6],
+        m[2] * m[3] - m[0] * m[5],
+        m[3] * m[7] - m[4] * m[6],
+        m[1] * m[6] - m[0] * m[7],
+        m[0] * m[4] - m[1] * m[3],
+    ];
+    let det = m[0] * cof[0] + m[1] * cof[3] + m[2] * cof[6];
+    if det.abs() < 1e-12 || !det.is_finite() {
+        return None;
+    }
+    Some(cof.map(|c| (c / det) as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard() -> Image {
+        let mut img = Image::new(16, 16);
+        for (x, y) in img.coordinates() {
+            if (x / 4 + y / 4) % 2 == 0 {
+                img.set_pixel(x, y, consts::WHITE);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn zero_coefficients_keep_the_image() {
+        let img = checkerboard();
+        for filter in &[Filter::Nearest, Filter::Bilinear] {
+            assert_eq!(img.undistort(0.0, 0.0, 7.5, 7.5, *filter), img);
+        }
+    }
+
+    #[test]
+    fn undistort_moves_pixels_radially() {
+        let mut img = Image::new(21, 21);
+        img.set_pixel(18, 10, consts::WHITE);
+        img.set_pixel(10, 10, consts::RED);
+
+        // r = 0.5 at x = 15, which was captured at 0.5 * 1.6 = 0.8, x = 18
+        let out = img.undistort(2.4, 0.0, 10.0, 10.0, Filter::Nearest);
+        assert_eq!(out.get_pixel(15, 10), consts::WHITE);
+        assert_eq!(out.get_pixel(10, 10), consts::RED);
+        // Sampled beyond the edge
+        assert_eq!(out.get_pixel(20, 20), consts::BLACK);
+    }
+
+    #[test]
+    fn warp_perspective_applies_the_homography() {
+        let img = checkerboard();
+        let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let out = img.warp_perspective(identity, 16, 16, Filter::Nearest, consts::RED);
+        assert_eq!(out, img);
+
+        // Scaling down by two and moving right by 4, the rest is background
+        let h = [0.5, 0.0, 4.0, 0.0, 0.5, 0.0, 0.0, 0.0, 1.0];
+        let out = img.warp_perspective(h, 16, 16, Filter::Nearest, consts::RED);
+        assert_eq!(out.get_pixel(4, 0), img.get_pixel(0, 0));
+        assert_eq!(out.get_pixel(6, 0), img.get_pixel(4, 0));
+        assert_eq!(out.get_pixel(0, 0), consts::RED);
+        assert_eq!(out.get_pixel(4, 12), consts::RED);
+
+        let out = img.warp_perspective([0.0; 9], 2, 2, Filter::Bilinear, consts::RED);
+        assert_eq!(out.get_pixel(1, 1), consts::RED);
+    }
+
+    #[test]
+    fn rotate_turns_clockwise() {
+        let mut img = Image::new(5, 5);
+        img.set_pixel(0, 0, consts::WHITE);
+        img.set_pixel(1, 0, consts::RED);
+
+        let out = img.rotate(90.0, Filter::Nearest, consts::BLUE);
+        assert_eq!(out.get_pixel(4, 0), consts::WHITE);
+        assert_eq!(out.get_pixel(4, 1), consts::RED);
+        assert_eq!(img.rotate(0.0, Filter::Bilinear, consts::BLUE), img);
+
+        // The corners of a square are rotated out at 45 degrees
+        let out = img.rotate(45.0, Filter::Nearest, consts::BLUE);
+        assert_eq!(out.get_pixel(0, 0), consts::BLUE);
+    }
+
+    #[test]
+    fn anamorphic_pixels_are_made_square() {
+        let mut img = checkerboard();
+        assert_eq!(img.resample_to_square_pixels(Filter::Nearest).unwrap(), img);
+
+        // Twice as many pixels per inch across, so they are half as wide as tall
+        img.set_dpi(144.0, 72.0);
+        assert!((img.pixel_aspect_ratio() - 0.5).abs() < 0.001);
+        let out = img.resample_to_square_pixels(Filter::Nearest).unwrap();
+        assert_eq!((out.width, out.height), (16, 32));
+        assert_eq!(out.get_pixel(0, 7), img.get_pixel(0, 3));
+        assert_eq!(out.get_pixel(0, 8), img.get_pixel(0, 4));
+        assert_eq!(out.pixel_aspect_ratio(), 1.0);
+    }
+
+    #[test]
+    fn extreme_resolutions_exceed_the_limits() {
+        let mut img = crate::open("test/bmpsuite-2.5/g/rgb24.bmp").unwrap();
+        for vres in [100_000, i32::MAX] {
+            img.dib_header.hres = 1;
+            img.dib_header.vres = vres;
+            match img.resample_to_square_pixels(Filter::Nearest) {
+                Err(BmpError {
+                    kind: BmpErrorKind::LimitsExceeded,
+                    ..
+                }) => (/* Expected */),
+                _ => panic!("{} times wider pixels should exceed the limits", vres),
+            }
+        }
+    }
+}
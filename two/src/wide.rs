@@ -0,0 +1,212 @@
+//! The 16 bits per channel of 64 bpp files, as written by GDI+.
+//!
+//! `open` and the other decoding functions convert these files to 8 bits per
+//! channel. The functions here keep the channels as they are stored: linear,
+//! signed fixed point numbers with 13 fractional bits, so that `WIDE_ONE`
+//! stands for 1.0 and values beyond it are brighter than white.
+//!
+//! ```no_run
+//! use two::wide::{self, WIDE_ONE};
+//!
+//! let img = wide::open("hdr.bmp").unwrap();
+//! let [r, g, b, _] = img.get_pixel(0, 0);
+//! println!("{} {} {}", r as f32 / WIDE_ONE as f32, g, b);
+//! ```
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+pub use crate::decoder::WIDE_ONE;
+use crate::decoder::{self, BmpError, BmpErrorKind, BmpResult};
+use crate::policy::ColorPolicy;
+use crate::{flip_rows, Image, ImageIndex, Limits, Pixel};
+
+/// A 64 bpp image, stored as red, green, blue and alpha per pixel, row by row
+/// from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WideImage {
+    width: u32,
+    height: u32,
+    data: Vec<[u16; 4]>,
+}
+
+impl WideImage {
+    /// Returns the `width` of the image.
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the `height` of the image.
+    #[inline]
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the red, green, blue and alpha of the pixel at `(x, y)`.
+    #[inline]
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u16; 4] {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    #[inline]
+    pub fn coordinates(&self) -> ImageIndex {
+        ImageIndex::new(self.width, self.height)
+    }
+
+    /// Returns the pixels, row by row from the top.
+    #[inline]
+    pub fn as_slice(&self) -> &[[u16; 4]] {
+        &self.data
+    }
+
+    /// Converts the image to 8-bit sRGB with alpha, as `open` decodes it.
+    pub fn to_image(&self) -> Image {
+        self.to_image_with(ColorPolicy::default())
+    }
+
+    /// Like `to_image`, converting the sRGB channels to 8 bits with `policy`.
+    pub fn to_image_with(&self, policy: ColorPolicy) -> Image {
+        let mut img = Image::new(self.width, self.height);
+        let channel = |val: u16| decoder::wide_color_with(val, policy);
+        for (px, &[r, g, b, _]) in img.data.iter_mut().zip(self.data.iter()) {
+            *px = px!(channel(r), channel(g), channel(b));
+        }
+        img.alpha = Some(
+            self.data
+                .iter()
+                .map(|c| decoder::wide_alpha(c[3]))
+                .collect(),
+        );
+        img
+    }
+}
+
+/// Opens a 64 bpp file, keeping its 16 bits per channel.
+pub fn open<P: AsRef<Path>>(path: P) -> BmpResult<WideImage> {
+    from_bytes(&fs::read(path)?)
+}
+
+/// Decodes a 64 bpp file already in memory, keeping its 16 bits per channel.
+///
+/// Files of any other bit depth fail with `UnsupportedBitsPerPixel`.
+pub fn from_bytes(bytes: &[u8]) -> BmpResult<WideImage> {
+    let mut bmp_data = Cursor::new(bytes);
+    let (header, dib_header, _) = decoder::decode_headers(&mut bmp_data, Limits::default())?;
+    if dib_header.bits_per_pixel != 64 {
+        return Err(BmpError::new(
+            BmpErrorKind::UnsupportedBitsPerPixel,
+            format!(
+                "{} bpp files have no wide channels",
+                dib_header.bits_per_pixel
+            ),
+        ));
+    }
+
+    let width = dib_header.width.unsigned_abs();
+    let height = dib_header.height.unsigned_abs();
+    let stride = decoder::row_size(width, 64);
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height as usize {
+        let start = header.pixel_offset as usize + stride * y;
+        let row = bytes
+            .get(start..start + width as usize * 8)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("The row at {} lies outside of the file", start),
+                )
+            })?;
+        data.extend(row.chunks_exact(8).map(|px| {
+            let channel = |i: usize| u16::from_le_bytes([px[i], px[i + 1]]);
+            [channel(4), channel(2), channel(0), channel(6)]
+        }));
+    }
+    if dib_header.is_bottom_up() {
+        flip_rows(&mut data, width as usize);
+    }
+
+    Ok(WideImage {
+        width,
+        height,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts, wire, BmpDibHeader, BmpHeader};
+
+    /// Returns a bottom-up 64 bpp file of one row holding `pixels`, given as
+    /// red, green, blue and alpha.
+    fn wide_file(pixels: &[[u16; 4]]) -> Vec<u8> {
+        let width = pixels.len() as u32;
+        let data_size = width * 8;
+        let mut bmp_data = Vec::new();
+        let mut dib_header = BmpDibHeader::new(width as i32, 1);
+        dib_header.bits_per_pixel = 64;
+        dib_header.data_size = data_size;
+        wire::write_file_header(&mut bmp_data, &BmpHeader::new(54, data_size)).unwrap();
+        wire::write_dib_header(&mut bmp_data, &dib_header).unwrap();
+        for &[r, g, b, a] in pixels {
+            for c in [b, g, r, a] {
+                bmp_data.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        bmp_data
+    }
+
+    #[test]
+    fn wide_channels_are_converted_to_srgb() {
+        let half = WIDE_ONE / 2;
+        let pixels = [
+            [WIDE_ONE, 0, 0, WIDE_ONE],
+            [half, half, half, half],
+            [2 * WIDE_ONE, 0xFFFF, WIDE_ONE, 0],
+        ];
+        let bmp_data = wide_file(&pixels);
+
+        let img = crate::from_bytes(&bmp_data).unwrap();
+        assert_eq!(img.get_pixel(0, 0), consts::RED);
+        // Linear 0.5 is brighter than the middle of sRGB
+        assert_eq!(img.get_pixel(1, 0), px!(188, 188, 188));
+        // Out of range values are clamped, negative ones to black
+        assert_eq!(img.get_pixel(2, 0), px!(255, 0, 255));
+        assert_eq!(img.alpha(), Some(&[255, 128, 0][..]));
+
+        let wide = from_bytes(&bmp_data).unwrap();
+        assert_eq!(wide.as_slice(), &pixels[..]);
+        let converted = wide.to_image();
+        assert_eq!(
+            (converted.data, converted.alpha),
+            (img.data.clone(), img.alpha.clone())
+        );
+        let truncate = ColorPolicy {
+            rounding: crate::policy::Rounding::Down,
+            ..ColorPolicy::default()
+        };
+        assert_eq!(
+            wide.to_image_with(truncate).get_pixel(1, 0),
+            px!(187, 187, 187)
+        );
+
+        let streamed = crate::stream::StreamDecoder::new(Cursor::new(&bmp_data))
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!((streamed.data, streamed.alpha), (img.data, img.alpha));
+    }
+
+    #[test]
+    fn only_64_bpp_files_are_wide() {
+        match from_bytes(include_bytes!("../test/rgbw.bmp")) {
+            Err(BmpError {
+                kind: BmpErrorKind::UnsupportedBitsPerPixel,
+                ..
+            }) => (/* Expected */),
+            other => panic!("{:?} should not be wide", other),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Byte layouts of the headers at the start of every BMP file.
+//!
+//! All fields are little-endian whatever the byte order of the host. Reading
+//! and writing them field by field through `byteorder`, instead of casting
+//! memory, keeps the crate correct on big-endian targets.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+use crate::{BmpDibHeader, BmpHeader};
+
+/// The magic numbers every file starts with.
+pub const MAGIC: [u8; 2] = *b"BM";
+/// Size of the file header, including the magic numbers.
+pub const FILE_HEADER_SIZE: u64 = 14;
+/// Size of a version 3 DIB header, the fields shared by all later versions.
+pub const DIB_HEADER_SIZE: u32 = 40;
+/// Size of a version 4 DIB header, adding masks and a color space.
+pub const V4_HEADER_SIZE: u32 = 108;
+/// Size of a version 5 DIB header, adding a rendering intent and a profile.
+pub const V5_HEADER_SIZE: u32 = 124;
+
+/// Reads the magic numbers and the file header.
+pub fn read_file_header<R: Read>(r: &mut R) -> io::Result<([u8; 2], BmpHeader)> {
+    let mut magic = [0; 2];
+    r.read_exact(&mut magic)?;
+    let header = BmpHeader {
+        file_size: r.read_u32::<LittleEndian>()?,
+        creator1: r.read_u16::<LittleEndian>()?,
+        creator2: r.read_u16::<LittleEndian>()?,
+        pixel_offset: r.read_u32::<LittleEndian>()?,
+    };
+    Ok((magic, header))
+}
+
+/// Writes the magic numbers and the file header.
+pub fn write_file_header<W: Write>(w: &mut W, header: &BmpHeader) -> io::Result<()> {
+    w.write_all(&MAGIC)?;
+    w.write_u32::<LittleEndian>(header.file_size)?;
+    w.write_u16::<LittleEndian>(header.creator1)?;
+    w.write_u16::<LittleEndian>(header.creator2)?;
+    w.write_u32::<LittleEndian>(header.pixel_offset)
+}
+
+/// Reads the first 40 bytes of a DIB header. Later versions follow them with
+/// more fields, which are read where they are needed.
+pub fn read_dib_header<R: Read>(r: &mut R) -> io::Result<BmpDibHeader> {
+    Ok(BmpDibHeader {
+        header_size: r.read_u32::<LittleEndian>()?,
+        width: r.read_i32::<LittleEndian>()?,
+        height: r.read_i32::<LittleEndian>()?,
+        num_planes: r.read_u16::<LittleEndian>()?,
+        bits_per_pixel: r.read_u16::<LittleEndian>()?,
+        compress_type: r.read_u32::<LittleEndian>()?,
+        data_size: r.read_u32::<LittleEndian>()?,
+        hres: r.read_i32::<LittleEndian>()?,
+        vres: r.read_i32::<LittleEndian>()?,
+        num_colors: r.read_u32::<LittleEndian>()?,
+        num_imp_colors: r.read_u32::<LittleEndian>()?,
+    })
+}
+
+/// Writes the first 40 bytes of a DIB header.
+pub fn write_dib_header<W: Write>(w: &mut W, dh: &BmpDibHeader) -> io::Result<()> {
+    w.write_u32::<LittleEndian>(dh.header_size)?;
+    w.write_i32::<LittleEndian>(dh.width)?;
+    w.write_i32::<LittleEndian>(dh.height)?;
+    w.write_u16::<LittleEndian>(dh.num_planes)?;
+    w.write_u16::<LittleEndian>(dh.bits_per_pixel)?;
+    w.write_u32::<LittleEndian>(dh.compress_type)?;
+    w.write_u32::<LittleEndian>(dh.data_size)?;
+    w.write_i32::<LittleEndian>(dh.hres)?;
+    w.write_i32::<LittleEndian>(dh.vres)?;
+    w.write_u32::<LittleEndian>(dh.num_colors)?;
+    w.write_u32::<LittleEndian>(dh.num_imp_colors)
+}
+
+/// The fields version 4 headers add to the first 40 bytes, of which the last
+/// three are only in version 5 headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColorFields {
+    pub masks: [u32; 4],
+    pub cs_type: u32,
+    pub intent: u32,
+    pub profile_offset: u32,
+    pub profile_size: u32,
+}
+
+/// Writes the fields that follow the first 40 bytes of a DIB header of
+/// `header_size` bytes. The endpoints and gammas of the color space are zero.
+pub fn write_color_fields<W: Write>(
+    w: &mut W,
+    fields: &ColorFields,
+    header_size: u32,
+) -> io::Result<()> {
+    for &mask in &fields.masks {
+        w.write_u32::<LittleEndian>(mask)?;
+    }
+    w.write_u32::<LittleEndian>(fields.cs_type)?;
+    w.write_all(&[0; 48])?;
+    if header_size >= V5_HEADER_SIZE {
+        w.write_u32::<LittleEndian>(fields.intent)?;
+        w.write_u32::<LittleEndian>(fields.profile_offset)?;
+        w.write_u32::<LittleEndian>(fields.profile_size)?;
+        // Reserved
+        w.write_u32::<LittleEndian>(0)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Every multi-byte field has distinct bytes, so a swapped order shows
+    #[rustfmt::skip]
+    const HEADERS: [u8; 54] = [
+        b'B', b'M',
+        0x01, 0x02, 0x03, 0x04, // file_size
+        0x05, 0x06,             // creator1
+        0x07, 0x08,             // creator2
+        0x36, 0x00, 0x00, 0x00, // pixel_offset
+        0x28, 0x00, 0x00, 0x00, // header_size
+        0x02, 0x01, 0x00, 0x00, // width
+        0xFE, 0xFF, 0xFF, 0xFF, // height
+        0x01, 0x00,             // num_planes
+        0x18, 0x00,             // bits_per_pixel
+        0x00, 0x00, 0x00, 0x00, // compress_type
+        0x10, 0x20, 0x30, 0x00, // data_size
+        0x13, 0x0B, 0x00, 0x00, // hres
+        0x12, 0x0B, 0x00, 0x00, // vres
+        0x00, 0x01, 0x00, 0x00, // num_colors
+        0x0F, 0x00, 0x00, 0x00, // num_imp_colors
+    ];
+
+    #[test]
+    fn headers_are_read_little_endian() {
+        let mut r = Cursor::new(&HEADERS[..]);
+        let (magic, header) = read_file_header(&mut r).unwrap();
+        assert_eq!(magic, MAGIC);
+        assert_eq!(header.file_size, 0x0403_0201);
+        assert_eq!((header.creator1, header.creator2), (0x0605, 0x0807));
+        assert_eq!(header.pixel_offset, 54);
+
+        let dh = read_dib_header(&mut r).unwrap();
+        assert_eq!(dh.header_size, DIB_HEADER_SIZE);
+        assert_eq!((dh.width, dh.height), (258, -2));
+        assert_eq!((dh.num_planes, dh.bits_per_pixel), (1, 24));
+        assert_eq!((dh.compress_type, dh.data_size), (0, 0x0030_2010));
+        assert_eq!((dh.hres, dh.vres), (2835, 2834));
+        assert_eq!((dh.num_colors, dh.num_imp_colors), (256, 15));
+        assert_eq!(r.position(), FILE_HEADER_SIZE + DIB_HEADER_SIZE as u64);
+    }
+
+    #[test]
+    fn headers_are_written_byte_for_byte() {
+        let mut r = Cursor::new(&HEADERS[..]);
+        let (_, header) = read_file_header(&mut r).unwrap();
+        let dh = read_dib_header(&mut r).unwrap();
+
+        let mut bytes = Vec::new();
+        write_file_header(&mut bytes, &header).unwrap();
+        write_dib_header(&mut bytes, &dh).unwrap();
+        assert_eq!(bytes, HEADERS);
+    }
+
+    #[test]
+    fn color_fields_fill_the_newer_headers() {
+        let fields = ColorFields {
+            masks: [1, 2, 3, 4],
+            cs_type: 5,
+            intent: 6,
+            profile_offset: 7,
+            profile_size: 8,
+        };
+        for size in [V4_HEADER_SIZE, V5_HEADER_SIZE] {
+            let mut bytes = Vec::new();
+            write_color_fields(&mut bytes, &fields, size).unwrap();
+            assert_eq!(bytes.len() as u32, size - DIB_HEADER_SIZE);
+            assert_eq!(&bytes[12..20], &[4, 0, 0, 0, 5, 0, 0, 0]);
+        }
+    }
+}